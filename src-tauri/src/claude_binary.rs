@@ -6,7 +6,8 @@ use std::cmp::Ordering;
 /// Supports NVM installations, aliased paths, version-based selection, and bundled sidecars
 use std::path::PathBuf;
 use std::process::Command;
-use tauri::Manager;
+use std::sync::{Mutex, OnceLock};
+use tauri::{Emitter, Manager};
 
 /// Type of Claude installation
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -26,15 +27,70 @@ pub struct ClaudeInstallation {
     pub path: String,
     /// Version string if available
     pub version: Option<String>,
+    /// Build metadata (e.g. commit or build date) when the binary reports it
+    #[serde(default)]
+    pub build: Option<String>,
     /// Source of discovery (e.g., "nvm", "system", "homebrew", "where", "bundled")
     pub source: String,
     /// Type of installation
     pub installation_type: InstallationType,
 }
 
+/// Binary path resolved by `find_claude_binary`, memoized for the app lifetime.
+/// Discovery runs outside this lock, so a panic during discovery can't poison the cache.
+static CLAUDE_BINARY_CACHE: Mutex<ClaudeBinaryCache> = Mutex::new(ClaudeBinaryCache { generation: 0, path: None });
+
+/// Held for the whole of a discovery so concurrent cold-start callers wait for the
+/// first one's result instead of each spawning their own version probes
+static CLAUDE_BINARY_DISCOVERY: Mutex<()> = Mutex::new(());
+
+struct ClaudeBinaryCache {
+    /// Bumped by every clear, so a discovery that started before it doesn't store a stale path
+    generation: u64,
+    path: Option<String>,
+}
+
+fn lock_claude_binary_cache() -> std::sync::MutexGuard<'static, ClaudeBinaryCache> {
+    CLAUDE_BINARY_CACHE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// The memoized path, or the generation a new discovery should store under
+fn cached_claude_binary() -> Result<String, u64> {
+    let cache = lock_claude_binary_cache();
+    cache.path.clone().ok_or(cache.generation)
+}
+
 /// Main function to find the Claude binary - Windows optimized version
 /// Only uses system-installed Claude CLI, no bundled binaries
 pub fn find_claude_binary(app_handle: &tauri::AppHandle) -> Result<String, String> {
+    if let Ok(path) = cached_claude_binary() {
+        return Ok(path);
+    }
+
+    let _discovery = CLAUDE_BINARY_DISCOVERY.lock().unwrap_or_else(|e| e.into_inner());
+    // Another caller may have finished discovery while this one waited
+    let generation = match cached_claude_binary() {
+        Ok(path) => return Ok(path),
+        Err(generation) => generation,
+    };
+
+    // Only successful lookups are memoized, so a later call can pick up a fresh install
+    let path = discover_claude_binary(app_handle)?;
+    let mut cache = lock_claude_binary_cache();
+    if cache.generation == generation {
+        cache.path = Some(path.clone());
+    }
+    Ok(path)
+}
+
+/// Forget the memoized binary path so the next `find_claude_binary` call rediscovers it
+pub fn clear_claude_binary_cache() {
+    let mut cache = lock_claude_binary_cache();
+    cache.generation += 1;
+    cache.path = None;
+}
+
+fn discover_claude_binary(app_handle: &tauri::AppHandle) -> Result<String, String> {
     info!("Searching for system Claude CLI...");
 
     // First check if we have a stored path in the database
@@ -108,43 +164,100 @@ pub fn find_claude_binary(app_handle: &tauri::AppHandle) -> Result<String, Strin
     }
 }
 
+/// Open agents.db in the app data directory, creating the settings table if needed
+fn open_settings_db(app_handle: &tauri::AppHandle) -> Result<rusqlite::Connection, String> {
+    let app_data_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|_| "Failed to get app data directory".to_string())?;
+    if let Err(e) = std::fs::create_dir_all(&app_data_dir) {
+        return Err(format!("Failed to create app data directory: {}", e));
+    }
+
+    let conn = rusqlite::Connection::open(app_data_dir.join("agents.db"))
+        .map_err(|e| format!("Failed to open database: {}", e))?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS app_settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        )",
+        [],
+    )
+    .map_err(|e| format!("Failed to create settings table: {}", e))?;
+
+    Ok(conn)
+}
+
 /// Store Claude CLI path in database for future use
 fn store_claude_path(app_handle: &tauri::AppHandle, path: &str) -> Result<(), String> {
-    if let Ok(app_data_dir) = app_handle.path().app_data_dir() {
-        if let Err(e) = std::fs::create_dir_all(&app_data_dir) {
-            return Err(format!("Failed to create app data directory: {}", e));
-        }
-        
-        let db_path = app_data_dir.join("agents.db");
-        match rusqlite::Connection::open(&db_path) {
-            Ok(conn) => {
-                // Create table if it doesn't exist
-                if let Err(e) = conn.execute(
-                    "CREATE TABLE IF NOT EXISTS app_settings (
-                        key TEXT PRIMARY KEY,
-                        value TEXT NOT NULL
-                    )",
-                    [],
-                ) {
-                    return Err(format!("Failed to create settings table: {}", e));
-                }
-                
-                // Store the path
-                if let Err(e) = conn.execute(
-                    "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
-                    rusqlite::params!["claude_binary_path", path],
-                ) {
-                    return Err(format!("Failed to store claude path: {}", e));
-                }
-                
-                info!("Stored claude path in database: {}", path);
-                Ok(())
-            }
-            Err(e) => Err(format!("Failed to open database: {}", e)),
+    let conn = open_settings_db(app_handle)?;
+    conn.execute(
+        "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+        rusqlite::params!["claude_binary_path", path],
+    )
+    .map_err(|e| format!("Failed to store claude path: {}", e))?;
+
+    info!("Stored claude path in database: {}", path);
+    Ok(())
+}
+
+/// Event emitted when the configured binary reports a different version than last time
+pub const CLAUDE_VERSION_CHANGED_EVENT: &str = "claude-version-changed";
+
+/// Version of the configured binary compared with the last version recorded for it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaudeVersionCheck {
+    pub path: String,
+    pub version: Option<String>,
+    pub previous_version: Option<String>,
+    pub changed: bool,
+}
+
+/// Re-run `--version` on the configured binary and compare it with the version stored in
+/// `app_settings`. Catches in-place upgrades, where the cached path stays valid but the
+/// binary behind it changed; emits `claude-version-changed` when the versions differ.
+pub fn check_claude_binary_current(app_handle: &tauri::AppHandle) -> Result<ClaudeVersionCheck, String> {
+    let path = find_claude_binary(app_handle)?;
+    let VersionInfo { version, .. } = get_claude_version(&path);
+
+    let conn = open_settings_db(app_handle)?;
+    let previous_version = match conn.query_row(
+        "SELECT value FROM app_settings WHERE key = 'claude_binary_version'",
+        [],
+        |row| row.get::<_, String>(0),
+    ) {
+        Ok(value) => Some(value),
+        Err(rusqlite::Error::QueryReturnedNoRows) => None,
+        Err(e) => return Err(format!("Failed to read stored Claude version: {}", e)),
+    };
+
+    // The first check only records a baseline, and a failed probe is not a change
+    let changed = matches!((&previous_version, &version), (Some(previous), Some(current)) if previous != current);
+    if let Some(current) = &version {
+        conn.execute(
+            "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+            rusqlite::params!["claude_binary_version", current],
+        )
+        .map_err(|e| format!("Failed to store Claude version: {}", e))?;
+    }
+
+    let check = ClaudeVersionCheck {
+        path,
+        version,
+        previous_version,
+        changed,
+    };
+    if changed {
+        info!(
+            "Claude version changed: {:?} -> {:?}",
+            check.previous_version, check.version
+        );
+        if let Err(e) = app_handle.emit(CLAUDE_VERSION_CHANGED_EVENT, check.clone()) {
+            warn!("Failed to emit {}: {}", CLAUDE_VERSION_CHANGED_EVENT, e);
         }
-    } else {
-        Err("Failed to get app data directory".to_string())
     }
+
+    Ok(check)
 }
 
 /// Test if a Claude binary is actually functional (Windows-only)
@@ -221,8 +334,9 @@ fn source_preference(installation: &ClaudeInstallation) -> u8 {
         "bun" => 9,
         "node-modules" => 10,
         "home-bin" => 11,
-        "PATH" => 12,
-        _ => 13,
+        "winget" => 12,
+        "PATH" => 13,
+        _ => 14,
     }
 }
 
@@ -300,11 +414,12 @@ fn try_where_command() -> Option<ClaudeInstallation> {
             }
 
             // Get version
-            let version = get_claude_version(&path).ok().flatten();
+            let VersionInfo { version, build } = get_claude_version(&path);
 
             Some(ClaudeInstallation {
                 path,
                 version,
+                build,
                 source: "where".to_string(),
                 installation_type: InstallationType::System,
             })
@@ -358,11 +473,12 @@ fn try_which_command() -> Option<ClaudeInstallation> {
             }
 
             // Get version
-            let version = get_claude_version(&path).ok().flatten();
+            let VersionInfo { version, build } = get_claude_version(&path);
 
             Some(ClaudeInstallation {
                 path,
                 version,
+                build,
                 source: "where".to_string(),
                 installation_type: InstallationType::System,
             })
@@ -401,11 +517,12 @@ fn find_nvm_installations() -> Vec<ClaudeInstallation> {
                             debug!("Found Claude in NVM node {}: {}", node_version, path_str);
 
                             // Get Claude version
-                            let version = get_claude_version(&path_str).ok().flatten();
+                            let VersionInfo { version, build } = get_claude_version(&path_str);
 
                             installations.push(ClaudeInstallation {
                                 path: path_str,
                                 version,
+                                build,
                                 source: format!("nvm ({})", node_version),
                                 installation_type: InstallationType::System,
                             });
@@ -473,11 +590,12 @@ fn find_standard_installations() -> Vec<ClaudeInstallation> {
             debug!("Found claude at standard path: {} ({})", path, source);
 
             // Get version
-            let version = get_claude_version(&path).ok().flatten();
+            let VersionInfo { version, build } = get_claude_version(&path);
 
             installations.push(ClaudeInstallation {
                 path,
                 version,
+                build,
                 source,
                 installation_type: InstallationType::System,
             });
@@ -488,29 +606,17 @@ fn find_standard_installations() -> Vec<ClaudeInstallation> {
     let claude_commands = vec!["claude", "claude.cmd"];
 
     for cmd in claude_commands {
-        let mut command = Command::new(cmd);
-        command.arg("--version");
-        
-        // Add CREATE_NO_WINDOW flag on Windows to prevent terminal window popup
-        #[cfg(target_os = "windows")]
-        {
-            use std::os::windows::process::CommandExt;
-            command.creation_flags(0x08000000); // CREATE_NO_WINDOW
-        }
-        
-        if let Ok(output) = command.output() {
-            if output.status.success() {
-                debug!("{} is available in PATH", cmd);
-                let version = extract_version_from_output(&output.stdout);
-
-                installations.push(ClaudeInstallation {
-                    path: cmd.to_string(),
-                    version,
-                    source: "PATH".to_string(),
-                    installation_type: InstallationType::System,
-                });
-                break; // Only add one PATH entry
-            }
+        if let Some(VersionInfo { version, build }) = query_claude_version(cmd) {
+            debug!("{} is available in PATH", cmd);
+
+            installations.push(ClaudeInstallation {
+                path: cmd.to_string(),
+                version,
+                build,
+                source: "PATH".to_string(),
+                installation_type: InstallationType::System,
+            });
+            break; // Only add one PATH entry
         }
     }
 
@@ -547,6 +653,36 @@ fn find_windows_installations() -> Vec<ClaudeInstallation> {
         ]);
     }
 
+    // Check winget locations: the Links shim directory and the per-package install roots,
+    // both per-user (LOCALAPPDATA) and machine-wide (ProgramFiles)
+    let mut winget_roots: Vec<PathBuf> = vec![];
+    if let Ok(local_appdata) = std::env::var("LOCALAPPDATA") {
+        winget_roots.push(PathBuf::from(local_appdata).join("Microsoft").join("WinGet"));
+    }
+    if let Ok(program_files) = std::env::var("ProgramFiles") {
+        winget_roots.push(PathBuf::from(program_files).join("WinGet"));
+    }
+
+    for winget_root in winget_roots {
+        paths_to_check.push((
+            winget_root.join("Links").join("claude.exe").to_string_lossy().to_string(),
+            "winget".to_string(),
+        ));
+
+        // Package directories are named "<PackageIdentifier>_<SourceHash>"
+        if let Ok(packages) = std::fs::read_dir(winget_root.join("Packages")) {
+            for package in packages.flatten() {
+                let package_name = package.file_name().to_string_lossy().to_lowercase();
+                if package_name.contains("claude") {
+                    paths_to_check.push((
+                        package.path().join("claude.exe").to_string_lossy().to_string(),
+                        "winget".to_string(),
+                    ));
+                }
+            }
+        }
+    }
+
     // Check each path
     for (path, source) in paths_to_check {
         let path_buf = PathBuf::from(&path);
@@ -554,11 +690,12 @@ fn find_windows_installations() -> Vec<ClaudeInstallation> {
             debug!("Found claude at Windows path: {} ({})", path, source);
 
             // Get version
-            let version = get_claude_version(&path).ok().flatten();
+            let VersionInfo { version, build } = get_claude_version(&path);
 
             installations.push(ClaudeInstallation {
                 path,
                 version,
+                build,
                 source,
                 installation_type: InstallationType::System,
             });
@@ -567,39 +704,105 @@ fn find_windows_installations() -> Vec<ClaudeInstallation> {
 
     installations
 }
+/// Version and build metadata reported by a Claude binary
+#[derive(Debug, Default)]
+struct VersionInfo {
+    version: Option<String>,
+    build: Option<String>,
+}
+
 /// Get Claude version by running --version command (Windows-only)
-fn get_claude_version(path: &str) -> Result<Option<String>, String> {
+fn get_claude_version(path: &str) -> VersionInfo {
     debug!("Getting version for Claude at: {}", path);
-    
+    let info = query_claude_version(path).unwrap_or_default();
+    debug!("Successfully got version: {:?}", info);
+    info
+}
+
+/// Ask the binary for its version, preferring `--version --output-format json` and
+/// falling back to the plain text output on CLIs that don't support the flag.
+/// Returns `None` when the binary can't be run at all.
+fn query_claude_version(path: &str) -> Option<VersionInfo> {
+    if let Some(stdout) = run_version_command(path, &["--version", "--output-format", "json"]) {
+        if let Some(info) = parse_json_version(&stdout) {
+            return Some(info);
+        }
+        // Some versions ignore the flag and still print plain text
+        let version = extract_version_from_output(&stdout);
+        if version.is_some() {
+            return Some(VersionInfo { version, build: None });
+        }
+    }
+
+    let stdout = run_version_command(path, &["--version"])?;
+    Some(VersionInfo {
+        version: extract_version_from_output(&stdout),
+        build: None,
+    })
+}
+
+/// Run the binary with `args`, returning stdout only if it exited successfully
+fn run_version_command(path: &str, args: &[&str]) -> Option<Vec<u8>> {
     let mut cmd = Command::new(path);
-    cmd.arg("--version");
-    
+    cmd.args(args);
+
     // Add CREATE_NO_WINDOW flag on Windows to prevent terminal window popup
     #[cfg(target_os = "windows")]
     {
         use std::os::windows::process::CommandExt;
         cmd.creation_flags(0x08000000); // CREATE_NO_WINDOW
     }
-    
+
     match cmd.output() {
+        Ok(output) if output.status.success() => Some(output.stdout),
         Ok(output) => {
-            if output.status.success() {
-                let version = extract_version_from_output(&output.stdout);
-                debug!("Successfully got version: {:?}", version);
-                Ok(version)
-            } else {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                debug!("Claude version command failed with stderr: {}", stderr);
-                Ok(None)
-            }
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            debug!("Claude {:?} failed with stderr: {}", args, stderr);
+            None
         }
         Err(e) => {
             debug!("Failed to execute version command for {}: {}", path, e);
-            Ok(None)
+            None
         }
     }
 }
 
+/// Parse structured version output, e.g. `{"version": "1.0.41", "build": "abc123"}`
+fn parse_json_version(stdout: &[u8]) -> Option<VersionInfo> {
+    let value: serde_json::Value = serde_json::from_slice(stdout).ok()?;
+    let version = value
+        .get("version")
+        .and_then(|v| v.as_str())
+        .and_then(|v| extract_version_from_output(v.as_bytes()))?;
+    let build = ["build", "commit", "buildTime", "build_time"]
+        .iter()
+        .find_map(|key| value.get(*key).and_then(|v| v.as_str()))
+        .filter(|b| !b.trim().is_empty())
+        .map(|b| b.trim().to_string());
+
+    Some(VersionInfo {
+        version: Some(version),
+        build,
+    })
+}
+
+/// Version pattern (e.g., "1.0.41"): three dot-separated numbers, optionally
+/// followed by pre-release/build metadata
+const VERSION_PATTERN: &str = r"(\d+\.\d+\.\d+(?:-[a-zA-Z0-9.-]+)?(?:\+[a-zA-Z0-9.-]+)?)";
+
+static VERSION_REGEX: OnceLock<regex::Regex> = OnceLock::new();
+
+/// Compiled once for all discovery runs. A bad pattern is a bug, so it fails loudly
+/// instead of making every installation look version-less.
+fn version_regex() -> &'static regex::Regex {
+    VERSION_REGEX.get_or_init(|| {
+        regex::Regex::new(VERSION_PATTERN).unwrap_or_else(|e| {
+            error!("Failed to compile version regex: {}", e);
+            panic!("invalid version regex: {}", e)
+        })
+    })
+}
+
 /// Extract version string from command output
 fn extract_version_from_output(stdout: &[u8]) -> Option<String> {
     let output_str = String::from_utf8_lossy(stdout);
@@ -607,17 +810,7 @@ fn extract_version_from_output(stdout: &[u8]) -> Option<String> {
     // Debug log the raw output
     debug!("Raw version output: {:?}", output_str);
     
-    // Use regex to directly extract version pattern (e.g., "1.0.41")
-    // This pattern matches:
-    // - One or more digits, followed by
-    // - A dot, followed by
-    // - One or more digits, followed by
-    // - A dot, followed by
-    // - One or more digits
-    // - Optionally followed by pre-release/build metadata
-    let version_regex = regex::Regex::new(r"(\d+\.\d+\.\d+(?:-[a-zA-Z0-9.-]+)?(?:\+[a-zA-Z0-9.-]+)?)").ok()?;
-    
-    if let Some(captures) = version_regex.captures(&output_str) {
+    if let Some(captures) = version_regex().captures(&output_str) {
         if let Some(version_match) = captures.get(1) {
             let version = version_match.as_str().to_string();
             debug!("Extracted version: {:?}", version);
@@ -700,6 +893,25 @@ fn compare_versions(a: &str, b: &str) -> Ordering {
     Ordering::Equal
 }
 
+/// Variables set on spawned Claude processes on top of what they inherit: the active
+/// provider's proxy and the active profile's config dir. The app's own environment and
+/// the user's system settings stay untouched.
+pub fn claude_session_env() -> Vec<(String, String)> {
+    let mut vars: Vec<(String, String)> = crate::commands::provider::active_provider_proxy_env()
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect();
+
+    // An explicit CLAUDE_CONFIG_DIR wins over profiles and is inherited as-is
+    let env_override = std::env::var("CLAUDE_CONFIG_DIR").is_ok_and(|dir| !dir.trim().is_empty());
+    if !env_override {
+        if let Some(profile_dir) = crate::commands::profiles::active_profile_dir() {
+            vars.push(("CLAUDE_CONFIG_DIR".to_string(), profile_dir.to_string_lossy().to_string()));
+        }
+    }
+    vars
+}
+
 /// Helper function to create a Command with proper Windows environment variables
 pub fn create_command_with_env(program: &str) -> Command {
     let mut cmd = Command::new(program);
@@ -739,6 +951,11 @@ pub fn create_command_with_env(program: &str) -> Command {
         }
     }
 
+    for (key, value) in claude_session_env() {
+        debug!("Setting session env var: {}", key);
+        cmd.env(&key, &value);
+    }
+
     cmd
 }
 
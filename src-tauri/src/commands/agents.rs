@@ -1847,6 +1847,73 @@ pub async fn get_claude_binary_path(db: State<'_, AgentDb>) -> Result<Option<Str
     }
 }
 
+/// Location and health of the app database
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DbStatus {
+    pub db_path: String,
+    pub exists: bool,
+    pub has_app_settings: bool,
+    pub claude_binary_path: Option<String>,
+    /// Set when the database exists but could not be opened or queried
+    pub error: Option<String>,
+}
+
+/// Report where agents.db lives and what it holds, for settings diagnostics.
+/// Opens the file read-only so a missing database is not created.
+#[tauri::command]
+pub async fn get_app_db_status(app: AppHandle) -> Result<DbStatus, String> {
+    let db_path = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data dir: {}", e))?
+        .join("agents.db");
+
+    let mut status = DbStatus {
+        db_path: db_path.to_string_lossy().to_string(),
+        exists: db_path.is_file(),
+        has_app_settings: false,
+        claude_binary_path: None,
+        error: None,
+    };
+    if !status.exists {
+        return Ok(status);
+    }
+
+    let conn = match Connection::open_with_flags(&db_path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY) {
+        Ok(conn) => conn,
+        Err(e) => {
+            status.error = Some(format!("Failed to open database: {}", e));
+            return Ok(status);
+        }
+    };
+
+    match conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'app_settings'",
+        [],
+        |row| row.get::<_, i64>(0),
+    ) {
+        Ok(count) => status.has_app_settings = count > 0,
+        Err(e) => {
+            status.error = Some(format!("Failed to query database: {}", e));
+            return Ok(status);
+        }
+    }
+
+    if status.has_app_settings {
+        match conn.query_row(
+            "SELECT value FROM app_settings WHERE key = 'claude_binary_path'",
+            [],
+            |row| row.get::<_, String>(0),
+        ) {
+            Ok(path) => status.claude_binary_path = Some(path),
+            Err(rusqlite::Error::QueryReturnedNoRows) => {}
+            Err(e) => status.error = Some(format!("Failed to get Claude binary path: {}", e)),
+        }
+    }
+
+    Ok(status)
+}
+
 /// Set the Claude binary path in settings
 #[tauri::command]
 pub async fn set_claude_binary_path(db: State<'_, AgentDb>, path: String) -> Result<(), String> {
@@ -1862,6 +1929,7 @@ pub async fn set_claude_binary_path(db: State<'_, AgentDb>, path: String) -> Res
             params![path],
         )
         .map_err(|e| format!("Failed to save Claude binary path: {}", e))?;
+        crate::claude_binary::clear_claude_binary_cache();
         return Ok(());
     }
 
@@ -1893,9 +1961,37 @@ pub async fn set_claude_binary_path(db: State<'_, AgentDb>, path: String) -> Res
     )
     .map_err(|e| format!("Failed to save Claude binary path: {}", e))?;
 
+    crate::claude_binary::clear_claude_binary_cache();
     Ok(())
 }
 
+/// Clear the stored and memoized Claude binary path and rediscover the installation
+#[tauri::command]
+pub async fn reset_claude_binary_cache(app: AppHandle, db: State<'_, AgentDb>) -> Result<String, String> {
+    {
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+        conn.execute(
+            "DELETE FROM app_settings WHERE key = 'claude_binary_path'",
+            [],
+        )
+        .map_err(|e| format!("Failed to clear Claude binary path: {}", e))?;
+    }
+
+    crate::claude_binary::clear_claude_binary_cache();
+
+    // find_claude_binary stores the newly selected path again
+    crate::claude_binary::find_claude_binary(&app)
+}
+
+/// Re-check the configured binary's version, emitting `claude-version-changed` if it differs
+/// from the last recorded version
+#[tauri::command]
+pub async fn check_claude_binary_current(
+    app: AppHandle,
+) -> Result<crate::claude_binary::ClaudeVersionCheck, String> {
+    crate::claude_binary::check_claude_binary_current(&app)
+}
+
 /// List all available Claude installations on the system
 #[tauri::command]
 pub async fn list_claude_installations(
@@ -2091,6 +2187,10 @@ fn create_command_with_env(program: &str) -> Command {
     }
     // END PATCH
 
+    for (key, value) in crate::claude_binary::claude_session_env() {
+        tokio_cmd.env(&key, &value);
+    }
+
     tokio_cmd
 }
 
@@ -11,6 +11,7 @@ use tokio::process::{Child, Command};
 use tokio::sync::Mutex;
 use tauri_plugin_shell::ShellExt;
 use regex;
+use crate::commands::usage::encode_project_dir_name;
 
 // Windows-specific imports
 #[cfg(target_os = "windows")]
@@ -186,12 +187,52 @@ fn get_project_path_from_sessions(project_dir: &PathBuf) -> Result<String, Strin
 /// The directory names in ~/.claude/projects are encoded paths
 /// DEPRECATED: Use get_project_path_from_sessions instead when possible
 fn decode_project_path(encoded: &str) -> String {
+    decode_project_path_for(encoded, cfg!(windows))
+}
+
+fn decode_project_path_for(encoded: &str, windows: bool) -> String {
     // This is a fallback - the encoding isn't reversible when paths contain hyphens
     // For example: -Users-mufeedvh-dev-jsonl-viewer could be /Users/mufeedvh/dev/jsonl-viewer
     // or /Users/mufeedvh/dev/jsonl/viewer
+    if !windows {
+        // A leading "--" here is a top-level hidden dir: --config-app was /.config/app
+        return encoded.replace('-', "/");
+    }
+    let bytes = encoded.as_bytes();
+    // Windows drive paths: C--Users-me was C:\Users\me
+    if bytes.len() >= 3 && bytes[0].is_ascii_alphabetic() && bytes[1] == b'-' && bytes[2] == b'-' {
+        return format!("{}:\\{}", &encoded[..1], encoded[3..].replace('-', "\\"));
+    }
+    // UNC paths: --server-share-repo was \\server\share\repo
+    if let Some(rest) = encoded.strip_prefix("--") {
+        return format!("\\\\{}", rest.replace('-', "\\"));
+    }
     encoded.replace('-', "/")
 }
 
+/// Encodes a project path to its directory name under ~/.claude/projects, the
+/// inverse of `decode_project_path`. Claude Code replaces every non-alphanumeric
+/// character with '-', so `/Users/me/app` becomes `-Users-me-app`.
+#[tauri::command]
+pub async fn encode_project_path(path: String) -> Result<String, String> {
+    encode_project_dir(&path)
+}
+
+fn encode_project_dir(path: &str) -> Result<String, String> {
+    let path = path.trim();
+    let is_absolute = path.starts_with(['/', '\\'])
+        || (path.len() >= 2 && path.as_bytes()[0].is_ascii_alphabetic() && path.as_bytes()[1] == b':');
+    if !is_absolute {
+        return Err(format!("Project path must be absolute: {}", path));
+    }
+
+    // Claude Code records the cwd without a trailing separator
+    let trimmed = path.trim_end_matches(['/', '\\']);
+    let path = if trimmed.is_empty() || trimmed.ends_with(':') { path } else { trimmed };
+
+    Ok(encode_project_dir_name(path))
+}
+
 /// Extracts the first valid user message from a JSONL file
 fn extract_first_user_message(jsonl_path: &PathBuf) -> (Option<String>, Option<String>) {
     let file = match fs::File::open(jsonl_path) {
@@ -315,6 +356,11 @@ fn create_command_with_env(program: &str) -> Command {
         }
     }
 
+    for (key, value) in crate::claude_binary::claude_session_env() {
+        log::debug!("Setting session env var: {}", key);
+        tokio_cmd.env(&key, &value);
+    }
+
     tokio_cmd
 }
 
@@ -2796,3 +2842,42 @@ async fn find_claude_executable() -> Result<String, String> {
     Err("无法找到Claude Code可执行文件。请确保Claude Code已正确安装。您可以运行 'npm install -g @anthropic-ai/claude-code' 来安装。".to_string())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(path: &str) -> String {
+        encode_project_dir(path).unwrap()
+    }
+
+    #[test]
+    fn test_project_path_round_trip() {
+        for (path, encoded) in [
+            ("/Users/me/dev/app", "-Users-me-dev-app"),
+            ("/Users/me/dev/app/", "-Users-me-dev-app"),
+            ("C:\\Users\\me\\app", "C--Users-me-app"),
+            ("\\\\server\\share\\repo", "--server-share-repo"),
+        ] {
+            assert_eq!(encode(path), encoded);
+            assert_eq!(encode(&decode_project_path_for(encoded, true)), encoded);
+        }
+
+        assert_eq!(decode_project_path_for("-Users-me-dev-app", true), "/Users/me/dev/app");
+        assert_eq!(decode_project_path_for("C--Users-me-app", true), "C:\\Users\\me\\app");
+        assert_eq!(decode_project_path_for("--server-share-repo", true), "\\\\server\\share\\repo");
+
+        // Off Windows the same names are POSIX paths under a top-level hidden dir
+        assert_eq!(decode_project_path_for("-Users-me-dev-app", false), "/Users/me/dev/app");
+        assert_eq!(decode_project_path_for("--config-app", false), "//config/app");
+        assert_eq!(encode(&decode_project_path_for("--config-app", false)), "--config-app");
+    }
+
+    #[test]
+    fn test_encode_project_path_is_lossy_for_punctuation() {
+        // Hyphens and dots encode to '-' too, so only the encoded form is stable
+        let encoded = encode("/Users/me/my-app.v2");
+        assert_eq!(encoded, "-Users-me-my-app-v2");
+        assert_eq!(encode(&decode_project_path(&encoded)), encoded);
+        assert!(encode_project_dir("relative/path").is_err());
+    }
+}
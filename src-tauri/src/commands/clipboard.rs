@@ -22,35 +22,29 @@ pub async fn save_clipboard_image(
     
     // 解析Data URL格式 (data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAA...)
     let data_url_prefix = "data:image/";
-    let (base64_content, extension) = if base64_data.starts_with(data_url_prefix) {
+    let (base64_content, mime_extension) = if base64_data.starts_with(data_url_prefix) {
         // 找到逗号位置，分离元数据和Base64数据
         let comma_pos = base64_data.find(",").ok_or_else(|| {
             "Invalid data URL format: missing comma separator".to_string()
         })?;
         
-        // 提取MIME类型信息
+        // 提取MIME子类型 (例如 "png;base64" -> "png")
         let mime_part = &base64_data[data_url_prefix.len()..comma_pos];
-        let extension = if mime_part.contains("png") {
-            "png"
-        } else if mime_part.contains("jpeg") || mime_part.contains("jpg") {
-            "jpg"
-        } else if mime_part.contains("gif") {
-            "gif"
-        } else if mime_part.contains("webp") {
-            "webp"
-        } else {
-            "png" // 默认为PNG
-        };
+        let subtype = mime_part
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_lowercase();
         
         // 提取纯Base64内容
         let base64_content = &base64_data[comma_pos + 1..];
-        (base64_content, extension)
+        (base64_content, extension_from_mime_subtype(&subtype))
     } else {
         // 如果没有Data URL前缀，假设是纯Base64数据
-        (base64_data.as_str(), format.as_deref().unwrap_or("png"))
+        (base64_data.as_str(), Some(format.as_deref().unwrap_or("png")))
     };
 
-    println!("Detected extension: {}", extension);
     println!("Base64 content length: {}", base64_content.len());
     
     // 解码Base64数据
@@ -60,6 +54,22 @@ pub async fn save_clipboard_image(
 
     println!("Decoded image data size: {} bytes", image_data.len());
 
+    // MIME类型无法识别时，根据文件头魔数判断真实格式，而不是默认写成PNG
+    let extension = match mime_extension {
+        Some(ext) => ext,
+        None => {
+            let detected = image::guess_format(&image_data)
+                .map_err(|_| "Clipboard data is not a recognized image format".to_string())?;
+            detected
+                .extensions_str()
+                .first()
+                .copied()
+                .ok_or_else(|| "Clipboard data is not a recognized image format".to_string())?
+        }
+    };
+
+    println!("Detected extension: {}", extension);
+
     // 获取用户临时目录，确保使用完整路径
     let temp_dir = std::env::var("TEMP")
         .or_else(|_| std::env::var("TMP"))
@@ -117,4 +127,17 @@ pub async fn save_clipboard_image(
         file_path: Some(path_str),
         error: None,
     })
-}
\ No newline at end of file
+}
+
+/// 将MIME子类型映射为文件扩展名，无法识别时返回None
+fn extension_from_mime_subtype(subtype: &str) -> Option<&'static str> {
+    match subtype {
+        "png" => Some("png"),
+        "jpeg" | "jpg" | "pjpeg" => Some("jpg"),
+        "gif" => Some("gif"),
+        "webp" => Some("webp"),
+        "bmp" | "x-bmp" | "x-ms-bmp" => Some("bmp"),
+        "tiff" | "tif" => Some("tiff"),
+        _ => None,
+    }
+}
@@ -0,0 +1,70 @@
+use serde::Serialize;
+use std::fmt;
+
+/// Structured error returned by commands.
+///
+/// Serialized as `{ "kind": "not_found", "message": "..." }` so the frontend can
+/// switch on `kind` (e.g. retry vs. show an error) instead of parsing the message.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", content = "message", rename_all = "snake_case")]
+pub enum CommandError {
+    /// The home directory (and therefore ~/.claude) could not be resolved
+    HomeDirNotFound(String),
+    /// A file or directory could not be read
+    FileRead(String),
+    /// A file or directory could not be written, moved or deleted
+    FileWrite(String),
+    /// Stored or returned data could not be parsed or serialized
+    Parse(String),
+    /// The requested item does not exist
+    NotFound(String),
+    /// The caller passed invalid input
+    Validation(String),
+    /// A network request failed
+    Network(String),
+    /// Anything else, e.g. failures of external processes
+    Other(String),
+}
+
+impl CommandError {
+    pub fn message(&self) -> &str {
+        match self {
+            CommandError::HomeDirNotFound(message)
+            | CommandError::FileRead(message)
+            | CommandError::FileWrite(message)
+            | CommandError::Parse(message)
+            | CommandError::NotFound(message)
+            | CommandError::Validation(message)
+            | CommandError::Network(message)
+            | CommandError::Other(message) => message,
+        }
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+// Helpers that still report plain strings surface as `Other`
+impl From<String> for CommandError {
+    fn from(message: String) -> Self {
+        CommandError::Other(message)
+    }
+}
+
+impl From<&str> for CommandError {
+    fn from(message: &str) -> Self {
+        CommandError::Other(message.to_string())
+    }
+}
+
+// Lets code that still returns `Result<_, String>` use `?` on `CommandError`
+impl From<CommandError> for String {
+    fn from(error: CommandError) -> Self {
+        error.to_string()
+    }
+}
@@ -6,3 +6,5 @@ pub mod storage;
 pub mod slash_commands;
 pub mod clipboard;
 pub mod provider;
+pub mod error;
+pub mod profiles;
@@ -0,0 +1,319 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use tauri::command;
+use crate::commands::error::CommandError;
+use crate::commands::usage::write_usage_file_atomic;
+
+/// Name of the built-in profile that always points at `~/.claude`
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// A named Claude config directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaudeProfile {
+    pub name: String,
+    pub config_dir: String,
+    /// Whether this profile is the one commands currently read from
+    pub active: bool,
+    /// Whether the config directory exists on disk
+    pub exists: bool,
+}
+
+/// On-disk registry of profiles, stored in `~/.claude/profiles.json`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ProfileRegistry {
+    /// Active profile name; `None` means the default profile
+    #[serde(default)]
+    active: Option<String>,
+    /// Profile name -> config directory
+    #[serde(default)]
+    profiles: BTreeMap<String, String>,
+}
+
+// Serializes read-modify-write cycles on the registry
+static PROFILES_LOCK: Mutex<()> = Mutex::new(());
+
+// Last registry read, keyed by its path. Every config dir lookup consults the
+// registry, so it is only re-read after a profile command saves it.
+static REGISTRY_CACHE: Mutex<Option<(PathBuf, ProfileRegistry)>> = Mutex::new(None);
+
+fn lock_profiles() -> Result<MutexGuard<'static, ()>, CommandError> {
+    PROFILES_LOCK
+        .lock()
+        .map_err(|e| CommandError::Other(format!("Failed to lock profiles: {}", e)))
+}
+
+fn default_config_dir() -> Result<PathBuf, CommandError> {
+    dirs::home_dir()
+        .map(|home| home.join(".claude"))
+        .ok_or_else(|| CommandError::HomeDirNotFound("Failed to get home directory".to_string()))
+}
+
+// The registry always lives in the default profile's directory so switching
+// profiles never hides it
+fn profiles_registry_path(default_dir: &Path) -> PathBuf {
+    default_dir.join("profiles.json")
+}
+
+fn load_registry(default_dir: &Path) -> Result<ProfileRegistry, CommandError> {
+    let path = profiles_registry_path(default_dir);
+    let mut cache = REGISTRY_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((cached_path, registry)) = cache.as_ref() {
+        if *cached_path == path {
+            return Ok(registry.clone());
+        }
+    }
+
+    let registry = read_registry(&path)?;
+    *cache = Some((path, registry.clone()));
+    Ok(registry)
+}
+
+fn read_registry(path: &Path) -> Result<ProfileRegistry, CommandError> {
+    if !path.exists() {
+        return Ok(ProfileRegistry::default());
+    }
+
+    let content = fs::read_to_string(path)
+        .map_err(|e| CommandError::FileRead(format!("Failed to read profiles: {}", e)))?;
+    if content.trim().is_empty() {
+        return Ok(ProfileRegistry::default());
+    }
+
+    serde_json::from_str(&content)
+        .map_err(|e| CommandError::Parse(format!("Failed to parse profiles: {}", e)))
+}
+
+fn save_registry(default_dir: &Path, registry: &ProfileRegistry) -> Result<(), CommandError> {
+    let content = serde_json::to_string_pretty(registry)
+        .map_err(|e| CommandError::Parse(format!("Failed to serialize profiles: {}", e)))?;
+    let result = write_usage_file_atomic(&profiles_registry_path(default_dir), content.as_bytes());
+    invalidate_registry_cache();
+    result
+}
+
+fn invalidate_registry_cache() {
+    *REGISTRY_CACHE.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Config directory of the active profile, or `None` when the default profile
+/// is active (or the registry is missing or unreadable)
+pub(crate) fn active_profile_dir() -> Option<PathBuf> {
+    active_profile_dir_in(&default_config_dir().ok()?)
+}
+
+fn active_profile_dir_in(default_dir: &Path) -> Option<PathBuf> {
+    let registry = load_registry(default_dir).ok()?;
+    let active = registry.active.as_ref()?;
+    registry.profiles.get(active).map(PathBuf::from)
+}
+
+fn to_profiles(registry: &ProfileRegistry, default_dir: &Path) -> Vec<ClaudeProfile> {
+    let active = registry
+        .active
+        .as_deref()
+        .filter(|name| registry.profiles.contains_key(*name))
+        .unwrap_or(DEFAULT_PROFILE);
+
+    let mut profiles = vec![ClaudeProfile {
+        name: DEFAULT_PROFILE.to_string(),
+        config_dir: default_dir.to_string_lossy().to_string(),
+        active: active == DEFAULT_PROFILE,
+        exists: default_dir.exists(),
+    }];
+    profiles.extend(registry.profiles.iter().map(|(name, dir)| ClaudeProfile {
+        name: name.clone(),
+        config_dir: dir.clone(),
+        active: active == name,
+        exists: PathBuf::from(dir).is_dir(),
+    }));
+
+    profiles
+}
+
+fn find_profile(registry: &ProfileRegistry, default_dir: &Path, name: &str) -> Result<ClaudeProfile, CommandError> {
+    to_profiles(registry, default_dir)
+        .into_iter()
+        .find(|p| p.name == name)
+        .ok_or_else(|| CommandError::NotFound(format!("Profile '{}' not found", name)))
+}
+
+fn validate_profile_name(name: &str) -> Result<String, CommandError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(CommandError::Validation("Profile name cannot be empty".to_string()));
+    }
+    if name == DEFAULT_PROFILE {
+        return Err(CommandError::Validation(format!(
+            "'{}' is reserved for ~/.claude",
+            DEFAULT_PROFILE
+        )));
+    }
+    Ok(name.to_string())
+}
+
+/// List the default profile followed by the registered profiles, sorted by name
+#[command]
+pub fn list_profiles() -> Result<Vec<ClaudeProfile>, CommandError> {
+    let default_dir = default_config_dir()?;
+    Ok(to_profiles(&load_registry(&default_dir)?, &default_dir))
+}
+
+#[command]
+pub fn get_active_profile() -> Result<ClaudeProfile, CommandError> {
+    let default_dir = default_config_dir()?;
+    to_profiles(&load_registry(&default_dir)?, &default_dir)
+        .into_iter()
+        .find(|p| p.active)
+        .ok_or_else(|| CommandError::NotFound("No active profile".to_string()))
+}
+
+/// Make `name` the active profile. Usage, provider and slash command commands read
+/// from its directory unless `CLAUDE_CONFIG_DIR` is set, which still takes precedence,
+/// and Claude processes the app starts get it as their `CLAUDE_CONFIG_DIR`.
+#[command]
+pub fn set_active_profile(name: String) -> Result<ClaudeProfile, CommandError> {
+    set_active_profile_in(&default_config_dir()?, &name)
+}
+
+fn set_active_profile_in(default_dir: &Path, name: &str) -> Result<ClaudeProfile, CommandError> {
+    let _guard = lock_profiles()?;
+    let mut registry = load_registry(default_dir)?;
+
+    let name = name.trim();
+    if name == DEFAULT_PROFILE {
+        registry.active = None;
+    } else if registry.profiles.contains_key(name) {
+        registry.active = Some(name.to_string());
+    } else {
+        return Err(CommandError::NotFound(format!("Profile '{}' not found", name)));
+    }
+    save_registry(default_dir, &registry)?;
+
+    find_profile(&registry, default_dir, name)
+}
+
+/// Register a profile, or point an existing one at a new directory
+#[command]
+pub fn add_profile(name: String, config_dir: String) -> Result<ClaudeProfile, CommandError> {
+    add_profile_in(&default_config_dir()?, &name, &config_dir)
+}
+
+fn add_profile_in(default_dir: &Path, name: &str, config_dir: &str) -> Result<ClaudeProfile, CommandError> {
+    let name = validate_profile_name(name)?;
+    let dir = PathBuf::from(config_dir.trim());
+    if !dir.is_absolute() {
+        return Err(CommandError::Validation("Config directory must be an absolute path".to_string()));
+    }
+    if !dir.is_dir() {
+        return Err(CommandError::Validation(format!(
+            "Config directory does not exist: {}",
+            dir.display()
+        )));
+    }
+
+    let _guard = lock_profiles()?;
+    let mut registry = load_registry(default_dir)?;
+    registry.profiles.insert(name.clone(), dir.to_string_lossy().to_string());
+    save_registry(default_dir, &registry)?;
+
+    find_profile(&registry, default_dir, &name)
+}
+
+/// Remove a profile; removing the active one falls back to the default profile
+#[command]
+pub fn remove_profile(name: String) -> Result<(), CommandError> {
+    remove_profile_in(&default_config_dir()?, &name)
+}
+
+fn remove_profile_in(default_dir: &Path, name: &str) -> Result<(), CommandError> {
+    let name = validate_profile_name(name)?;
+
+    let _guard = lock_profiles()?;
+    let mut registry = load_registry(default_dir)?;
+    if registry.profiles.remove(&name).is_none() {
+        return Err(CommandError::NotFound(format!("Profile '{}' not found", name)));
+    }
+    if registry.active.as_deref() == Some(name.as_str()) {
+        registry.active = None;
+    }
+    save_registry(default_dir, &registry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_set_active_profile_switches_config_dir() {
+        let default_dir = TempDir::new().unwrap();
+        let work_dir = TempDir::new().unwrap();
+        let work_path = work_dir.path().to_string_lossy().to_string();
+
+        let added = add_profile_in(default_dir.path(), "work", &work_path).unwrap();
+        assert!(!added.active);
+        assert_eq!(active_profile_dir_in(default_dir.path()), None);
+
+        let active = set_active_profile_in(default_dir.path(), " work ").unwrap();
+        assert_eq!(active.name, "work");
+        assert!(active.active && active.exists);
+        assert_eq!(active_profile_dir_in(default_dir.path()), Some(work_dir.path().to_path_buf()));
+
+        // The saved registry agrees with what later lookups return
+        let registry = read_registry(&profiles_registry_path(default_dir.path())).unwrap();
+        assert_eq!(registry.active.as_deref(), Some("work"));
+
+        let default = set_active_profile_in(default_dir.path(), DEFAULT_PROFILE).unwrap();
+        assert_eq!(default.name, DEFAULT_PROFILE);
+        assert_eq!(active_profile_dir_in(default_dir.path()), None);
+
+        assert!(matches!(
+            set_active_profile_in(default_dir.path(), "missing"),
+            Err(CommandError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_removing_active_profile_falls_back_to_default() {
+        let default_dir = TempDir::new().unwrap();
+        let work_dir = TempDir::new().unwrap();
+        add_profile_in(default_dir.path(), "work", &work_dir.path().to_string_lossy()).unwrap();
+        set_active_profile_in(default_dir.path(), "work").unwrap();
+
+        remove_profile_in(default_dir.path(), "work").unwrap();
+        assert_eq!(active_profile_dir_in(default_dir.path()), None);
+
+        let registry = load_registry(default_dir.path()).unwrap();
+        let profiles = to_profiles(&registry, default_dir.path());
+        assert_eq!(profiles.len(), 1);
+        assert_eq!(profiles[0].name, DEFAULT_PROFILE);
+        assert!(profiles[0].active);
+
+        assert!(matches!(
+            remove_profile_in(default_dir.path(), "work"),
+            Err(CommandError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_reserved_profile_name_is_rejected() {
+        let default_dir = TempDir::new().unwrap();
+        let other_dir = TempDir::new().unwrap();
+        let other_path = other_dir.path().to_string_lossy().to_string();
+
+        for name in [DEFAULT_PROFILE, " default ", ""] {
+            assert!(matches!(
+                add_profile_in(default_dir.path(), name, &other_path),
+                Err(CommandError::Validation(_))
+            ));
+            assert!(matches!(
+                remove_profile_in(default_dir.path(), name),
+                Err(CommandError::Validation(_))
+            ));
+        }
+        assert!(!profiles_registry_path(default_dir.path()).exists());
+    }
+}
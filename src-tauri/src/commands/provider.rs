@@ -1,9 +1,17 @@
+use futures::stream::{self, StreamExt};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
 use std::fs;
+use std::io::Write;
 use std::path::PathBuf;
 use std::process::Command;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 use tauri::{command, AppHandle, Manager};
+use crate::commands::error::CommandError;
+use crate::commands::usage::{get_claude_config_dir, ModelPricing};
+use crate::process::registry::{ProcessInfo, ProcessType};
 use crate::process::ProcessRegistryState;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -15,6 +23,21 @@ pub struct ProviderConfig {
     pub auth_token: Option<String>,
     pub api_key: Option<String>,
     pub model: Option<String>,
+    /// 后台任务使用的小模型，切换时写入 ANTHROPIC_SMALL_FAST_MODEL
+    #[serde(default)]
+    pub small_fast_model: Option<String>,
+    /// 该代理商使用的 HTTP 代理。连通性测试时优先于系统 HTTP(S)_PROXY；
+    /// 激活时仅作为 HTTP_PROXY/HTTPS_PROXY 注入本应用启动的 Claude 进程，
+    /// 不修改用户的系统环境变量，未激活时这些进程沿用系统代理
+    #[serde(default)]
+    pub http_proxy: Option<String>,
+    /// 不经过代理的主机列表 (逗号分隔)，随 http_proxy 作为 NO_PROXY 注入
+    #[serde(default)]
+    pub no_proxy: Option<String>,
+    /// 代理商自定义价格 (每百万 token)。设置后经此 base_url 的用量按该价格计费，
+    /// 不再按模型名匹配官方价格
+    #[serde(default)]
+    pub pricing_override: Option<ModelPricing>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -25,24 +48,97 @@ pub struct CurrentConfig {
     pub anthropic_model: Option<String>,
 }
 
+/// providers.json 中无法解析的单个条目
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MalformedProviderEntry {
+    pub index: usize,
+    pub id: Option<String>,
+    pub error: String,
+}
+
+/// providers.json 校验结果
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProvidersValidation {
+    pub valid: bool,
+    pub provider_count: usize,
+    pub error: Option<String>,
+    pub error_line: Option<usize>,
+    pub error_column: Option<usize>,
+    pub malformed_entries: Vec<MalformedProviderEntry>,
+    pub repaired: bool,
+    pub backup_path: Option<String>,
+}
+
+/// 连通性测试的结论
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderTestStatus {
+    /// 端点可达且请求被接受
+    Ok,
+    /// 端点可达但被限流 (HTTP 429)
+    Throttled,
+    /// 端点可达但认证失败 (HTTP 401/403)
+    AuthFailed,
+    /// 端点可达但返回其他错误
+    Error,
+    /// 无法建立连接或请求超时
+    Unreachable,
+}
+
+/// 单个代理商的连通性测试结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderTestResult {
+    pub provider_id: String,
+    pub provider_name: String,
+    pub base_url: String,
+    pub reachable: bool,
+    pub status: ProviderTestStatus,
+    pub status_code: Option<u16>,
+    pub latency_ms: Option<u64>,
+    pub error: Option<String>,
+    /// 仅在请求校验模型时填写：模型是否接受了 1 token 的补全请求
+    pub model_accepted: Option<bool>,
+}
+
+/// 代理商配置及其当前状态
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProviderWithStatus {
+    #[serde(flatten)]
+    pub config: ProviderConfig,
+    /// 当前环境变量是否指向该代理商
+    pub is_active: bool,
+    /// 本次运行期间最近一次连通性测试的结果
+    pub last_test: Option<ProviderTestResult>,
+    /// 最近一次测试的时间 (RFC3339)
+    pub last_tested_at: Option<String>,
+}
+
+// 连通性测试的超时与并发上限
+const PROVIDER_TEST_CONNECT_TIMEOUT_SECS: u64 = 5;
+const PROVIDER_TEST_TIMEOUT_SECS: u64 = 10;
+const PROVIDER_TEST_CONCURRENCY: usize = 4;
+
+// 保护 providers.json 读-改-写过程的进程级互斥锁
+static PROVIDERS_LOCK: Mutex<()> = Mutex::new(());
+
+// 最近一次连通性测试结果 (按代理商 ID 缓存，仅保存在内存中)
+static LAST_TEST_RESULTS: Mutex<Option<HashMap<String, (ProviderTestResult, String)>>> = Mutex::new(None);
+
 // 获取配置文件路径
-fn get_providers_config_path() -> Result<PathBuf, String> {
-    let home_dir = dirs::home_dir()
-        .ok_or_else(|| "无法获取用户主目录".to_string())?;
-    
-    let config_dir = home_dir.join(".claude");
+fn get_providers_config_path() -> Result<PathBuf, CommandError> {
+    let config_dir = get_claude_config_dir()?;
     
     // 确保配置目录存在
     if !config_dir.exists() {
         fs::create_dir_all(&config_dir)
-            .map_err(|e| format!("无法创建配置目录: {}", e))?;
+            .map_err(|e| CommandError::FileWrite(format!("无法创建配置目录: {}", e)))?;
     }
     
     Ok(config_dir.join("providers.json"))
 }
 
 // 从文件加载代理商配置
-fn load_providers_from_file() -> Result<Vec<ProviderConfig>, String> {
+pub(crate) fn load_providers_from_file() -> Result<Vec<ProviderConfig>, CommandError> {
     let config_path = get_providers_config_path()?;
     
     if !config_path.exists() {
@@ -51,34 +147,123 @@ fn load_providers_from_file() -> Result<Vec<ProviderConfig>, String> {
     }
     
     let content = fs::read_to_string(&config_path)
-        .map_err(|e| format!("读取配置文件失败: {}", e))?;
+        .map_err(|e| CommandError::FileRead(format!("读取配置文件失败: {}", e)))?;
     
     if content.trim().is_empty() {
         return Ok(vec![]);
     }
     
     let providers: Vec<ProviderConfig> = serde_json::from_str(&content)
-        .map_err(|e| format!("解析配置文件失败: {}", e))?;
+        .map_err(|e| CommandError::Parse(format!("解析配置文件失败: {}", e)))?;
     
     Ok(providers)
 }
 
-// 保存代理商配置到文件
-fn save_providers_to_file(providers: &Vec<ProviderConfig>) -> Result<(), String> {
+// 保存代理商配置到文件 (先写入同目录临时文件再原子重命名，避免写入中断导致文件损坏)
+fn save_providers_to_file(providers: &Vec<ProviderConfig>) -> Result<(), CommandError> {
     let config_path = get_providers_config_path()?;
     
     let content = serde_json::to_string_pretty(providers)
-        .map_err(|e| format!("序列化配置失败: {}", e))?;
+        .map_err(|e| CommandError::Parse(format!("序列化配置失败: {}", e)))?;
     
-    fs::write(&config_path, content)
-        .map_err(|e| format!("写入配置文件失败: {}", e))?;
+    let config_dir = config_path.parent()
+        .ok_or_else(|| CommandError::Other("无法获取配置目录".to_string()))?;
+    
+    let mut temp_file = tempfile::NamedTempFile::new_in(config_dir)
+        .map_err(|e| CommandError::FileWrite(format!("创建临时文件失败: {}", e)))?;
+    
+    temp_file.write_all(content.as_bytes())
+        .and_then(|_| temp_file.as_file().sync_all())
+        .map_err(|e| CommandError::FileWrite(format!("写入配置文件失败: {}", e)))?;
+    
+    temp_file.persist(&config_path)
+        .map_err(|e| CommandError::FileWrite(format!("写入配置文件失败: {}", e)))?;
     
     Ok(())
 }
 
+// 获取代理商配置文件的进程级锁，保证读-改-写过程不会被并发命令覆盖
+fn lock_providers() -> Result<MutexGuard<'static, ()>, CommandError> {
+    PROVIDERS_LOCK.lock()
+        .map_err(|e| CommandError::Other(format!("获取配置文件锁失败: {}", e)))
+}
+
+/// 校验 providers.json，可选择备份损坏文件并重置为空列表
+#[command]
+pub fn validate_providers_file(repair: bool) -> Result<ProvidersValidation, CommandError> {
+    let _guard = lock_providers()?;
+    let config_path = get_providers_config_path()?;
+
+    let mut validation = ProvidersValidation {
+        valid: true,
+        provider_count: 0,
+        error: None,
+        error_line: None,
+        error_column: None,
+        malformed_entries: vec![],
+        repaired: false,
+        backup_path: None,
+    };
+
+    if !config_path.exists() {
+        return Ok(validation);
+    }
+
+    let content = fs::read_to_string(&config_path)
+        .map_err(|e| CommandError::FileRead(format!("读取配置文件失败: {}", e)))?;
+
+    if content.trim().is_empty() {
+        return Ok(validation);
+    }
+
+    match serde_json::from_str::<Vec<ProviderConfig>>(&content) {
+        Ok(providers) => {
+            validation.provider_count = providers.len();
+            return Ok(validation);
+        }
+        Err(e) => {
+            validation.valid = false;
+            validation.error = Some(e.to_string());
+            validation.error_line = Some(e.line());
+            validation.error_column = Some(e.column());
+        }
+    }
+
+    // 如果数组本身可以解析，逐个定位格式错误的条目
+    if let Ok(values) = serde_json::from_str::<Vec<serde_json::Value>>(&content) {
+        for (index, value) in values.into_iter().enumerate() {
+            let id = value.get("id").and_then(|v| v.as_str()).map(|s| s.to_string());
+            match serde_json::from_value::<ProviderConfig>(value) {
+                Ok(_) => validation.provider_count += 1,
+                Err(e) => validation.malformed_entries.push(MalformedProviderEntry {
+                    index,
+                    id,
+                    error: e.to_string(),
+                }),
+            }
+        }
+    }
+
+    if repair {
+        // 先备份原文件，备份成功后才重置，避免数据丢失
+        let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
+        let backup_path = config_path.with_file_name(format!("providers.json.bak-{}", timestamp));
+        fs::copy(&config_path, &backup_path)
+            .map_err(|e| CommandError::FileWrite(format!("备份配置文件失败: {}", e)))?;
+
+        save_providers_to_file(&vec![])?;
+
+        log::warn!("providers.json 已损坏，已备份到 {:?} 并重置为空列表", backup_path);
+        validation.repaired = true;
+        validation.backup_path = Some(backup_path.to_string_lossy().to_string());
+    }
+
+    Ok(validation)
+}
+
 // CRUD 操作 - 获取所有代理商配置
 #[command]
-pub fn get_provider_presets() -> Result<Vec<ProviderConfig>, String> {
+pub fn get_provider_presets() -> Result<Vec<ProviderConfig>, CommandError> {
     let config_path = get_providers_config_path()?;
     
     if !config_path.exists() {
@@ -86,36 +271,75 @@ pub fn get_provider_presets() -> Result<Vec<ProviderConfig>, String> {
     }
     
     let content = fs::read_to_string(&config_path)
-        .map_err(|e| format!("无法读取配置文件: {}", e))?;
+        .map_err(|e| CommandError::FileRead(format!("无法读取配置文件: {}", e)))?;
     
     let configs: Vec<ProviderConfig> = serde_json::from_str(&content)
-        .map_err(|e| format!("配置文件格式错误: {}", e))?;
+        .map_err(|e| CommandError::Parse(format!("配置文件格式错误: {}", e)))?;
     
     Ok(configs)
 }
 
+// 规范化 base_url 以便比较 (忽略大小写、首尾空白和末尾斜杠)
+fn normalize_base_url(base_url: &str) -> String {
+    base_url.trim().trim_end_matches('/').to_lowercase()
+}
+
+/// 添加代理商的结果；`duplicate_of` 为已使用相同 base_url 的代理商 ID (非致命警告)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AddProviderResult {
+    pub message: String,
+    pub duplicate_of: Option<String>,
+}
+
 #[command]
-pub fn add_provider_config(config: ProviderConfig) -> Result<String, String> {
+pub fn add_provider_config(config: ProviderConfig, strict: Option<bool>) -> Result<AddProviderResult, CommandError> {
+    let _guard = lock_providers()?;
+    
     let mut providers = load_providers_from_file()?;
     
     // 检查ID是否已存在
     if providers.iter().any(|p| p.id == config.id) {
-        return Err(format!("ID '{}' 已存在，请使用不同的ID", config.id));
+        return Err(CommandError::Validation(format!("ID '{}' 已存在，请使用不同的ID", config.id)));
+    }
+    
+    // 检查是否已有代理商使用相同的 base_url (同一地址使用不同认证是合法的，默认仅警告)
+    let normalized_url = normalize_base_url(&config.base_url);
+    let duplicate = providers
+        .iter()
+        .find(|p| normalize_base_url(&p.base_url) == normalized_url)
+        .map(|p| (p.id.clone(), p.name.clone()));
+    
+    if let Some((_, existing_name)) = &duplicate {
+        if strict.unwrap_or(false) {
+            return Err(CommandError::Validation(format!("代理商 '{}' 已使用相同的 base_url: {}", existing_name, config.base_url)));
+        }
+        log::warn!("代理商 '{}' 与 '{}' 使用相同的 base_url: {}", config.name, existing_name, config.base_url);
     }
     
     providers.push(config.clone());
     save_providers_to_file(&providers)?;
     
-    Ok(format!("成功添加代理商配置: {}", config.name))
+    Ok(match duplicate {
+        Some((existing_id, existing_name)) => AddProviderResult {
+            message: format!("成功添加代理商配置: {} (警告: 与 '{}' 使用相同的 base_url)", config.name, existing_name),
+            duplicate_of: Some(existing_id),
+        },
+        None => AddProviderResult {
+            message: format!("成功添加代理商配置: {}", config.name),
+            duplicate_of: None,
+        },
+    })
 }
 
 // CRUD 操作 - 更新代理商配置
 #[command]
-pub fn update_provider_config(config: ProviderConfig) -> Result<String, String> {
+pub fn update_provider_config(config: ProviderConfig) -> Result<String, CommandError> {
+    let _guard = lock_providers()?;
+    
     let mut providers = load_providers_from_file()?;
     
     let index = providers.iter().position(|p| p.id == config.id)
-        .ok_or_else(|| format!("未找到ID为 '{}' 的配置", config.id))?;
+        .ok_or_else(|| CommandError::NotFound(format!("未找到ID为 '{}' 的配置", config.id)))?;
     
     providers[index] = config.clone();
     save_providers_to_file(&providers)?;
@@ -125,11 +349,13 @@ pub fn update_provider_config(config: ProviderConfig) -> Result<String, String>
 
 // CRUD 操作 - 删除代理商配置
 #[command]
-pub fn delete_provider_config(id: String) -> Result<String, String> {
+pub fn delete_provider_config(id: String) -> Result<String, CommandError> {
+    let _guard = lock_providers()?;
+    
     let mut providers = load_providers_from_file()?;
     
     let index = providers.iter().position(|p| p.id == id)
-        .ok_or_else(|| format!("未找到ID为 '{}' 的配置", id))?;
+        .ok_or_else(|| CommandError::NotFound(format!("未找到ID为 '{}' 的配置", id)))?;
     
     let deleted_config = providers.remove(index);
     save_providers_to_file(&providers)?;
@@ -137,18 +363,67 @@ pub fn delete_provider_config(id: String) -> Result<String, String> {
     Ok(format!("成功删除代理商配置: {}", deleted_config.name))
 }
 
+// 当前环境变量是否指向该代理商：base_url 一致，且代理商配置的密钥与环境变量相同
+fn is_provider_active(provider: &ProviderConfig, current: &CurrentConfig) -> bool {
+    let url_matches = current
+        .anthropic_base_url
+        .as_deref()
+        .is_some_and(|url| normalize_base_url(url) == normalize_base_url(&provider.base_url));
+    let token_matches = provider.auth_token.is_none() || provider.auth_token == current.anthropic_auth_token;
+    let key_matches = provider.api_key.is_none() || provider.api_key == current.anthropic_api_key;
+    
+    url_matches && token_matches && key_matches
+}
+
+/// 获取所有代理商及其激活状态和最近一次测试结果 (不会发起新的连通性测试)
+#[command]
+pub fn get_providers_with_status() -> Result<Vec<ProviderWithStatus>, CommandError> {
+    let providers = load_providers_from_file()?;
+    let current = get_current_provider_config()?;
+    let last_results = LAST_TEST_RESULTS
+        .lock()
+        .map_err(|e| CommandError::Other(format!("获取测试结果缓存失败: {}", e)))?;
+    
+    Ok(providers
+        .into_iter()
+        .map(|config| {
+            let cached = last_results.as_ref().and_then(|cache| cache.get(&config.id)).cloned();
+            ProviderWithStatus {
+                is_active: is_provider_active(&config, &current),
+                last_test: cached.as_ref().map(|(result, _)| result.clone()),
+                last_tested_at: cached.map(|(_, tested_at)| tested_at),
+                config,
+            }
+        })
+        .collect())
+}
+
 // CRUD 操作 - 获取单个代理商配置
 #[command]
-pub fn get_provider_config(id: String) -> Result<ProviderConfig, String> {
+pub fn get_provider_config(id: String) -> Result<ProviderConfig, CommandError> {
     let providers = load_providers_from_file()?;
     
     providers.into_iter()
         .find(|p| p.id == id)
-        .ok_or_else(|| format!("未找到ID为 '{}' 的配置", id))
+        .ok_or_else(|| CommandError::NotFound(format!("未找到ID为 '{}' 的配置", id)))
+}
+
+// 按规范化后的 base_url 查找第一个匹配的代理商
+pub(crate) fn find_provider_by_base_url<'a>(providers: &'a [ProviderConfig], base_url: &str) -> Option<&'a ProviderConfig> {
+    let target = normalize_base_url(base_url);
+    providers.iter().find(|p| normalize_base_url(&p.base_url) == target)
 }
 
+// 根据 base_url 反查已保存的代理商配置，未匹配时返回 None
 #[command]
-pub fn get_current_provider_config() -> Result<CurrentConfig, String> {
+pub fn get_provider_by_base_url(base_url: String) -> Result<Option<ProviderConfig>, CommandError> {
+    let providers = load_providers_from_file()?;
+
+    Ok(find_provider_by_base_url(&providers, &base_url).cloned())
+}
+
+#[command]
+pub fn get_current_provider_config() -> Result<CurrentConfig, CommandError> {
     Ok(CurrentConfig {
         anthropic_base_url: env::var("ANTHROPIC_BASE_URL").ok(),
         anthropic_auth_token: env::var("ANTHROPIC_AUTH_TOKEN").ok(),
@@ -157,8 +432,265 @@ pub fn get_current_provider_config() -> Result<CurrentConfig, String> {
     })
 }
 
+/// 切换代理商时单个环境变量的变化 (密钥已打码)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EnvDiff {
+    pub name: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+// 密钥只保留首尾各 4 位，较短的值完全隐藏
+fn mask_secret(value: &str) -> String {
+    let chars: Vec<char> = value.chars().collect();
+    if chars.len() <= 8 {
+        "****".to_string()
+    } else {
+        format!(
+            "{}****{}",
+            chars[..4].iter().collect::<String>(),
+            chars[chars.len() - 4..].iter().collect::<String>()
+        )
+    }
+}
+
+/// 预览切换到指定代理商时会改变的环境变量，与 switch_provider_config 的设置逻辑保持一致
+#[command]
+pub fn preview_provider_switch(config: ProviderConfig) -> Result<Vec<EnvDiff>, CommandError> {
+    let current = get_current_provider_config()?;
+    let non_empty = |value: &Option<String>| value.clone().filter(|v| !v.trim().is_empty());
+    let proxy_before = active_proxy_env_for(&load_providers_from_file()?, &current);
+    
+    let mut changes: Vec<(&str, Option<String>, Option<String>, bool)> = vec![
+        ("ANTHROPIC_BASE_URL", current.anthropic_base_url, Some(config.base_url.clone()), false),
+        ("ANTHROPIC_AUTH_TOKEN", current.anthropic_auth_token, config.auth_token.clone(), true),
+        ("ANTHROPIC_API_KEY", current.anthropic_api_key, config.api_key.clone(), true),
+        ("ANTHROPIC_MODEL", current.anthropic_model, config.model.clone(), false),
+        (
+            "ANTHROPIC_SMALL_FAST_MODEL",
+            env::var("ANTHROPIC_SMALL_FAST_MODEL").ok(),
+            non_empty(&config.small_fast_model),
+            false,
+        ),
+    ];
+    
+    // 代理变量只注入启动的 Claude 进程：比较切换前后这些进程实际得到的值
+    let proxy_after = provider_proxy_vars(&config);
+    for name in PROXY_ENV_VARS {
+        let from = effective_proxy_value(&proxy_before, name);
+        let to = effective_proxy_value(&proxy_after, name);
+        changes.push((name, from, to, false));
+    }
+    
+    Ok(changes
+        .into_iter()
+        .filter(|(_, from, to, _)| from != to)
+        .map(|(name, from, to, secret)| {
+            let mask = |value: Option<String>| {
+                if secret {
+                    value.map(|v| mask_secret(&v))
+                } else {
+                    value
+                }
+            };
+            EnvDiff {
+                name: name.to_string(),
+                from: mask(from),
+                to: mask(to),
+            }
+        })
+        .collect())
+}
+
+/// 两个代理商配置中单个字段的差异 (密钥已打码)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FieldDiff {
+    pub field: String,
+    pub a: Option<String>,
+    pub b: Option<String>,
+}
+
+/// 对比两个已保存的代理商，只返回取值不同的字段。密钥按原值比较，但只返回打码后的值
+#[command]
+pub fn diff_providers(id_a: String, id_b: String) -> Result<Vec<FieldDiff>, CommandError> {
+    let providers = load_providers_from_file()?;
+    let find = |id: &str| {
+        providers
+            .iter()
+            .find(|p| p.id == id)
+            .ok_or_else(|| CommandError::NotFound(format!("未找到ID为 '{}' 的配置", id)))
+    };
+    let a = find(&id_a)?;
+    let b = find(&id_b)?;
+
+    let fields: [(&str, &Option<String>, &Option<String>, bool); 7] = [
+        ("auth_token", &a.auth_token, &b.auth_token, true),
+        ("api_key", &a.api_key, &b.api_key, true),
+        ("model", &a.model, &b.model, false),
+        ("small_fast_model", &a.small_fast_model, &b.small_fast_model, false),
+        ("http_proxy", &a.http_proxy, &b.http_proxy, false),
+        ("no_proxy", &a.no_proxy, &b.no_proxy, false),
+        ("description", &Some(a.description.clone()), &Some(b.description.clone()), false),
+    ];
+
+    let mut diffs = Vec::new();
+    if normalize_base_url(&a.base_url) != normalize_base_url(&b.base_url) {
+        diffs.push(FieldDiff {
+            field: "base_url".to_string(),
+            a: Some(a.base_url.clone()),
+            b: Some(b.base_url.clone()),
+        });
+    }
+    for (field, value_a, value_b, secret) in fields {
+        // 空字符串与未设置视为相同
+        let value_a = value_a.as_deref().filter(|v| !v.trim().is_empty());
+        let value_b = value_b.as_deref().filter(|v| !v.trim().is_empty());
+        if value_a == value_b {
+            continue;
+        }
+        let show = |value: Option<&str>| {
+            value.map(|v| if secret { mask_secret(v) } else { v.to_string() })
+        };
+        diffs.push(FieldDiff {
+            field: field.to_string(),
+            a: show(value_a),
+            b: show(value_b),
+        });
+    }
+    if a.pricing_override != b.pricing_override {
+        let show = |pricing: &Option<ModelPricing>| pricing.as_ref().and_then(|p| serde_json::to_string(p).ok());
+        diffs.push(FieldDiff {
+            field: "pricing_override".to_string(),
+            a: show(&a.pricing_override),
+            b: show(&b.pricing_override),
+        });
+    }
+
+    Ok(diffs)
+}
+
+// 切换到该代理商时会写入的环境变量: (变量名, 值, 是否为密钥)，与 switch_provider_config 保持一致
+fn provider_env_vars(config: &ProviderConfig) -> Vec<(&'static str, String, bool)> {
+    let non_empty = |value: &Option<String>| value.clone().filter(|v| !v.trim().is_empty());
+    
+    let mut vars = vec![("ANTHROPIC_BASE_URL", config.base_url.clone(), false)];
+    for (name, value, secret) in [
+        ("ANTHROPIC_AUTH_TOKEN", &config.auth_token, true),
+        ("ANTHROPIC_API_KEY", &config.api_key, true),
+        ("ANTHROPIC_MODEL", &config.model, false),
+        ("ANTHROPIC_SMALL_FAST_MODEL", &config.small_fast_model, false),
+    ] {
+        if let Some(value) = non_empty(value) {
+            vars.push((name, value, secret));
+        }
+    }
+    vars.extend(provider_proxy_vars(config).into_iter().map(|(name, value)| (name, value, false)));
+    vars
+}
+
+const PROXY_ENV_VARS: [&str; 3] = ["HTTP_PROXY", "HTTPS_PROXY", "NO_PROXY"];
+
+// 代理商的代理设置对应的环境变量；未指定 http_proxy 时 no_proxy 无意义，一并忽略
+fn provider_proxy_vars(config: &ProviderConfig) -> Vec<(&'static str, String)> {
+    let non_empty = |value: &Option<String>| value.clone().filter(|v| !v.trim().is_empty());
+    
+    let mut vars = Vec::new();
+    if let Some(http_proxy) = non_empty(&config.http_proxy) {
+        vars.push(("HTTP_PROXY", http_proxy.clone()));
+        vars.push(("HTTPS_PROXY", http_proxy));
+        if let Some(no_proxy) = non_empty(&config.no_proxy) {
+            vars.push(("NO_PROXY", no_proxy));
+        }
+    }
+    vars
+}
+
+// 当前激活代理商的代理变量，没有激活的代理商时为空
+fn active_proxy_env_for(providers: &[ProviderConfig], current: &CurrentConfig) -> Vec<(&'static str, String)> {
+    providers
+        .iter()
+        .find(|p| is_provider_active(p, current))
+        .map(provider_proxy_vars)
+        .unwrap_or_default()
+}
+
+// 启动的 Claude 进程实际得到的值：代理商设置优先，否则继承系统环境变量
+fn effective_proxy_value(proxy_vars: &[(&str, String)], name: &str) -> Option<String> {
+    proxy_vars
+        .iter()
+        .find(|(var, _)| *var == name)
+        .map(|(_, value)| value.clone())
+        .or_else(|| env::var(name).ok())
+}
+
+/// 需要注入本应用启动的 Claude 进程的代理变量 (来自当前激活的代理商)。
+/// 用户的系统 HTTP(S)_PROXY 从不被修改，切换或清理代理商后自然恢复生效
+pub(crate) fn active_provider_proxy_env() -> Vec<(&'static str, String)> {
+    let providers = match load_providers_from_file() {
+        Ok(providers) => providers,
+        Err(e) => {
+            log::warn!("读取代理商配置失败，启动的 Claude 进程将使用系统代理: {}", e);
+            return Vec::new();
+        }
+    };
+    match get_current_provider_config() {
+        Ok(current) => active_proxy_env_for(&providers, &current),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// 生成设置指定代理商环境变量的脚本内容 (format: sh / bat / ps1)。
+/// include_secrets 默认为 false，此时密钥以打码形式写出
 #[command]
-pub async fn switch_provider_config(app: tauri::AppHandle, config: ProviderConfig) -> Result<String, String> {
+pub fn export_provider_env_script(
+    id: String,
+    format: String,
+    include_secrets: Option<bool>,
+) -> Result<String, CommandError> {
+    let format = format.trim().to_lowercase();
+    if !["sh", "bat", "ps1"].contains(&format.as_str()) {
+        return Err(CommandError::Validation(format!(
+            "不支持的脚本格式 '{}'，可选 sh、bat、ps1",
+            format
+        )));
+    }
+    
+    let provider = load_providers_from_file()?
+        .into_iter()
+        .find(|p| p.id == id)
+        .ok_or_else(|| CommandError::NotFound(format!("未找到ID为 '{}' 的配置", id)))?;
+    
+    Ok(provider_env_script(&provider, &format, include_secrets.unwrap_or(false)))
+}
+
+fn provider_env_script(provider: &ProviderConfig, format: &str, include_secrets: bool) -> String {
+    let mut lines: Vec<String> = match format {
+        "sh" => vec!["#!/bin/sh".to_string()],
+        "bat" => vec!["@echo off".to_string()],
+        _ => vec![],
+    };
+    let comment = if format == "bat" { "REM" } else { "#" };
+    // 名称中的换行等控制字符会结束注释行，使后续内容作为命令执行，统一替换为空格
+    let name: String = provider.name.chars().map(|c| if c.is_control() { ' ' } else { c }).collect();
+    lines.push(format!("{} Claude Code provider: {}", comment, name));
+    
+    for (name, value, secret) in provider_env_vars(provider) {
+        let value = if secret && !include_secrets { mask_secret(&value) } else { value };
+        lines.push(match format {
+            // 单引号内不做任何展开，单引号本身需要拆开转义
+            "sh" => format!("export {}='{}'", name, value.replace('\'', "'\\''")),
+            // 引号包住整个赋值以免特殊字符被解释，% 需要写成 %%
+            "bat" => format!("set \"{}={}\"", name, value.replace('%', "%%")),
+            _ => format!("$env:{} = '{}'", name, value.replace('\'', "''")),
+        });
+    }
+    
+    let newline = if format == "sh" { "\n" } else { "\r\n" };
+    lines.join(newline) + newline
+}
+
+#[command]
+pub async fn switch_provider_config(app: tauri::AppHandle, config: ProviderConfig) -> Result<String, CommandError> {
     // 首先清理现有环境变量 (但不重启，因为我们马上要设置新的)
     clear_env_vars_only()?;
     
@@ -177,14 +709,45 @@ pub async fn switch_provider_config(app: tauri::AppHandle, config: ProviderConfi
         set_env_var("ANTHROPIC_MODEL", model)?;
     }
     
+    if let Some(small_fast_model) = config.small_fast_model.as_deref().filter(|m| !m.trim().is_empty()) {
+        set_env_var("ANTHROPIC_SMALL_FAST_MODEL", small_fast_model)?;
+    }
+    
+    // 代理商指定的代理不写入系统环境变量，由 active_provider_proxy_env 在启动 Claude 进程时注入
+    
     // 终止所有运行中的Claude进程以使新环境变量生效
     terminate_claude_processes(&app).await;
     
     Ok(format!("已成功切换到 {} ({})，所有Claude会话已重启以应用新配置", config.name, config.description))
 }
 
+/// 仅切换代理商的默认模型，若该代理商当前处于激活状态则同步更新 ANTHROPIC_MODEL
+#[command]
+pub fn switch_provider_model(id: String, model: String) -> Result<String, CommandError> {
+    if model.trim().is_empty() {
+        return Err(CommandError::Validation("模型名称不能为空".to_string()));
+    }
+
+    let _guard = lock_providers()?;
+    let mut providers = load_providers_from_file()?;
+
+    let index = providers.iter().position(|p| p.id == id)
+        .ok_or_else(|| CommandError::NotFound(format!("未找到ID为 '{}' 的配置", id)))?;
+
+    providers[index].model = Some(model.clone());
+    save_providers_to_file(&providers)?;
+
+    let provider = &providers[index];
+    if is_provider_active(provider, &get_current_provider_config()?) {
+        set_env_var("ANTHROPIC_MODEL", &model)?;
+        Ok(format!("已将 {} 的模型切换为 {} 并已应用", provider.name, model))
+    } else {
+        Ok(format!("已将 {} 的模型更新为 {}", provider.name, model))
+    }
+}
+
 #[command]
-pub async fn clear_provider_config(app: tauri::AppHandle) -> Result<String, String> {
+pub async fn clear_provider_config(app: tauri::AppHandle) -> Result<String, CommandError> {
     // 使用统一的清理函数
     clear_anthropic_env_vars()?;
     
@@ -199,21 +762,25 @@ fn clear_env_vars_only() -> Result<(), String> {
     clear_anthropic_env_vars()
 }
 
-/// 清理 ANTHROPIC 相关环境变量 - 参考批处理文件的完整清理流程
-#[cfg(target_os = "windows")]
+/// 清理 ANTHROPIC 相关环境变量
 fn clear_anthropic_env_vars() -> Result<(), String> {
-    use std::os::windows::process::CommandExt;
-    
-    let vars_to_clear = vec![
+    clear_env_vars(&[
         "ANTHROPIC_API_KEY",
         "ANTHROPIC_AUTH_TOKEN", 
         "ANTHROPIC_BASE_URL",
-        "ANTHROPIC_MODEL"
-    ];
+        "ANTHROPIC_MODEL",
+        "ANTHROPIC_SMALL_FAST_MODEL"
+    ])
+}
+
+/// 清理指定的环境变量 - 参考批处理文件的完整清理流程
+#[cfg(target_os = "windows")]
+fn clear_env_vars(vars_to_clear: &[&str]) -> Result<(), String> {
+    use std::os::windows::process::CommandExt;
     
-    log::info!("开始清理 ANTHROPIC 环境变量...");
+    log::info!("开始清理环境变量: {:?}", vars_to_clear);
     
-    for var_name in &vars_to_clear {
+    for var_name in vars_to_clear {
         log::info!("清理环境变量: {}", var_name);
         
         // 1. 使用 setx 设置为空值 (持久化清理) - 参考批处理文件
@@ -298,20 +865,13 @@ fn clear_anthropic_env_vars() -> Result<(), String> {
         }
     }
     
-    log::info!("ANTHROPIC 环境变量清理完成");
+    log::info!("环境变量清理完成");
     Ok(())
 }
 
 #[cfg(not(target_os = "windows"))]
-fn clear_anthropic_env_vars() -> Result<(), String> {
-    let vars_to_clear = vec![
-        "ANTHROPIC_API_KEY",
-        "ANTHROPIC_AUTH_TOKEN", 
-        "ANTHROPIC_BASE_URL",
-        "ANTHROPIC_MODEL"
-    ];
-    
-    for var_name in &vars_to_clear {
+fn clear_env_vars(vars_to_clear: &[&str]) -> Result<(), String> {
+    for var_name in vars_to_clear {
         env::remove_var(var_name);
     }
     
@@ -390,18 +950,314 @@ fn set_env_var(name: &str, value: &str) -> Result<(), String> {
     Ok(())
 }
 
+/// 环境变量在当前进程与持久化位置中的取值 (密钥已打码)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EnvVerification {
+    pub name: String,
+    pub process_value: Option<String>,
+    /// Windows 下为 HKCU\Environment 中的值；其他系统不做持久化，始终为 None
+    pub persisted_value: Option<String>,
+    /// 当前平台是否支持读取持久化的值
+    pub persistence_supported: bool,
+    /// 进程值与持久化值是否一致 (不支持持久化时为 true)
+    pub consistent: bool,
+}
+
+// 从 `reg query HKCU\Environment /v <name>` 的输出中取出变量值
+fn parse_reg_query_value(output: &str, name: &str) -> Option<String> {
+    output.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        if !parts.next()?.eq_ignore_ascii_case(name) {
+            return None;
+        }
+        if !parts.next()?.starts_with("REG_") {
+            return None;
+        }
+        let value = parts.collect::<Vec<_>>().join(" ");
+        Some(value).filter(|v| !v.is_empty())
+    })
+}
+
+#[cfg(target_os = "windows")]
+fn read_persisted_env_var(name: &str) -> Result<Option<String>, String> {
+    use std::os::windows::process::CommandExt;
+    
+    let output = Command::new("reg")
+        .args(&["query", "HKCU\\Environment", "/v", name])
+        .creation_flags(0x08000000) // CREATE_NO_WINDOW
+        .output()
+        .map_err(|e| format!("执行 reg query 失败: {}", e))?;
+    
+    // 变量不存在时 reg query 返回非零退出码
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(parse_reg_query_value(&String::from_utf8_lossy(&output.stdout), name))
+}
+
+#[cfg(not(target_os = "windows"))]
+fn read_persisted_env_var(_name: &str) -> Result<Option<String>, String> {
+    // 非 Windows 系统的 set_env_var 只设置当前进程，没有持久化位置可读
+    Ok(None)
+}
+
+/// 读取环境变量在当前进程和持久化位置 (Windows 注册表) 中的值，用于确认切换代理商后是否真正生效
 #[command]
-pub fn test_provider_connection(base_url: String) -> Result<String, String> {
-    // 简单的连接测试 - 尝试访问 API 端点
-    let test_url = if base_url.ends_with('/') {
+pub fn verify_env_persistence(name: String) -> Result<EnvVerification, CommandError> {
+    let name = name.trim().to_string();
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(CommandError::Validation(format!("无效的环境变量名: '{}'", name)));
+    }
+    
+    let process_value = env::var(&name).ok().filter(|v| !v.is_empty());
+    let persisted_value = read_persisted_env_var(&name)?;
+    let persistence_supported = cfg!(target_os = "windows");
+    let consistent = !persistence_supported || process_value == persisted_value;
+    
+    let upper = name.to_uppercase();
+    let secret = upper.contains("KEY") || upper.contains("TOKEN");
+    let mask = |value: Option<String>| if secret { value.map(|v| mask_secret(&v)) } else { value };
+    
+    Ok(EnvVerification {
+        process_value: mask(process_value),
+        persisted_value: mask(persisted_value),
+        name,
+        persistence_supported,
+        consistent,
+    })
+}
+
+// 构造连接测试使用的 API 端点
+fn build_test_url(base_url: &str) -> String {
+    if base_url.ends_with('/') {
         format!("{}v1/messages", base_url)
     } else {
         format!("{}/v1/messages", base_url)
+    }
+}
+
+// 创建带超时设置的 HTTP 客户端，避免单个失效端点拖住整个测试。
+// 指定 http_proxy 时优先走该代理，未指定时沿用系统 HTTP(S)_PROXY 环境变量
+fn build_test_client(http_proxy: Option<&str>, no_proxy: Option<&str>) -> Result<reqwest::Client, String> {
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(PROVIDER_TEST_CONNECT_TIMEOUT_SECS))
+        .timeout(Duration::from_secs(PROVIDER_TEST_TIMEOUT_SECS));
+    
+    if let Some(proxy_url) = http_proxy.map(str::trim).filter(|p| !p.is_empty()) {
+        let proxy = reqwest::Proxy::all(proxy_url)
+            .map_err(|e| format!("代理地址无效 '{}': {}", proxy_url, e))?
+            .no_proxy(no_proxy.and_then(reqwest::NoProxy::from_string));
+        builder = builder.proxy(proxy);
+    }
+    
+    builder.build()
+        .map_err(|e| format!("创建 HTTP 客户端失败: {}", e))
+}
+
+// 对代理商端点发起一次真实的 HTTP 请求，只要收到 HTTP 响应即视为可达。
+// 指定 model 时发送 1 token 的补全请求以确认模型可用，否则仅发送轻量的 GET 请求
+async fn probe_provider(
+    client: &reqwest::Client,
+    base_url: &str,
+    auth_token: Option<&str>,
+    api_key: Option<&str>,
+    model: Option<&str>,
+) -> Result<(u16, u64, Option<String>), String> {
+    let test_url = build_test_url(base_url);
+    
+    let mut request = match model {
+        Some(model) => client.post(&test_url).json(&serde_json::json!({
+            "model": model,
+            "max_tokens": 1,
+            "messages": [{ "role": "user", "content": "ping" }],
+        })),
+        None => client.get(&test_url),
     };
+    request = request
+        .header("anthropic-version", "2023-06-01")
+        .header("User-Agent", "Claude-Workbench-App");
+    if let Some(token) = auth_token.filter(|t| !t.is_empty()) {
+        request = request.bearer_auth(token);
+    }
+    if let Some(key) = api_key.filter(|k| !k.is_empty()) {
+        request = request.header("x-api-key", key);
+    }
+    
+    let started = Instant::now();
+    let response = request.send().await.map_err(|e| {
+        if e.is_timeout() {
+            format!("请求超时 ({} 秒): {}", PROVIDER_TEST_TIMEOUT_SECS, test_url)
+        } else {
+            format!("无法连接到 {}: {}", test_url, e)
+        }
+    })?;
+    let latency_ms = started.elapsed().as_millis() as u64;
+    let status_code = response.status().as_u16();
+    
+    // 模型校验失败时保留响应内容，便于定位网关返回的具体原因
+    let body = if model.is_some() && !response.status().is_success() {
+        response.text().await.ok().map(|text| text.chars().take(300).collect())
+    } else {
+        None
+    };
+    
+    Ok((status_code, latency_ms, body))
+}
+
+// 将一次探测结果整理为测试结果
+fn build_test_result(
+    provider: &ProviderConfig,
+    probe: Result<(u16, u64, Option<String>), String>,
+    check_model: bool,
+) -> ProviderTestResult {
+    let (status_code, latency_ms, body) = match probe {
+        Ok(probe) => probe,
+        Err(e) => {
+            return ProviderTestResult {
+                provider_id: provider.id.clone(),
+                provider_name: provider.name.clone(),
+                base_url: provider.base_url.clone(),
+                reachable: false,
+                status: ProviderTestStatus::Unreachable,
+                status_code: None,
+                latency_ms: None,
+                error: Some(e),
+                model_accepted: None,
+            };
+        }
+    };
+    
+    let (status, error) = match status_code {
+        429 => (ProviderTestStatus::Throttled, Some("已限流 (HTTP 429)".to_string())),
+        401 | 403 => (ProviderTestStatus::AuthFailed, Some(format!("认证失败 (HTTP {})", status_code))),
+        code if code >= 500 => (ProviderTestStatus::Error, Some(format!("服务端错误 (HTTP {})", code))),
+        code if check_model && !(200..300).contains(&code) => (
+            ProviderTestStatus::Error,
+            Some(format!("模型请求被拒绝 (HTTP {}): {}", code, body.unwrap_or_default())),
+        ),
+        // 仅测试可达性时 GET /v1/messages 返回 404/405 等属于正常现象
+        _ => (ProviderTestStatus::Ok, None),
+    };
+    
+    // 限流和认证失败时无法判断模型本身是否可用
+    let model_accepted = if !check_model {
+        None
+    } else {
+        match status {
+            ProviderTestStatus::Ok => Some(true),
+            ProviderTestStatus::Error => Some(false),
+            _ => None,
+        }
+    };
+    
+    ProviderTestResult {
+        provider_id: provider.id.clone(),
+        provider_name: provider.name.clone(),
+        base_url: provider.base_url.clone(),
+        reachable: true,
+        status,
+        status_code: Some(status_code),
+        latency_ms: Some(latency_ms),
+        error,
+        model_accepted,
+    }
+}
+
+// 使用代理商自身的认证与代理设置进行一次测试
+async fn test_provider(provider: &ProviderConfig, check_model: bool) -> ProviderTestResult {
+    let model = if check_model { provider.model.as_deref() } else { None };
+    
+    // 每个代理商可能配置了不同的 HTTP 代理，因此分别创建客户端
+    let probe = match build_test_client(provider.http_proxy.as_deref(), provider.no_proxy.as_deref()) {
+        Ok(client) => probe_provider(
+            &client,
+            &provider.base_url,
+            provider.auth_token.as_deref(),
+            provider.api_key.as_deref(),
+            model,
+        ).await,
+        Err(e) => Err(e),
+    };
+    
+    let result = build_test_result(provider, probe, check_model);
+    
+    if let Ok(mut cache) = LAST_TEST_RESULTS.lock() {
+        cache
+            .get_or_insert_with(HashMap::new)
+            .insert(provider.id.clone(), (result.clone(), chrono::Local::now().to_rfc3339()));
+    }
     
-    // 这里可以实现实际的 HTTP 请求测试
-    // 目前返回一个简单的成功消息
-    Ok(format!("连接测试完成：{}", test_url))
+    result
+}
+
+/// 测试代理商连通性；check_model 为 true 时额外发送 1 token 请求确认 config.model 可用
+#[command]
+pub async fn test_provider_connection(config: ProviderConfig, check_model: Option<bool>) -> Result<ProviderTestResult, CommandError> {
+    let check_model = check_model.unwrap_or(false);
+    
+    if check_model && config.model.as_deref().is_none_or(|m| m.trim().is_empty()) {
+        return Err(CommandError::Validation("未配置模型，无法校验模型可用性".to_string()));
+    }
+    
+    Ok(test_provider(&config, check_model).await)
+}
+
+/// 并发测试所有已保存代理商的连通性
+#[command]
+pub async fn test_all_providers() -> Result<Vec<ProviderTestResult>, CommandError> {
+    let providers = load_providers_from_file()?;
+    
+    log::info!("开始测试 {} 个代理商的连通性", providers.len());
+    
+    let results = stream::iter(providers)
+        .map(|provider| async move { test_provider(&provider, false).await })
+        .buffered(PROVIDER_TEST_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+    
+    Ok(results)
+}
+
+/// 切换代理商时将被终止的 Claude 会话
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionToTerminate {
+    pub run_id: i64,
+    pub pid: u32,
+    pub session_id: String,
+    pub project_path: String,
+    pub model: String,
+    pub started_at: String,
+}
+
+// 注册表中 Claude 会话的 session_id
+fn claude_session_id(session: &ProcessInfo) -> &str {
+    match &session.process_type {
+        ProcessType::ClaudeSession { session_id } => session_id.as_str(),
+        _ => "unknown",
+    }
+}
+
+/// 预览 switch_provider_config 将终止的 Claude 会话 (与 terminate_claude_processes 使用同一查询)，不终止任何进程
+#[command]
+pub fn preview_terminate_claude_processes(app: AppHandle) -> Result<Vec<SessionToTerminate>, CommandError> {
+    let registry = app.state::<ProcessRegistryState>();
+    let mut sessions: Vec<SessionToTerminate> = registry
+        .0
+        .get_running_claude_sessions()
+        .map_err(|e| CommandError::Other(format!("获取Claude会话列表失败: {}", e)))?
+        .iter()
+        .map(|session| SessionToTerminate {
+            run_id: session.run_id,
+            pid: session.pid,
+            session_id: claude_session_id(session).to_string(),
+            project_path: session.project_path.clone(),
+            model: session.model.clone(),
+            started_at: session.started_at.to_rfc3339(),
+        })
+        .collect();
+    sessions.sort_by_key(|s| s.run_id);
+
+    Ok(sessions)
 }
 
 /// 终止所有运行中的Claude进程以使新环境变量生效
@@ -417,10 +1273,7 @@ async fn terminate_claude_processes(app: &AppHandle) {
             log::info!("找到 {} 个活动的Claude会话", sessions.len());
             
             for session in sessions {
-                let session_id_str = match &session.process_type {
-                    crate::process::registry::ProcessType::ClaudeSession { session_id } => session_id.as_str(),
-                    _ => "unknown",
-                };
+                let session_id_str = claude_session_id(&session);
                 
                 log::info!("正在终止Claude会话: session_id={}, run_id={}, PID={}", 
                     session_id_str,
@@ -459,4 +1312,79 @@ async fn terminate_claude_processes(app: &AppHandle) {
     }
     
     log::info!("Claude进程终止操作完成");
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider(id: &str, base_url: &str, http_proxy: Option<&str>) -> ProviderConfig {
+        ProviderConfig {
+            id: id.to_string(),
+            name: id.to_string(),
+            description: String::new(),
+            base_url: base_url.to_string(),
+            auth_token: Some(format!("{}-token", id)),
+            api_key: None,
+            model: None,
+            small_fast_model: None,
+            http_proxy: http_proxy.map(str::to_string),
+            no_proxy: Some("localhost".to_string()),
+            pricing_override: None,
+        }
+    }
+
+    fn current(base_url: Option<&str>, auth_token: Option<&str>) -> CurrentConfig {
+        CurrentConfig {
+            anthropic_base_url: base_url.map(str::to_string),
+            anthropic_auth_token: auth_token.map(str::to_string),
+            anthropic_api_key: None,
+            anthropic_model: None,
+        }
+    }
+
+    #[test]
+    fn test_proxy_env_restores_system_proxy_after_switching_away() {
+        let providers = vec![
+            provider("proxied", "https://proxied.example.com", Some("http://proxy.corp:8080")),
+            provider("direct", "https://direct.example.com", None),
+        ];
+
+        let active = active_proxy_env_for(&providers, &current(Some("https://PROXIED.example.com/"), Some("proxied-token")));
+        assert_eq!(
+            active,
+            vec![
+                ("HTTP_PROXY", "http://proxy.corp:8080".to_string()),
+                ("HTTPS_PROXY", "http://proxy.corp:8080".to_string()),
+                ("NO_PROXY", "localhost".to_string()),
+            ]
+        );
+        assert_eq!(effective_proxy_value(&active, "HTTPS_PROXY").as_deref(), Some("http://proxy.corp:8080"));
+
+        // Switching to a provider without a proxy, or clearing the provider, injects
+        // nothing, so spawned processes see the user's own proxy again
+        for current in [
+            current(Some("https://direct.example.com"), Some("direct-token")),
+            current(None, None),
+        ] {
+            let vars = active_proxy_env_for(&providers, &current);
+            assert!(vars.is_empty());
+            for name in PROXY_ENV_VARS {
+                assert_eq!(effective_proxy_value(&vars, name), env::var(name).ok());
+            }
+        }
+    }
+
+    #[test]
+    fn test_env_script_name_cannot_break_out_of_comment() {
+        let mut config = provider("injected", "https://api.example.com", None);
+        config.name = "evil\nrm -rf ~\r\ncalc.exe".to_string();
+
+        for format in ["sh", "bat", "ps1"] {
+            let script = provider_env_script(&config, format, false);
+            let comment = script.lines().find(|line| line.contains("Claude Code provider")).unwrap();
+            assert!(comment.contains("evil rm -rf ~  calc.exe"), "{}: {}", format, comment);
+            assert!(!script.lines().any(|line| line.starts_with("rm ") || line.starts_with("calc")));
+        }
+    }
+}
@@ -1,14 +1,20 @@
 use anyhow::{Context, Result};
-use dirs;
-use log::{debug, error, info};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use log::{debug, error, info, warn};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
+use std::io::BufRead;
 use std::path::{Path, PathBuf};
+use crate::commands::error::CommandError;
+use crate::commands::usage::{get_claude_config_dir, is_usage_log, open_usage_log};
 
 /// Represents a custom slash command
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SlashCommand {
-    /// Unique identifier for the command (derived from file path)
+    /// Unique identifier for the command: scope plus URL-safe base64 of the
+    /// path relative to the commands directory (see `encode_command_id`)
     pub id: String,
     /// Command name (without prefix)
     pub name: String,
@@ -32,6 +38,30 @@ pub struct SlashCommand {
     pub has_file_references: bool,
     /// Whether the command uses $ARGUMENTS placeholder
     pub accepts_arguments: bool,
+    /// Non-fatal problems, e.g. unrecognized `allowed-tools` entries
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+/// A `@path` reference found in a command body
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileReferencePreview {
+    /// The reference as written (without the leading `@`)
+    pub reference: String,
+    /// Absolute path the reference resolves to, if it could be resolved
+    pub resolved_path: Option<String>,
+    /// Whether the resolved path exists
+    pub exists: bool,
+}
+
+/// What a command's bash and file-reference directives would pull in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExpansionPreview {
+    pub command_id: String,
+    /// Commands from `!`cmd`` directives (never executed by the preview)
+    pub bash_commands: Vec<String>,
+    /// Files from `@path` directives
+    pub file_references: Vec<FileReferencePreview>,
 }
 
 /// YAML frontmatter structure
@@ -81,6 +111,72 @@ fn parse_markdown_with_frontmatter(content: &str) -> Result<(Option<CommandFront
     }
 }
 
+/// Convert a path relative to a commands directory to '/'-separated form.
+/// Both '/' and '\\' count as separators so the result is the same on every platform.
+fn normalize_relative_path(relative_path: &Path) -> String {
+    relative_path.to_string_lossy().replace('\\', "/")
+}
+
+/// Build a command ID from its scope and relative path, e.g. `project-ZnJvbnRlbmQvYnV0dG9uLm1k`.
+/// Unlike the raw file path, the encoded form survives separators and non-ASCII names unchanged.
+fn encode_command_id(scope: &str, relative_path: &str) -> String {
+    format!("{}-{}", scope, URL_SAFE_NO_PAD.encode(relative_path.replace('\\', "/")))
+}
+
+/// Split a command ID back into its scope and relative path components
+fn decode_command_id(command_id: &str) -> Result<(String, Vec<String>)> {
+    let (scope, encoded) = command_id
+        .split_once('-')
+        .ok_or_else(|| anyhow::anyhow!("Invalid command ID: {}", command_id))?;
+    
+    if scope != "project" && scope != "user" {
+        return Err(anyhow::anyhow!("Invalid command scope in ID: {}", command_id));
+    }
+    
+    let decoded = URL_SAFE_NO_PAD
+        .decode(encoded)
+        .context("Invalid command ID encoding")?;
+    let relative_path = String::from_utf8(decoded).context("Invalid command ID encoding")?;
+    
+    let components: Vec<String> = relative_path.split('/').map(str::to_string).collect();
+    
+    // Never let an ID point outside its commands directory
+    if components.iter().any(|c| c.is_empty() || c == "." || c == "..") {
+        return Err(anyhow::anyhow!("Invalid command path in ID: {}", command_id));
+    }
+    
+    Ok((scope.to_string(), components))
+}
+
+/// Resolve the commands directory for a scope
+fn commands_base_dir(scope: &str, project_path: Option<&str>) -> Result<PathBuf, CommandError> {
+    if scope == "project" {
+        if let Some(proj_path) = project_path {
+            Ok(PathBuf::from(proj_path).join(".claude").join("commands"))
+        } else {
+            Err(CommandError::Validation("Project path required for project scope".to_string()))
+        }
+    } else {
+        Ok(get_claude_config_dir()?.join("commands"))
+    }
+}
+
+/// Resolve a command ID to its scope, commands directory and markdown file
+fn resolve_command_id(command_id: &str, project_path: Option<&str>) -> Result<(String, PathBuf, PathBuf), CommandError> {
+    let (scope, components) = decode_command_id(command_id).map_err(|e| CommandError::Validation(e.to_string()))?;
+    
+    if scope == "project" && project_path.is_none() {
+        return Err(CommandError::Validation("Project path required for project commands".to_string()));
+    }
+    
+    let base_dir = commands_base_dir(&scope, project_path)?;
+    let file_path = components
+        .iter()
+        .fold(base_dir.clone(), |path, component| path.join(component));
+    
+    Ok((scope, base_dir, file_path))
+}
+
 /// Extract command name and namespace from file path
 fn extract_command_info(file_path: &Path, base_path: &Path) -> Result<(String, Option<String>)> {
     let relative_path = file_path
@@ -88,10 +184,7 @@ fn extract_command_info(file_path: &Path, base_path: &Path) -> Result<(String, O
         .context("Failed to get relative path")?;
     
     // Remove .md extension
-    let path_without_ext = relative_path
-        .with_extension("")
-        .to_string_lossy()
-        .to_string();
+    let path_without_ext = normalize_relative_path(&relative_path.with_extension(""));
     
     // Split into components
     let components: Vec<&str> = path_without_ext.split('/').collect();
@@ -136,7 +229,10 @@ fn load_command_from_file(
     };
     
     // Generate unique ID
-    let id = format!("{}-{}", scope, file_path.to_string_lossy().replace('/', "-"));
+    let relative_path = file_path
+        .strip_prefix(base_path)
+        .context("Failed to get relative path")?;
+    let id = encode_command_id(scope, &normalize_relative_path(relative_path));
     
     // Check for special content
     let has_bash_commands = body.contains("!`");
@@ -149,6 +245,7 @@ fn load_command_from_file(
     } else {
         (None, Vec::new())
     };
+    let warnings = allowed_tools_warnings(&allowed_tools);
     
     Ok(SlashCommand {
         id,
@@ -163,9 +260,31 @@ fn load_command_from_file(
         has_bash_commands,
         has_file_references,
         accepts_arguments,
+        warnings,
     })
 }
 
+/// Built-in Claude Code tool names accepted in `allowed-tools`
+const KNOWN_TOOLS: &[&str] = &[
+    "Bash", "BashOutput", "Edit", "ExitPlanMode", "Glob", "Grep", "KillBash", "KillShell",
+    "LS", "MultiEdit", "NotebookEdit", "NotebookRead", "Read", "SlashCommand", "Task",
+    "TodoRead", "TodoWrite", "WebFetch", "WebSearch", "Write",
+];
+
+/// Warn about `allowed-tools` entries that don't name a known tool. Rule specifiers
+/// such as `Bash(git add:*)` are checked by their tool name and MCP tools (`mcp__*`)
+/// are always accepted. Only warns, since the tool set keeps growing.
+fn allowed_tools_warnings(allowed_tools: &[String]) -> Vec<String> {
+    allowed_tools
+        .iter()
+        .filter(|entry| {
+            let tool = entry.split('(').next().unwrap_or("").trim();
+            !tool.starts_with("mcp__") && !KNOWN_TOOLS.contains(&tool)
+        })
+        .map(|entry| format!("Unrecognized tool in allowed-tools: {}", entry))
+        .collect()
+}
+
 /// Recursively find all markdown files in a directory
 fn find_markdown_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
     if !dir.exists() {
@@ -214,6 +333,7 @@ fn create_default_commands() -> Vec<SlashCommand> {
             has_bash_commands: false,
             has_file_references: false,
             accepts_arguments: false,
+            warnings: vec![],
         },
         // 管理专门任务的自定义AI子代理
         SlashCommand {
@@ -229,6 +349,7 @@ fn create_default_commands() -> Vec<SlashCommand> {
             has_bash_commands: false,
             has_file_references: false,
             accepts_arguments: false,
+            warnings: vec![],
         },
         // 报告错误（发送对话给Anthropic）
         SlashCommand {
@@ -244,6 +365,7 @@ fn create_default_commands() -> Vec<SlashCommand> {
             has_bash_commands: false,
             has_file_references: false,
             accepts_arguments: false,
+            warnings: vec![],
         },
         // 清除对话历史
         SlashCommand {
@@ -259,6 +381,7 @@ fn create_default_commands() -> Vec<SlashCommand> {
             has_bash_commands: false,
             has_file_references: false,
             accepts_arguments: false,
+            warnings: vec![],
         },
         // 压缩对话内容以节省令牌
         SlashCommand {
@@ -274,6 +397,7 @@ fn create_default_commands() -> Vec<SlashCommand> {
             has_bash_commands: false,
             has_file_references: false,
             accepts_arguments: false,
+            warnings: vec![],
         },
         // 查看/修改配置
         SlashCommand {
@@ -289,6 +413,7 @@ fn create_default_commands() -> Vec<SlashCommand> {
             has_bash_commands: false,
             has_file_references: false,
             accepts_arguments: false,
+            warnings: vec![],
         },
         // 显示令牌使用统计
         SlashCommand {
@@ -304,6 +429,7 @@ fn create_default_commands() -> Vec<SlashCommand> {
             has_bash_commands: false,
             has_file_references: false,
             accepts_arguments: false,
+            warnings: vec![],
         },
         // 检查Claude Code安装的健康状态
         SlashCommand {
@@ -319,6 +445,7 @@ fn create_default_commands() -> Vec<SlashCommand> {
             has_bash_commands: false,
             has_file_references: false,
             accepts_arguments: false,
+            warnings: vec![],
         },
         // 获取使用帮助
         SlashCommand {
@@ -334,6 +461,7 @@ fn create_default_commands() -> Vec<SlashCommand> {
             has_bash_commands: false,
             has_file_references: false,
             accepts_arguments: false,
+            warnings: vec![],
         },
         // 使用CLAUDE.md指南初始化项目
         SlashCommand {
@@ -349,6 +477,7 @@ fn create_default_commands() -> Vec<SlashCommand> {
             has_bash_commands: false,
             has_file_references: false,
             accepts_arguments: false,
+            warnings: vec![],
         },
         // 切换Anthropic账户
         SlashCommand {
@@ -364,6 +493,7 @@ fn create_default_commands() -> Vec<SlashCommand> {
             has_bash_commands: false,
             has_file_references: false,
             accepts_arguments: false,
+            warnings: vec![],
         },
         // 退出Anthropic账户
         SlashCommand {
@@ -379,6 +509,7 @@ fn create_default_commands() -> Vec<SlashCommand> {
             has_bash_commands: false,
             has_file_references: false,
             accepts_arguments: false,
+            warnings: vec![],
         },
         // 管理MCP服务器连接和OAuth认证
         SlashCommand {
@@ -394,6 +525,7 @@ fn create_default_commands() -> Vec<SlashCommand> {
             has_bash_commands: false,
             has_file_references: false,
             accepts_arguments: false,
+            warnings: vec![],
         },
         // 编辑CLAUDE.md记忆文件
         SlashCommand {
@@ -409,6 +541,7 @@ fn create_default_commands() -> Vec<SlashCommand> {
             has_bash_commands: false,
             has_file_references: false,
             accepts_arguments: false,
+            warnings: vec![],
         },
         // 选择或更改AI模型
         SlashCommand {
@@ -424,6 +557,7 @@ fn create_default_commands() -> Vec<SlashCommand> {
             has_bash_commands: false,
             has_file_references: false,
             accepts_arguments: false,
+            warnings: vec![],
         },
         // 查看或更新权限
         SlashCommand {
@@ -439,6 +573,7 @@ fn create_default_commands() -> Vec<SlashCommand> {
             has_bash_commands: false,
             has_file_references: false,
             accepts_arguments: false,
+            warnings: vec![],
         },
         // 查看拉取请求评论
         SlashCommand {
@@ -454,6 +589,7 @@ fn create_default_commands() -> Vec<SlashCommand> {
             has_bash_commands: false,
             has_file_references: false,
             accepts_arguments: false,
+            warnings: vec![],
         },
         // 请求代码审查
         SlashCommand {
@@ -469,6 +605,7 @@ fn create_default_commands() -> Vec<SlashCommand> {
             has_bash_commands: false,
             has_file_references: false,
             accepts_arguments: false,
+            warnings: vec![],
         },
         // 查看账户和系统状态
         SlashCommand {
@@ -484,6 +621,7 @@ fn create_default_commands() -> Vec<SlashCommand> {
             has_bash_commands: false,
             has_file_references: false,
             accepts_arguments: false,
+            warnings: vec![],
         },
         // 安装Shift+Enter键绑定用于换行
         SlashCommand {
@@ -499,6 +637,7 @@ fn create_default_commands() -> Vec<SlashCommand> {
             has_bash_commands: false,
             has_file_references: false,
             accepts_arguments: false,
+            warnings: vec![],
         },
         // 进入vim模式，交替使用插入和命令模式
         SlashCommand {
@@ -514,15 +653,103 @@ fn create_default_commands() -> Vec<SlashCommand> {
             has_bash_commands: false,
             has_file_references: false,
             accepts_arguments: false,
+            warnings: vec![],
         },
     ]
 }
 
-/// Discover all custom slash commands
+/// How often and how recently a command was invoked, taken from session transcripts
+#[derive(Debug, Default, Clone)]
+struct CommandInvocations {
+    count: u64,
+    /// RFC3339 timestamp of the latest invocation
+    last_used: Option<String>,
+}
+
+/// Collect invocation stats from the `<command-name>` markers Claude writes into
+/// session transcripts under ~/.claude/projects, keyed by full command (e.g. "/review").
+/// Returns an empty map when no transcripts exist.
+fn load_command_invocations() -> HashMap<String, CommandInvocations> {
+    let mut invocations: HashMap<String, CommandInvocations> = HashMap::new();
+    let projects_dir = match get_claude_config_dir() {
+        Ok(dir) => dir.join("projects"),
+        Err(_) => return invocations,
+    };
+    if !projects_dir.exists() {
+        return invocations;
+    }
+    
+    let marker_re = Regex::new(r"<command-name>\s*(/[^<\s]+)\s*</command-name>").expect("valid command marker regex");
+    
+    for entry in walkdir::WalkDir::new(&projects_dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|e| is_usage_log(e.path()))
+    {
+        let reader = match open_usage_log(entry.path()) {
+            Ok(reader) => reader,
+            Err(e) => {
+                debug!("Skipping transcript {:?}: {}", entry.path(), e);
+                continue;
+            }
+        };
+        
+        for line in reader.lines().map_while(Result::ok).filter(|l| l.contains("<command-name>")) {
+            let timestamp = serde_json::from_str::<serde_json::Value>(&line)
+                .ok()
+                .and_then(|v| v.get("timestamp").and_then(|t| t.as_str()).map(String::from));
+            for cap in marker_re.captures_iter(&line) {
+                let stats = invocations.entry(cap[1].to_string()).or_default();
+                stats.count += 1;
+                if timestamp.is_some() && timestamp > stats.last_used {
+                    stats.last_used = timestamp.clone();
+                }
+            }
+        }
+    }
+    
+    invocations
+}
+
+/// Sort commands by "name", "recent" or "frequency". Ties (and never-used
+/// commands) keep their discovery order.
+fn sort_commands(commands: &mut [SlashCommand], sort_by: &str) -> Result<(), CommandError> {
+    match sort_by {
+        "name" => {
+            commands.sort_by_key(|cmd| cmd.full_command.to_lowercase());
+        }
+        "recent" => {
+            let invocations = load_command_invocations();
+            commands.sort_by(|a, b| {
+                let a_last = invocations.get(&a.full_command).and_then(|i| i.last_used.as_ref());
+                let b_last = invocations.get(&b.full_command).and_then(|i| i.last_used.as_ref());
+                b_last.cmp(&a_last)
+            });
+        }
+        "frequency" => {
+            let invocations = load_command_invocations();
+            commands.sort_by_key(|cmd| {
+                std::cmp::Reverse(invocations.get(&cmd.full_command).map(|i| i.count).unwrap_or(0))
+            });
+        }
+        other => {
+            return Err(CommandError::Validation(format!(
+                "Invalid sort option '{}': expected 'name', 'recent' or 'frequency'",
+                other
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Discover all custom slash commands, optionally sorted and truncated to `limit`.
+/// With neither option set the default, project and user commands are returned in discovery order.
 #[tauri::command]
 pub async fn slash_commands_list(
     project_path: Option<String>,
-) -> Result<Vec<SlashCommand>, String> {
+    sort_by: Option<String>,
+    limit: Option<usize>,
+) -> Result<Vec<SlashCommand>, CommandError> {
     info!("Discovering slash commands");
     let mut commands = Vec::new();
     
@@ -555,8 +782,8 @@ pub async fn slash_commands_list(
     }
     
     // Load user commands
-    if let Some(home_dir) = dirs::home_dir() {
-        let user_commands_dir = home_dir.join(".claude").join("commands");
+    if let Ok(claude_dir) = get_claude_config_dir() {
+        let user_commands_dir = claude_dir.join("commands");
         if user_commands_dir.exists() {
             debug!("Scanning user commands at: {:?}", user_commands_dir);
             
@@ -580,28 +807,39 @@ pub async fn slash_commands_list(
     }
     
     info!("Found {} slash commands", commands.len());
+    
+    if let Some(sort_by) = sort_by {
+        sort_commands(&mut commands, &sort_by)?;
+    }
+    if let Some(limit) = limit {
+        commands.truncate(limit);
+    }
+    
     Ok(commands)
 }
 
 /// Get a single slash command by ID
 #[tauri::command]
-pub async fn slash_command_get(command_id: String) -> Result<SlashCommand, String> {
+pub async fn slash_command_get(command_id: String, project_path: Option<String>) -> Result<SlashCommand, CommandError> {
     debug!("Getting slash command: {}", command_id);
     
-    // Parse the ID to determine scope and reconstruct file path
-    let parts: Vec<&str> = command_id.split('-').collect();
-    if parts.len() < 2 {
-        return Err("Invalid command ID".to_string());
+    // Built-in commands have fixed IDs and no backing file
+    if command_id.starts_with("default-") {
+        return create_default_commands()
+            .into_iter()
+            .find(|cmd| cmd.id == command_id)
+            .ok_or_else(|| CommandError::NotFound(format!("Command not found: {}", command_id)));
     }
     
-    // The actual implementation would need to reconstruct the path and reload the command
-    // For now, we'll list all commands and find the matching one
-    let commands = slash_commands_list(None).await?;
+    // Decode the ID to reconstruct the file path and reload the command
+    let (scope, base_dir, file_path) = resolve_command_id(&command_id, project_path.as_deref())?;
     
-    commands
-        .into_iter()
-        .find(|cmd| cmd.id == command_id)
-        .ok_or_else(|| format!("Command not found: {}", command_id))
+    if !file_path.is_file() {
+        return Err(CommandError::NotFound(format!("Command not found: {}", command_id)));
+    }
+    
+    load_command_from_file(&file_path, &base_dir, &scope)
+        .map_err(|e| CommandError::FileRead(format!("Failed to load command: {}", e)))
 }
 
 /// Create or update a slash command
@@ -614,47 +852,28 @@ pub async fn slash_command_save(
     description: Option<String>,
     allowed_tools: Vec<String>,
     project_path: Option<String>,
-) -> Result<SlashCommand, String> {
+) -> Result<SlashCommand, CommandError> {
     info!("Saving slash command: {} in scope: {}", name, scope);
     
     // Validate inputs
     if name.is_empty() {
-        return Err("Command name cannot be empty".to_string());
+        return Err(CommandError::Validation("Command name cannot be empty".to_string()));
     }
     
     if !["project", "user"].contains(&scope.as_str()) {
-        return Err("Invalid scope. Must be 'project' or 'user'".to_string());
+        return Err(CommandError::Validation("Invalid scope. Must be 'project' or 'user'".to_string()));
     }
     
     // Determine base directory
-    let base_dir = if scope == "project" {
-        if let Some(proj_path) = project_path {
-            PathBuf::from(proj_path).join(".claude").join("commands")
-        } else {
-            return Err("Project path required for project scope".to_string());
-        }
-    } else {
-        dirs::home_dir()
-            .ok_or_else(|| "Could not find home directory".to_string())?
-            .join(".claude")
-            .join("commands")
-    };
+    let base_dir = commands_base_dir(&scope, project_path.as_deref())?;
     
-    // Build file path
-    let mut file_path = base_dir.clone();
-    if let Some(ns) = &namespace {
-        for component in ns.split(':') {
-            file_path = file_path.join(component);
-        }
+    // Build file path and create directories if needed
+    let file_path = command_file_path(&base_dir, namespace.as_deref(), &name)?;
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| CommandError::FileWrite(format!("Failed to create directories: {}", e)))?;
     }
     
-    // Create directories if needed
-    fs::create_dir_all(&file_path)
-        .map_err(|e| format!("Failed to create directories: {}", e))?;
-    
-    // Add filename
-    file_path = file_path.join(format!("{}.md", name));
-    
     // Build content with frontmatter
     let mut full_content = String::new();
     
@@ -678,66 +897,481 @@ pub async fn slash_command_save(
     
     full_content.push_str(&content);
     
+    // Keep the previous version as <name>.md.bak (only the latest backup is kept)
+    if file_path.is_file() {
+        fs::copy(&file_path, backup_path_for(&file_path))
+            .map_err(|e| CommandError::FileWrite(format!("Failed to back up existing command file: {}", e)))?;
+    }
+    
     // Write file
     fs::write(&file_path, &full_content)
-        .map_err(|e| format!("Failed to write command file: {}", e))?;
+        .map_err(|e| CommandError::FileWrite(format!("Failed to write command file: {}", e)))?;
+    
+    // Load and return the saved command; unrecognized allowed-tools show up in its warnings
+    let saved = load_command_from_file(&file_path, &base_dir, &scope)
+        .map_err(|e| CommandError::FileRead(format!("Failed to load saved command: {}", e)))?;
+    for warning in &saved.warnings {
+        warn!("{}: {}", saved.full_command, warning);
+    }
+    Ok(saved)
+}
+
+/// Markdown file for a command: namespace parts become directories under `base_dir`.
+/// Names and namespace parts must be single path components, so imported bundles
+/// can't write outside the commands directory.
+fn command_file_path(base_dir: &Path, namespace: Option<&str>, name: &str) -> Result<PathBuf, CommandError> {
+    let is_plain_component = |part: &str| {
+        !part.is_empty() && part != "." && part != ".." && !part.contains(['/', '\\'])
+    };
+    
+    let mut file_path = base_dir.to_path_buf();
+    if let Some(ns) = namespace {
+        for component in ns.split(':') {
+            if !is_plain_component(component) {
+                return Err(CommandError::Validation(format!("Invalid namespace: {}", ns)));
+            }
+            file_path = file_path.join(component);
+        }
+    }
+    if !is_plain_component(name) {
+        return Err(CommandError::Validation(format!("Invalid command name: {}", name)));
+    }
+    
+    Ok(file_path.join(format!("{}.md", name)))
+}
+
+/// Backup location used when a command file is overwritten
+fn backup_path_for(file_path: &Path) -> PathBuf {
+    let mut backup = file_path.as_os_str().to_os_string();
+    backup.push(".bak");
+    PathBuf::from(backup)
+}
+
+/// Swap a command file with its `.md.bak` backup, so a restore can itself be undone
+#[tauri::command]
+pub async fn restore_slash_command(command_id: String, project_path: Option<String>) -> Result<SlashCommand, CommandError> {
+    info!("Restoring slash command from backup: {}", command_id);
+    
+    let (scope, base_dir, file_path) = resolve_command_id(&command_id, project_path.as_deref())?;
+    let backup_path = backup_path_for(&file_path);
+    
+    if !backup_path.is_file() {
+        return Err(CommandError::NotFound(format!("No backup found for command: {}", command_id)));
+    }
+    
+    if file_path.is_file() {
+        let swap_path = file_path.with_extension("md.swap");
+        fs::rename(&file_path, &swap_path)
+            .map_err(|e| CommandError::FileWrite(format!("Failed to restore command: {}", e)))?;
+        fs::rename(&backup_path, &file_path)
+            .map_err(|e| CommandError::FileWrite(format!("Failed to restore command: {}", e)))?;
+        fs::rename(&swap_path, &backup_path)
+            .map_err(|e| CommandError::FileWrite(format!("Failed to restore command: {}", e)))?;
+    } else {
+        fs::rename(&backup_path, &file_path)
+            .map_err(|e| CommandError::FileWrite(format!("Failed to restore command: {}", e)))?;
+    }
     
-    // Load and return the saved command
     load_command_from_file(&file_path, &base_dir, &scope)
-        .map_err(|e| format!("Failed to load saved command: {}", e))
+        .map_err(|e| CommandError::FileRead(format!("Failed to load restored command: {}", e)))
+}
+
+/// Duplicate an existing slash command under a new name in the same scope and namespace
+#[tauri::command]
+pub async fn slash_command_duplicate(
+    command_id: String,
+    new_name: String,
+    project_path: Option<String>,
+) -> Result<SlashCommand, CommandError> {
+    info!("Duplicating slash command: {} as {}", command_id, new_name);
+    
+    if command_id.starts_with("default-") {
+        return Err(CommandError::Validation("Built-in commands have no file and cannot be duplicated".to_string()));
+    }
+    
+    let source = slash_command_get(command_id, project_path.clone()).await?;
+    
+    // Don't silently overwrite an existing command with the same name
+    let base_dir = commands_base_dir(&source.scope, project_path.as_deref())?;
+    let target_path = command_file_path(&base_dir, source.namespace.as_deref(), &new_name)?;
+    if target_path.exists() {
+        return Err(CommandError::Validation(format!("Command already exists: {}", new_name)));
+    }
+    
+    slash_command_save(
+        source.scope,
+        new_name,
+        source.namespace,
+        source.content,
+        source.description,
+        source.allowed_tools,
+        project_path,
+    )
+    .await
+}
+
+/// Current version of the slash command bundle format
+const BUNDLE_VERSION: u32 = 1;
+
+/// A shareable set of custom slash commands
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlashCommandBundle {
+    pub version: u32,
+    pub commands: Vec<BundledCommand>,
+}
+
+/// A command as stored in a bundle; scope is chosen again on import
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundledCommand {
+    pub name: String,
+    pub namespace: Option<String>,
+    pub content: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub allowed_tools: Vec<String>,
+}
+
+/// Outcome of importing a bundle, by full command name
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlashCommandImportResult {
+    pub imported: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+/// Write custom commands to a JSON bundle. `scope` selects "user" or "project"
+/// commands; without it, user commands plus project commands when `project_path`
+/// is given. Built-in commands are never exported. Returns the number exported.
+#[tauri::command]
+pub async fn export_slash_commands(
+    path: String,
+    scope: Option<String>,
+    project_path: Option<String>,
+) -> Result<usize, CommandError> {
+    if let Some(scope) = scope.as_deref() {
+        if !["project", "user"].contains(&scope) {
+            return Err(CommandError::Validation("Invalid scope. Must be 'project' or 'user'".to_string()));
+        }
+        if scope == "project" && project_path.is_none() {
+            return Err(CommandError::Validation("Project path required for project scope".to_string()));
+        }
+    }
+    
+    let commands: Vec<BundledCommand> = slash_commands_list(project_path, None, None)
+        .await?
+        .into_iter()
+        .filter(|cmd| cmd.scope != "default")
+        .filter(|cmd| scope.as_deref().is_none_or(|s| cmd.scope == s))
+        .map(|cmd| BundledCommand {
+            name: cmd.name,
+            namespace: cmd.namespace,
+            content: cmd.content,
+            description: cmd.description,
+            allowed_tools: cmd.allowed_tools,
+        })
+        .collect();
+    
+    let bundle = SlashCommandBundle { version: BUNDLE_VERSION, commands };
+    let content = serde_json::to_string_pretty(&bundle)
+        .map_err(|e| CommandError::Parse(format!("Failed to serialize command bundle: {}", e)))?;
+    fs::write(&path, content)
+        .map_err(|e| CommandError::FileWrite(format!("Failed to write command bundle: {}", e)))?;
+    
+    info!("Exported {} slash commands to {}", bundle.commands.len(), path);
+    Ok(bundle.commands.len())
+}
+
+/// Recreate the commands from a bundle in `scope`. Existing commands with the same
+/// name are skipped unless `overwrite` is set (the previous file is kept as a backup).
+#[tauri::command]
+pub async fn import_slash_commands(
+    path: String,
+    scope: String,
+    project_path: Option<String>,
+    overwrite: Option<bool>,
+) -> Result<SlashCommandImportResult, CommandError> {
+    if !["project", "user"].contains(&scope.as_str()) {
+        return Err(CommandError::Validation("Invalid scope. Must be 'project' or 'user'".to_string()));
+    }
+    
+    let content = fs::read_to_string(&path)
+        .map_err(|e| CommandError::FileRead(format!("Failed to read command bundle: {}", e)))?;
+    let bundle: SlashCommandBundle = serde_json::from_str(&content)
+        .map_err(|e| CommandError::Parse(format!("Invalid command bundle: {}", e)))?;
+    if bundle.version > BUNDLE_VERSION {
+        return Err(CommandError::Validation(format!(
+            "Unsupported command bundle version: {}",
+            bundle.version
+        )));
+    }
+    
+    let base_dir = commands_base_dir(&scope, project_path.as_deref())?;
+    let overwrite = overwrite.unwrap_or(false);
+    let mut result = SlashCommandImportResult { imported: Vec::new(), skipped: Vec::new() };
+    
+    for cmd in bundle.commands {
+        let full_command = match &cmd.namespace {
+            Some(ns) => format!("/{}:{}", ns, cmd.name),
+            None => format!("/{}", cmd.name),
+        };
+        
+        if !overwrite && command_file_path(&base_dir, cmd.namespace.as_deref(), &cmd.name)?.exists() {
+            result.skipped.push(full_command);
+            continue;
+        }
+        
+        slash_command_save(
+            scope.clone(),
+            cmd.name,
+            cmd.namespace,
+            cmd.content,
+            cmd.description,
+            cmd.allowed_tools,
+            project_path.clone(),
+        )
+        .await?;
+        result.imported.push(full_command);
+    }
+    
+    info!("Imported {} slash commands ({} skipped)", result.imported.len(), result.skipped.len());
+    Ok(result)
+}
+
+/// Extract `!`cmd`` and `@path` directives from a command body, in order of appearance
+fn extract_directives(body: &str) -> (Vec<String>, Vec<String>) {
+    let bash_re = Regex::new(r"!`([^`]+)`").expect("valid bash directive regex");
+    let file_re = Regex::new(r"(?:^|\s)@([^\s`]+)").expect("valid file reference regex");
+    
+    let mut bash_commands: Vec<String> = Vec::new();
+    for cap in bash_re.captures_iter(body) {
+        let cmd = cap[1].trim().to_string();
+        if !cmd.is_empty() && !bash_commands.contains(&cmd) {
+            bash_commands.push(cmd);
+        }
+    }
+    
+    let mut file_references: Vec<String> = Vec::new();
+    for cap in file_re.captures_iter(body) {
+        // Drop sentence punctuation that directly follows a path
+        let reference = cap[1].trim_end_matches(['.', ',', ';', ':', ')', '!', '?']);
+        if !reference.is_empty() && !file_references.iter().any(|r| r == reference) {
+            file_references.push(reference.to_string());
+        }
+    }
+    
+    (bash_commands, file_references)
+}
+
+/// Preview the bash commands and file references a command would expand, without running anything
+#[tauri::command]
+pub async fn preview_command_expansion(
+    command_id: String,
+    project_path: Option<String>,
+) -> Result<ExpansionPreview, CommandError> {
+    debug!("Previewing expansion for slash command: {}", command_id);
+    
+    let command = slash_command_get(command_id.clone(), project_path.clone()).await?;
+    let (bash_commands, references) = extract_directives(&command.content);
+    
+    // Relative references resolve against the project, as Claude Code does
+    let file_references = references
+        .into_iter()
+        .map(|reference| {
+            let path = PathBuf::from(&reference);
+            let resolved = if path.is_absolute() {
+                Some(path)
+            } else {
+                project_path.as_ref().map(|proj| PathBuf::from(proj).join(&path))
+            };
+            
+            FileReferencePreview {
+                exists: resolved.as_ref().map(|p| p.exists()).unwrap_or(false),
+                resolved_path: resolved.map(|p| p.to_string_lossy().to_string()),
+                reference,
+            }
+        })
+        .collect();
+    
+    Ok(ExpansionPreview {
+        command_id,
+        bash_commands,
+        file_references,
+    })
 }
 
 /// Delete a slash command
 #[tauri::command]
-pub async fn slash_command_delete(command_id: String, project_path: Option<String>) -> Result<String, String> {
+pub async fn slash_command_delete(command_id: String, project_path: Option<String>) -> Result<String, CommandError> {
     info!("Deleting slash command: {}", command_id);
     
-    // First, we need to determine if this is a project command by parsing the ID
-    let is_project_command = command_id.starts_with("project-");
+    // Decode the ID to find the command file
+    let (scope, base_dir, file_path) = resolve_command_id(&command_id, project_path.as_deref())?;
     
-    // If it's a project command and we don't have a project path, error out
-    if is_project_command && project_path.is_none() {
-        return Err("Project path required to delete project commands".to_string());
+    if !file_path.is_file() {
+        return Err(CommandError::NotFound(format!("Command not found: {}", command_id)));
     }
     
-    // List all commands (including project commands if applicable)
-    let commands = slash_commands_list(project_path).await?;
+    let command = load_command_from_file(&file_path, &base_dir, &scope)
+        .map_err(|e| CommandError::FileRead(format!("Failed to load command: {}", e)))?;
     
-    // Find the command by ID
-    let command = commands
-        .into_iter()
-        .find(|cmd| cmd.id == command_id)
-        .ok_or_else(|| format!("Command not found: {}", command_id))?;
+    // Only ever delete files inside the scope's commands directory
+    let command_file = ensure_within_commands_dir(Path::new(&command.file_path), &base_dir)?;
     
     // Delete the file
-    fs::remove_file(&command.file_path)
-        .map_err(|e| format!("Failed to delete command file: {}", e))?;
+    fs::remove_file(&command_file)
+        .map_err(|e| CommandError::FileWrite(format!("Failed to delete command file: {}", e)))?;
     
     // Clean up empty directories
-    if let Some(parent) = Path::new(&command.file_path).parent() {
-        let _ = remove_empty_dirs(parent);
+    if let Some(parent) = command_file.parent() {
+        if let Ok(base_dir) = base_dir.canonicalize() {
+            let _ = remove_empty_dirs(parent, &base_dir);
+        }
     }
     
     Ok(format!("Deleted command: {}", command.full_command))
 }
 
-/// Remove empty directories recursively
-fn remove_empty_dirs(dir: &Path) -> Result<()> {
+/// Canonicalize `path` and check it lies strictly inside the commands directory `base_dir`
+fn ensure_within_commands_dir(path: &Path, base_dir: &Path) -> Result<PathBuf, CommandError> {
+    let base_dir = base_dir
+        .canonicalize()
+        .map_err(|e| CommandError::FileRead(format!("Failed to resolve commands directory: {}", e)))?;
+    let path = path
+        .canonicalize()
+        .map_err(|e| CommandError::FileRead(format!("Failed to resolve command file: {}", e)))?;
+
+    if path == base_dir || !path.starts_with(&base_dir) {
+        return Err(CommandError::Validation(format!(
+            "Refusing to touch {} outside {}",
+            path.display(),
+            base_dir.display()
+        )));
+    }
+
+    Ok(path)
+}
+
+/// Remove empty directories recursively, stopping before the canonical `base_dir`
+fn remove_empty_dirs(dir: &Path, base_dir: &Path) -> Result<()> {
     if !dir.exists() {
         return Ok(());
     }
     
+    // Never remove the commands directory itself or walk up past it
+    let dir = dir.canonicalize()?;
+    if dir == base_dir || !dir.starts_with(base_dir) {
+        return Ok(());
+    }
+    
     // Check if directory is empty
-    let is_empty = fs::read_dir(dir)?.next().is_none();
+    let is_empty = fs::read_dir(&dir)?.next().is_none();
     
     if is_empty {
-        fs::remove_dir(dir)?;
+        fs::remove_dir(&dir)?;
         
         // Try to remove parent if it's also empty
         if let Some(parent) = dir.parent() {
-            let _ = remove_empty_dirs(parent);
+            let _ = remove_empty_dirs(parent, base_dir);
         }
     }
     
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_command_id_round_trip_nested_namespace() {
+        let id = encode_command_id("project", "frontend/components/button.md");
+        let (scope, components) = decode_command_id(&id).unwrap();
+
+        assert_eq!(scope, "project");
+        assert_eq!(components, vec!["frontend", "components", "button.md"]);
+        assert!(id[("project-".len())..]
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+    }
+
+    #[test]
+    fn test_command_id_windows_style_path() {
+        let windows_id = encode_command_id("user", "frontend\\components\\button.md");
+        let unix_id = encode_command_id("user", "frontend/components/button.md");
+        assert_eq!(windows_id, unix_id);
+
+        let (scope, components) = decode_command_id(&windows_id).unwrap();
+        assert_eq!(scope, "user");
+        assert_eq!(components, vec!["frontend", "components", "button.md"]);
+
+        assert_eq!(
+            normalize_relative_path(Path::new("frontend\\components\\button.md")),
+            "frontend/components/button.md"
+        );
+    }
+
+    #[test]
+    fn test_command_id_non_ascii_name() {
+        let id = encode_command_id("user", "工具/审查 代码.md");
+        let (_, components) = decode_command_id(&id).unwrap();
+        assert_eq!(components, vec!["工具", "审查 代码.md"]);
+    }
+
+    #[test]
+    fn test_command_id_rejects_invalid() {
+        assert!(decode_command_id("nodash").is_err());
+        assert!(decode_command_id("default-add-dir").is_err());
+        assert!(decode_command_id("user-!!!").is_err());
+        assert!(decode_command_id(&encode_command_id("user", "../secrets.md")).is_err());
+        assert!(decode_command_id(&encode_command_id("user", "a//b.md")).is_err());
+    }
+
+    #[test]
+    fn test_extract_directives() {
+        let body = "Status: !`git status` and !`git diff HEAD`\nReview @src/main.rs, then @docs/guide.md.\nMail me@example.com !`git status`";
+        let (bash_commands, file_references) = extract_directives(body);
+
+        assert_eq!(bash_commands, vec!["git status", "git diff HEAD"]);
+        assert_eq!(file_references, vec!["src/main.rs", "docs/guide.md"]);
+    }
+
+    #[test]
+    fn test_loaded_command_id_resolves_to_file() {
+        let temp = TempDir::new().unwrap();
+        let project_path = temp.path().to_string_lossy().to_string();
+        let base_dir = commands_base_dir("project", Some(&project_path)).unwrap();
+        let file_path = base_dir.join("frontend").join("ui").join("button.md");
+        fs::create_dir_all(file_path.parent().unwrap()).unwrap();
+        fs::write(&file_path, "Create a button for $ARGUMENTS").unwrap();
+
+        let command = load_command_from_file(&file_path, &base_dir, "project").unwrap();
+        assert_eq!(command.full_command, "/frontend:ui:button");
+
+        let (scope, resolved_base, resolved_file) =
+            resolve_command_id(&command.id, Some(&project_path)).unwrap();
+        assert_eq!(scope, "project");
+        assert_eq!(resolved_base, base_dir);
+        assert_eq!(resolved_file, file_path);
+
+        assert!(resolve_command_id(&command.id, None).is_err());
+    }
+
+    #[test]
+    fn test_delete_guards_stay_inside_commands_dir() {
+        let temp = TempDir::new().unwrap();
+        let base_dir = temp.path().join(".claude").join("commands");
+        let nested = base_dir.join("frontend").join("ui");
+        fs::create_dir_all(&nested).unwrap();
+        let outside = temp.path().join("notes.md");
+        fs::write(&outside, "keep me").unwrap();
+
+        assert!(ensure_within_commands_dir(&outside, &base_dir).is_err());
+        assert!(ensure_within_commands_dir(&base_dir.join("..").join("..").join("notes.md"), &base_dir).is_err());
+        assert!(ensure_within_commands_dir(&base_dir, &base_dir).is_err());
+
+        let canonical_base = base_dir.canonicalize().unwrap();
+        remove_empty_dirs(&nested, &canonical_base).unwrap();
+        assert!(!base_dir.join("frontend").exists());
+        assert!(base_dir.is_dir());
+    }
+}
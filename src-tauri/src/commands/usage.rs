@@ -1,41 +1,127 @@
-use chrono::{DateTime, Local, NaiveDate, Duration};
+use chrono::{DateTime, Datelike, Duration, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeZone, Timelike};
+use flate2::read::GzDecoder;
 use serde::{Deserialize, Serialize};
 use serde_json;
-use std::collections::{HashMap, HashSet};
+use sha2::{Digest, Sha256};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs;
-use std::path::PathBuf;
+use std::io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
 use std::env;
-use tauri::command;
+use tauri::{command, AppHandle, Emitter};
+use crate::commands::error::CommandError;
+use crate::commands::profiles::active_profile_dir;
+use crate::commands::provider::{find_provider_by_base_url, load_providers_from_file, ProviderConfig};
 
 #[derive(Debug, Serialize, Deserialize)]
 struct ClaudeSettings {
     env: Option<HashMap<String, serde_json::Value>>,
 }
 
-fn get_api_base_url() -> String {
+/// Resolve the Claude config directory, honoring `CLAUDE_CONFIG_DIR` like Claude Code does
+pub fn get_claude_config_dir() -> Result<PathBuf, CommandError> {
+    resolve_claude_config_dir(env::var("CLAUDE_CONFIG_DIR").ok(), active_profile_dir(), dirs::home_dir())
+}
+
+// Precedence: a non-empty CLAUDE_CONFIG_DIR, then the active profile, then ~/.claude
+fn resolve_claude_config_dir(
+    env_value: Option<String>,
+    profile_dir: Option<PathBuf>,
+    home_dir: Option<PathBuf>,
+) -> Result<PathBuf, CommandError> {
+    if let Some(config_dir) = env_value.filter(|dir| !dir.trim().is_empty()) {
+        return Ok(PathBuf::from(config_dir));
+    }
+
+    if let Some(profile_dir) = profile_dir {
+        return Ok(profile_dir);
+    }
+
+    home_dir
+        .map(|home| home.join(".claude"))
+        .ok_or_else(|| CommandError::HomeDirNotFound("Failed to get home directory".to_string()))
+}
+
+/// How repeated usage lines (e.g. a response logged again after a resume) are deduplicated
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DedupMode {
+    /// Dedup on message id for every usage line, whatever its token types
+    Strict,
+    /// Default: IO lines dedup on session + message id, cache-only lines on
+    /// message id + request id
+    #[default]
+    Loose,
+    /// Count every usage-bearing line
+    None,
+}
+
+/// Where the effective ANTHROPIC_BASE_URL came from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigSource {
+    Env,
+    Settings,
+    Default,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResolvedConfig {
+    value: String,
+    source: ConfigSource,
+}
+
+fn resolve_api_base_url_with_source() -> ResolvedConfig {
+    resolve_api_base_url_in(env::var("ANTHROPIC_BASE_URL").ok(), get_claude_config_dir().ok().as_deref())
+}
+
+// Precedence: ANTHROPIC_BASE_URL env var, then `env` in settings.json, then the official API
+fn resolve_api_base_url_in(env_value: Option<String>, claude_dir: Option<&Path>) -> ResolvedConfig {
     // First check environment variable
-    if let Ok(api_base_url) = env::var("ANTHROPIC_BASE_URL") {
-        return api_base_url;
+    if let Some(api_base_url) = env_value {
+        return ResolvedConfig { value: api_base_url, source: ConfigSource::Env };
     }
     
     // Then check Claude settings.json
-    if let Some(home_dir) = dirs::home_dir() {
-        let settings_path = home_dir.join(".claude").join("settings.json");
-        if let Ok(settings_content) = fs::read_to_string(&settings_path) {
-            if let Ok(settings) = serde_json::from_str::<ClaudeSettings>(&settings_content) {
-                if let Some(env_vars) = settings.env {
-                    if let Some(api_base_url) = env_vars.get("ANTHROPIC_BASE_URL") {
-                        if let Some(url_str) = api_base_url.as_str() {
-                            return url_str.to_string();
-                        }
-                    }
-                }
-            }
+    if let Some(claude_dir) = claude_dir {
+        if let Some(url_str) = settings_base_url(&claude_dir.join("settings.json")) {
+            return ResolvedConfig { value: url_str, source: ConfigSource::Settings };
         }
     }
     
     // Default fallback
-    "https://api.anthropic.com".to_string()
+    ResolvedConfig {
+        value: "https://api.anthropic.com".to_string(),
+        source: ConfigSource::Default,
+    }
+}
+
+// Last settings.json read, keyed by its fingerprint so repeated scans don't
+// re-read and re-parse an unchanged file
+static SETTINGS_BASE_URL_CACHE: Mutex<Option<(PathBuf, FileFingerprint, Option<String>)>> = Mutex::new(None);
+
+fn settings_base_url(settings_path: &Path) -> Option<String> {
+    let fingerprint = file_fingerprint(settings_path)?;
+    let mut cache = SETTINGS_BASE_URL_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((path, cached_fingerprint, value)) = cache.as_ref() {
+        if path == settings_path && *cached_fingerprint == fingerprint {
+            return value.clone();
+        }
+    }
+
+    let value = fs::read_to_string(settings_path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<ClaudeSettings>(&content).ok())
+        .and_then(|settings| settings.env)
+        .and_then(|env_vars| env_vars.get("ANTHROPIC_BASE_URL")?.as_str().map(str::to_string));
+    *cache = Some((settings_path.to_path_buf(), fingerprint, value.clone()));
+    value
+}
+
+fn get_api_base_url() -> String {
+    resolve_api_base_url_with_source().value
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -50,6 +136,30 @@ pub struct UsageEntry {
     session_id: String,
     project_path: String,
     api_base_url: String,
+    /// Token-based estimate for entries whose cost came from `costUSD`, used when
+    /// reported costs aren't trusted. `None` when nothing was reported or the model
+    /// has no known pricing.
+    #[serde(skip)]
+    estimated_cost: Option<f64>,
+    /// Whether the log line carried `costUSD`, independent of pricing
+    #[serde(skip)]
+    cost_reported: bool,
+    // Dedup key this entry claimed during the scan, kept so a cached scan can
+    // seed `processed_hashes` without re-reading the file
+    #[serde(skip)]
+    dedup_key: Option<String>,
+}
+
+// Token sums saturate at u64::MAX rather than wrapping in release builds or
+// panicking in debug builds on absurd lifetime totals
+fn sum_tokens(counts: [u64; 4]) -> u64 {
+    counts.into_iter().fold(0, u64::saturating_add)
+}
+
+impl UsageEntry {
+    fn total_tokens(&self) -> u64 {
+        sum_tokens([self.input_tokens, self.output_tokens, self.cache_creation_tokens, self.cache_read_tokens])
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -60,7 +170,11 @@ pub struct UsageStats {
     total_output_tokens: u64,
     total_cache_creation_tokens: u64,
     total_cache_read_tokens: u64,
+    // Distinct sessions in range. With `count_only_billed_sessions` this and the
+    // per-model/project/API session counts only include sessions that had a nonzero-cost
+    // entry in that bucket, so cache-read-only or unpriced sessions don't dilute averages.
     total_sessions: u64,
+    unknown_model_token_count: u64,  // tokens from entries with no recorded or recognized model, costed at $0
     by_model: Vec<ModelUsage>,
     by_date: Vec<DailyUsage>,
     by_project: Vec<ProjectUsage>,
@@ -77,6 +191,7 @@ pub struct ModelUsage {
     cache_creation_tokens: u64,
     cache_read_tokens: u64,
     session_count: u64,
+    uncosted: bool,  // no price known and no cost recorded, so $0 means "unknown", not "free"
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -84,9 +199,43 @@ pub struct DailyUsage {
     date: String,
     total_cost: f64,
     total_tokens: u64,
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
+    #[serde(default)]
+    cache_creation_tokens: u64,
+    #[serde(default)]
+    cache_read_tokens: u64,
     models_used: Vec<String>,
 }
 
+fn empty_daily_usage(date: String) -> DailyUsage {
+    DailyUsage {
+        date,
+        total_cost: 0.0,
+        total_tokens: 0,
+        input_tokens: 0,
+        output_tokens: 0,
+        cache_creation_tokens: 0,
+        cache_read_tokens: 0,
+        models_used: vec![],
+    }
+}
+
+// Shared by every daily bucket so all daily views split tokens the same way
+fn add_to_daily_usage(daily_stat: &mut DailyUsage, entry: &UsageEntry) {
+    daily_stat.total_cost += entry.cost;
+    daily_stat.total_tokens = daily_stat.total_tokens.saturating_add(entry.total_tokens());
+    daily_stat.input_tokens = daily_stat.input_tokens.saturating_add(entry.input_tokens);
+    daily_stat.output_tokens = daily_stat.output_tokens.saturating_add(entry.output_tokens);
+    daily_stat.cache_creation_tokens = daily_stat.cache_creation_tokens.saturating_add(entry.cache_creation_tokens);
+    daily_stat.cache_read_tokens = daily_stat.cache_read_tokens.saturating_add(entry.cache_read_tokens);
+    if !daily_stat.models_used.contains(&entry.model) {
+        daily_stat.models_used.push(entry.model.clone());
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ProjectUsage {
     project_path: String,
@@ -95,8 +244,15 @@ pub struct ProjectUsage {
     total_tokens: u64,
     session_count: u64,
     last_used: String,
+    tokens_per_dollar: f64, // 0.0 when the project has no recorded cost
+    session_id: Option<String>, // only set by the per-session view
+    #[serde(default)]
+    tags: Vec<String>, // session tags, only set by the per-session view
 }
 
+/// Usage grouped by API base URL. Logs don't record the endpoint, so every entry
+/// is attributed to the base URL configured at scan time, not the one in use
+/// when the request was made.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ApiBaseUrlUsage {
     api_base_url: String,
@@ -107,44 +263,169 @@ pub struct ApiBaseUrlUsage {
     cache_creation_tokens: u64,
     cache_read_tokens: u64,
     session_count: u64,
+    provider_name: Option<String>, // saved provider whose base URL matches, if any
 }
 
 // Claude 4 pricing constants (per million tokens) - Updated January 2025
 const OPUS_4_INPUT_PRICE: f64 = 15.0;
 const OPUS_4_OUTPUT_PRICE: f64 = 75.0;
 const OPUS_4_CACHE_WRITE_PRICE: f64 = 18.75;
+const OPUS_4_CACHE_WRITE_1H_PRICE: f64 = 30.0;
 const OPUS_4_CACHE_READ_PRICE: f64 = 1.50;
 
 const SONNET_4_INPUT_PRICE: f64 = 3.0;
 const SONNET_4_OUTPUT_PRICE: f64 = 15.0;
 const SONNET_4_CACHE_WRITE_PRICE: f64 = 3.75;
+const SONNET_4_CACHE_WRITE_1H_PRICE: f64 = 6.0;
 const SONNET_4_CACHE_READ_PRICE: f64 = 0.30;
 
 // Claude 3.7 pricing constants (per million tokens)
 const SONNET_37_INPUT_PRICE: f64 = 3.0;
 const SONNET_37_OUTPUT_PRICE: f64 = 15.0;
 const SONNET_37_CACHE_WRITE_PRICE: f64 = 3.75;
+const SONNET_37_CACHE_WRITE_1H_PRICE: f64 = 6.0;
 const SONNET_37_CACHE_READ_PRICE: f64 = 0.30;
 
 // Claude 3.5 pricing constants (per million tokens)
 const SONNET_35_INPUT_PRICE: f64 = 3.0;
 const SONNET_35_OUTPUT_PRICE: f64 = 15.0;
 const SONNET_35_CACHE_WRITE_PRICE: f64 = 3.75;
+const SONNET_35_CACHE_WRITE_1H_PRICE: f64 = 6.0;
 const SONNET_35_CACHE_READ_PRICE: f64 = 0.30;
 
 const HAIKU_35_INPUT_PRICE: f64 = 0.80;
 const HAIKU_35_OUTPUT_PRICE: f64 = 4.0;
 const HAIKU_35_CACHE_WRITE_PRICE: f64 = 1.0;
+const HAIKU_35_CACHE_WRITE_1H_PRICE: f64 = 1.6;
 const HAIKU_35_CACHE_READ_PRICE: f64 = 0.08;
 
-// Claude Code session window duration (5 hours)
-const SESSION_WINDOW_HOURS: i64 = 5;
+// Default Claude Code session window duration (5 hours), overridable in usage_prefs.json
+const DEFAULT_SESSION_WINDOW_HOURS: i64 = 5;
+const MAX_SESSION_WINDOW_HOURS: i64 = 24 * 7;
+
+// Burn rate analysis warns once the busiest session passes this share of the window token cap
+const WINDOW_CAP_WARNING_PERCENT: f64 = 80.0;
+
+// Burn rate history: 10-minute buckets covering the last 3 hours
+const BURN_RATE_BUCKET_MINUTES: i64 = 10;
+const BURN_RATE_SERIES_HOURS: i64 = 3;
+
+// A session counts as live when it logged usage within this many minutes; its
+// rate is measured over the same span
+const LIVE_SESSION_MINUTES: i64 = 10;
+
+// User preferences for usage analysis, stored in <claude config dir>/usage_prefs.json
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct UsagePrefs {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    session_window_hours: Option<i64>,
+    // Plan token limit per session window; burn rate analysis reports usage against it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    window_token_cap: Option<u64>,
+    // Whether scans follow symlinks under projects/ (default true), e.g. session
+    // storage moved to another drive
+    #[serde(skip_serializing_if = "Option::is_none")]
+    follow_symlinks: Option<bool>,
+    // Monthly spending budget in USD, used by the budget runway
+    #[serde(skip_serializing_if = "Option::is_none")]
+    monthly_budget_usd: Option<f64>,
+    // Daily spending cap in USD, checked by check_daily_cap
+    #[serde(skip_serializing_if = "Option::is_none")]
+    daily_cap_usd: Option<f64>,
+}
+
+fn usage_prefs_path() -> Result<PathBuf, CommandError> {
+    Ok(get_claude_config_dir()?.join("usage_prefs.json"))
+}
+
+// Missing or unreadable prefs fall back to defaults
+fn load_usage_prefs() -> UsagePrefs {
+    let path = match usage_prefs_path() {
+        Ok(path) => path,
+        Err(_) => return UsagePrefs::default(),
+    };
+
+    match fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+            log::warn!("Ignoring invalid usage prefs {}: {}", path.display(), e);
+            UsagePrefs::default()
+        }),
+        Err(_) => UsagePrefs::default(),
+    }
+}
+
+fn save_usage_prefs(prefs: &UsagePrefs) -> Result<(), CommandError> {
+    let content = serde_json::to_string_pretty(prefs)
+        .map_err(|e| CommandError::Parse(format!("Failed to serialize usage prefs: {}", e)))?;
+    write_usage_file_atomic(&usage_prefs_path()?, content.as_bytes())
+}
+
+// Serializes read-modify-write cycles on the files usage.rs keeps under the
+// config dir (prefs and caches), so concurrent commands can't interleave updates.
+// Scan state such as `processed_hashes` stays local to each scan and needs no lock.
+static USAGE_FILES_LOCK: Mutex<()> = Mutex::new(());
+
+fn lock_usage_files() -> Result<MutexGuard<'static, ()>, CommandError> {
+    USAGE_FILES_LOCK
+        .lock()
+        .map_err(|e| CommandError::Other(format!("Failed to lock usage files: {}", e)))
+}
+
+// Write via a temp file in the same directory and rename over the target, so
+// readers never observe a partially written prefs or cache file
+pub(crate) fn write_usage_file_atomic(path: &Path, content: &[u8]) -> Result<(), CommandError> {
+    let dir = path
+        .parent()
+        .ok_or_else(|| CommandError::Other(format!("Invalid usage file path: {}", path.display())))?;
+    fs::create_dir_all(dir)
+        .map_err(|e| CommandError::FileWrite(format!("Failed to create config directory: {}", e)))?;
 
-// Helper function to check if a session is still active based on Claude Code's 5-hour window
-fn is_session_active(session_start: &str, current_time: &DateTime<Local>) -> bool {
-    if let Ok(start_time) = DateTime::parse_from_rfc3339(session_start) {
+    let mut temp_file = tempfile::NamedTempFile::new_in(dir)
+        .map_err(|e| CommandError::FileWrite(format!("Failed to create temp file: {}", e)))?;
+    temp_file
+        .write_all(content)
+        .and_then(|_| temp_file.as_file().sync_all())
+        .map_err(|e| CommandError::FileWrite(format!("Failed to write {}: {}", path.display(), e)))?;
+    temp_file
+        .persist(path)
+        .map_err(|e| CommandError::FileWrite(format!("Failed to write {}: {}", path.display(), e)))?;
+
+    Ok(())
+}
+
+// Single accessor for the session window used by all session analysis
+fn session_window_hours() -> i64 {
+    load_usage_prefs()
+        .session_window_hours
+        .filter(|hours| (1..=MAX_SESSION_WINDOW_HOURS).contains(hours))
+        .unwrap_or(DEFAULT_SESSION_WINDOW_HOURS)
+}
+
+// WalkDir detects symlink loops itself, so following links can't recurse forever
+fn follow_symlinks() -> bool {
+    load_usage_prefs().follow_symlinks.unwrap_or(true)
+}
+
+// Parse an entry timestamp. Strict RFC3339 first; some logs write naive timestamps
+// without an offset (e.g. "2025-01-15T10:00:00"), which are taken as local time
+fn parse_entry_time(timestamp: &str) -> Option<DateTime<FixedOffset>> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(timestamp) {
+        return Some(dt);
+    }
+
+    ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(timestamp.trim(), format).ok())
+        .and_then(|naive| Local.from_local_datetime(&naive).earliest())
+        .map(|dt| dt.fixed_offset())
+}
+
+// Helper function to check if a session is still active within a `window_hours` session window
+fn is_session_active(session_start: &str, current_time: &DateTime<Local>, window_hours: i64) -> bool {
+    if let Some(start_time) = parse_entry_time(session_start) {
         let elapsed = current_time.signed_duration_since(start_time);
-        elapsed.num_hours() < SESSION_WINDOW_HOURS
+        elapsed.num_hours() < window_hours
     } else {
         false
     }
@@ -155,7 +436,7 @@ fn track_active_sessions(entries: &[UsageEntry]) -> HashMap<String, DateTime<Loc
     let mut session_starts: HashMap<String, DateTime<Local>> = HashMap::new();
     
     for entry in entries {
-        if let Ok(entry_time) = DateTime::parse_from_rfc3339(&entry.timestamp) {
+        if let Some(entry_time) = parse_entry_time(&entry.timestamp) {
             let local_time = entry_time.with_timezone(&Local);
             
             // Track the earliest timestamp for each session
@@ -198,6 +479,111 @@ struct UsageData {
     output_tokens: Option<u64>,
     cache_creation_input_tokens: Option<u64>,
     cache_read_input_tokens: Option<u64>,
+    cache_creation: Option<CacheCreationData>,
+}
+
+// Breakdown of cache writes by TTL (5-minute writes are the standard rate, 1-hour writes cost more)
+#[derive(Debug, Deserialize)]
+struct CacheCreationData {
+    ephemeral_5m_input_tokens: Option<u64>,
+    ephemeral_1h_input_tokens: Option<u64>,
+}
+
+// Per-million-token prices (input, output, 5m cache write, 1h cache write, cache read),
+// or None when the model doesn't match any known pricing pattern
+fn model_prices(model: &str) -> Option<(f64, f64, f64, f64, f64)> {
+    let prices = if model.contains("opus-4") || model.contains("claude-opus-4") {
+        (
+            OPUS_4_INPUT_PRICE,
+            OPUS_4_OUTPUT_PRICE,
+            OPUS_4_CACHE_WRITE_PRICE,
+            OPUS_4_CACHE_WRITE_1H_PRICE,
+            OPUS_4_CACHE_READ_PRICE,
+        )
+    } else if model.contains("sonnet-4") || model.contains("claude-sonnet-4") {
+        (
+            SONNET_4_INPUT_PRICE,
+            SONNET_4_OUTPUT_PRICE,
+            SONNET_4_CACHE_WRITE_PRICE,
+            SONNET_4_CACHE_WRITE_1H_PRICE,
+            SONNET_4_CACHE_READ_PRICE,
+        )
+    } else if model.contains("sonnet-3.7") || model.contains("claude-sonnet-3.7") {
+        (
+            SONNET_37_INPUT_PRICE,
+            SONNET_37_OUTPUT_PRICE,
+            SONNET_37_CACHE_WRITE_PRICE,
+            SONNET_37_CACHE_WRITE_1H_PRICE,
+            SONNET_37_CACHE_READ_PRICE,
+        )
+    } else if model.contains("sonnet-3.5") || model.contains("claude-sonnet-3.5") {
+        (
+            SONNET_35_INPUT_PRICE,
+            SONNET_35_OUTPUT_PRICE,
+            SONNET_35_CACHE_WRITE_PRICE,
+            SONNET_35_CACHE_WRITE_1H_PRICE,
+            SONNET_35_CACHE_READ_PRICE,
+        )
+    } else if model.contains("haiku-3.5") || model.contains("claude-haiku-3.5") {
+        (
+            HAIKU_35_INPUT_PRICE,
+            HAIKU_35_OUTPUT_PRICE,
+            HAIKU_35_CACHE_WRITE_PRICE,
+            HAIKU_35_CACHE_WRITE_1H_PRICE,
+            HAIKU_35_CACHE_READ_PRICE,
+        )
+    } else {
+        return None;
+    };
+
+    Some(prices)
+}
+
+/// Flat per-million-token rates a provider charges regardless of model
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPricing {
+    pub input: f64,
+    pub output: f64,
+    #[serde(default)]
+    pub cache_write: f64,
+    #[serde(default)]
+    pub cache_read: f64,
+}
+
+impl ModelPricing {
+    fn cost(&self, input_tokens: u64, output_tokens: u64, cache_creation_tokens: u64, cache_read_tokens: u64) -> f64 {
+        (input_tokens as f64 * self.input
+            + output_tokens as f64 * self.output
+            + cache_creation_tokens as f64 * self.cache_write
+            + cache_read_tokens as f64 * self.cache_read)
+            / 1_000_000.0
+    }
+}
+
+// Reprice entries served through a saved provider that has a `pricing_override`.
+// Its rates replace both model pricing and any reported `costUSD`, since the
+// gateway bills by its own rates. Applied after parsing, so the aggregate cache
+// keeps model-based costs and edited overrides take effect on the next scan.
+fn apply_provider_pricing(entries: &mut [UsageEntry], providers: &[ProviderConfig]) {
+    if providers.iter().all(|p| p.pricing_override.is_none()) {
+        return;
+    }
+
+    let mut overrides: HashMap<String, Option<ModelPricing>> = HashMap::new();
+    for entry in entries {
+        let pricing = *overrides
+            .entry(entry.api_base_url.clone())
+            .or_insert_with(|| find_provider_by_base_url(providers, &entry.api_base_url).and_then(|p| p.pricing_override));
+        if let Some(pricing) = pricing {
+            entry.cost = pricing.cost(
+                entry.input_tokens,
+                entry.output_tokens,
+                entry.cache_creation_tokens,
+                entry.cache_read_tokens,
+            );
+            entry.estimated_cost = None;
+        }
+    }
 }
 
 fn calculate_cost(model: &str, usage: &UsageData) -> f64 {
@@ -207,170 +593,285 @@ fn calculate_cost(model: &str, usage: &UsageData) -> f64 {
     let cache_read_tokens = usage.cache_read_input_tokens.unwrap_or(0) as f64;
 
     // Calculate cost based on model - improved pattern matching
-    let (input_price, output_price, cache_write_price, cache_read_price) =
-        if model.contains("opus-4") || model.contains("claude-opus-4") {
-            (
-                OPUS_4_INPUT_PRICE,
-                OPUS_4_OUTPUT_PRICE,
-                OPUS_4_CACHE_WRITE_PRICE,
-                OPUS_4_CACHE_READ_PRICE,
-            )
-        } else if model.contains("sonnet-4") || model.contains("claude-sonnet-4") {
-            (
-                SONNET_4_INPUT_PRICE,
-                SONNET_4_OUTPUT_PRICE,
-                SONNET_4_CACHE_WRITE_PRICE,
-                SONNET_4_CACHE_READ_PRICE,
-            )
-        } else if model.contains("sonnet-3.7") || model.contains("claude-sonnet-3.7") {
-            (
-                SONNET_37_INPUT_PRICE,
-                SONNET_37_OUTPUT_PRICE,
-                SONNET_37_CACHE_WRITE_PRICE,
-                SONNET_37_CACHE_READ_PRICE,
-            )
-        } else if model.contains("sonnet-3.5") || model.contains("claude-sonnet-3.5") {
-            (
-                SONNET_35_INPUT_PRICE,
-                SONNET_35_OUTPUT_PRICE,
-                SONNET_35_CACHE_WRITE_PRICE,
-                SONNET_35_CACHE_READ_PRICE,
-            )
-        } else if model.contains("haiku-3.5") || model.contains("claude-haiku-3.5") {
-            (
-                HAIKU_35_INPUT_PRICE,
-                HAIKU_35_OUTPUT_PRICE,
-                HAIKU_35_CACHE_WRITE_PRICE,
-                HAIKU_35_CACHE_READ_PRICE,
-            )
-        } else {
-            // Return 0 for unknown models to avoid incorrect cost estimations (旧版本逻辑)
-            (0.0, 0.0, 0.0, 0.0)
-        };
+    // Return 0 for unknown models to avoid incorrect cost estimations (旧版本逻辑)
+    let (input_price, output_price, cache_write_price, cache_write_1h_price, cache_read_price) =
+        model_prices(model).unwrap_or((0.0, 0.0, 0.0, 0.0, 0.0));
+
+    // Price 5-minute and 1-hour cache writes separately when the log provides the breakdown;
+    // any cache-creation tokens not covered by it are billed at the standard (5-minute) rate
+    let cache_write_cost = match &usage.cache_creation {
+        Some(breakdown)
+            if breakdown.ephemeral_5m_input_tokens.is_some()
+                || breakdown.ephemeral_1h_input_tokens.is_some() =>
+        {
+            let tokens_5m = breakdown.ephemeral_5m_input_tokens.unwrap_or(0) as f64;
+            let tokens_1h = breakdown.ephemeral_1h_input_tokens.unwrap_or(0) as f64;
+            let remainder = (cache_creation_tokens - tokens_5m - tokens_1h).max(0.0);
+
+            ((tokens_5m + remainder) * cache_write_price + tokens_1h * cache_write_1h_price)
+                / 1_000_000.0
+        }
+        _ => cache_creation_tokens * cache_write_price / 1_000_000.0,
+    };
 
     // Calculate cost (prices are per million tokens)
     let cost = (input_tokens * input_price / 1_000_000.0)
         + (output_tokens * output_price / 1_000_000.0)
-        + (cache_creation_tokens * cache_write_price / 1_000_000.0)
+        + cache_write_cost
         + (cache_read_tokens * cache_read_price / 1_000_000.0);
 
     cost
 }
 
+// Tokens obtained per dollar spent, guarding against zero-cost buckets
+fn tokens_per_dollar(total_tokens: u64, total_cost: f64) -> f64 {
+    if total_cost > 0.0 {
+        total_tokens as f64 / total_cost
+    } else {
+        0.0
+    }
+}
+
+// Parse a usage log line by line without loading the whole file into memory.
+// `api_base_url` is resolved once per scan by the caller and stamped on every entry:
+// the logs don't record which endpoint served a request.
 fn parse_jsonl_file(
     path: &PathBuf,
     encoded_project_name: &str,
+    api_base_url: &str,
+    dedup_mode: DedupMode,
     processed_hashes: &mut HashSet<String>,
 ) -> Vec<UsageEntry> {
+    match open_usage_log(path) {
+        Ok(reader) => parse_usage_lines(
+            reader.lines().map_while(Result::ok),
+            path,
+            encoded_project_name,
+            api_base_url,
+            dedup_mode,
+            processed_hashes,
+        ),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn parse_usage_lines<I, S>(
+    lines: I,
+    path: &Path,
+    encoded_project_name: &str,
+    api_base_url: &str,
+    dedup_mode: DedupMode,
+    processed_hashes: &mut HashSet<String>,
+) -> Vec<UsageEntry>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    parse_usage_lines_from(lines, path, encoded_project_name, None, api_base_url, dedup_mode, processed_hashes).0
+}
+
+// `project_cwd` is the cwd found in earlier lines of the same log when resuming
+// mid-file; it's returned (possibly newly found) alongside the entries
+fn parse_usage_lines_from<I, S>(
+    lines: I,
+    path: &Path,
+    encoded_project_name: &str,
+    project_cwd: Option<String>,
+    api_base_url: &str,
+    dedup_mode: DedupMode,
+    processed_hashes: &mut HashSet<String>,
+) -> (Vec<UsageEntry>, Option<String>)
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
     let mut entries = Vec::new();
-    let mut actual_project_path: Option<String> = None;
+    let mut actual_project_path: Option<String> = project_cwd;
 
-    if let Ok(content) = fs::read_to_string(path) {
-        // Extract session ID from the file path
-        let session_id = path
-            .parent()
-            .and_then(|p| p.file_name())
-            .and_then(|n| n.to_str())
-            .unwrap_or("unknown")
-            .to_string();
+    // Extract session ID from the file path
+    let session_id = path
+        .parent()
+        .and_then(|p| p.file_name())
+        .and_then(|n| n.to_str())
+        .unwrap_or("unknown")
+        .to_string();
 
-        for line in content.lines() {
-            if line.trim().is_empty() {
-                continue;
-            }
+    for line in lines {
+        let line = line.as_ref();
+        if line.trim().is_empty() {
+            continue;
+        }
 
-            if let Ok(json_value) = serde_json::from_str::<serde_json::Value>(line) {
-                // Extract the actual project path from cwd if we haven't already
-                if actual_project_path.is_none() {
-                    if let Some(cwd) = json_value.get("cwd").and_then(|v| v.as_str()) {
-                        actual_project_path = Some(cwd.to_string());
-                    }
+        if let Ok(json_value) = serde_json::from_str::<serde_json::Value>(line) {
+            // Extract the actual project path from cwd if we haven't already
+            if actual_project_path.is_none() {
+                if let Some(cwd) = json_value.get("cwd").and_then(|v| v.as_str()) {
+                    actual_project_path = Some(cwd.to_string());
                 }
+            }
 
-                // Get API Base URL from configuration
-                let api_base_url = get_api_base_url();
-
-                // Try to parse as JsonlEntry for usage data
-                if let Ok(entry) = serde_json::from_value::<JsonlEntry>(json_value.clone()) {
-                    if let Some(message) = &entry.message {
-                        if let Some(usage) = &message.usage {
-                            // 智能去重策略：结合两个版本的优点（最真实的统计方式）
-                            let has_io_tokens = usage.input_tokens.unwrap_or(0) > 0 || usage.output_tokens.unwrap_or(0) > 0;
-                            let has_cache_tokens = usage.cache_creation_input_tokens.unwrap_or(0) > 0 || usage.cache_read_input_tokens.unwrap_or(0) > 0;
-                            
-                            if has_io_tokens {
-                                // 对输入输出token使用严格去重（确保准确性）
-                                if let Some(msg_id) = &message.id {
-                                    let unique_hash = format!("io:{}:{}", session_id, msg_id);
-                                    if processed_hashes.contains(&unique_hash) {
-                                        continue; // Skip duplicate IO entry
+            // Try to parse as JsonlEntry for usage data
+            if let Ok(entry) = serde_json::from_value::<JsonlEntry>(json_value.clone()) {
+                if let Some(message) = &entry.message {
+                    if let Some(usage) = &message.usage {
+                        let mut dedup_key = None;
+                        match dedup_mode {
+                            DedupMode::Loose => {
+                                // 智能去重策略：结合两个版本的优点（最真实的统计方式）
+                                let has_io_tokens = usage.input_tokens.unwrap_or(0) > 0 || usage.output_tokens.unwrap_or(0) > 0;
+                                let has_cache_tokens = usage.cache_creation_input_tokens.unwrap_or(0) > 0 || usage.cache_read_input_tokens.unwrap_or(0) > 0;
+                        
+                                if has_io_tokens {
+                                    // 对输入输出token使用严格去重（确保准确性）
+                                    if let Some(msg_id) = &message.id {
+                                        let unique_hash = format!("io:{}:{}", session_id, msg_id);
+                                        if processed_hashes.contains(&unique_hash) {
+                                            continue; // Skip duplicate IO entry
+                                        }
+                                        processed_hashes.insert(unique_hash.clone());
+                                        dedup_key = Some(unique_hash);
                                     }
-                                    processed_hashes.insert(unique_hash);
-                                }
-                            } else if has_cache_tokens {
-                                // 对缓存token使用旧版本宽松去重（保持准确性）
-                                if let (Some(msg_id), Some(req_id)) = (&message.id, &entry.request_id) {
-                                    let unique_hash = format!("cache:{}:{}", msg_id, req_id);
-                                    if processed_hashes.contains(&unique_hash) {
-                                        continue; // Skip duplicate cache entry
+                                } else if has_cache_tokens {
+                                    // 对缓存token使用旧版本宽松去重（保持准确性）
+                                    if let (Some(msg_id), Some(req_id)) = (&message.id, &entry.request_id) {
+                                        let unique_hash = format!("cache:{}:{}", msg_id, req_id);
+                                        if processed_hashes.contains(&unique_hash) {
+                                            continue; // Skip duplicate cache entry
+                                        }
+                                        processed_hashes.insert(unique_hash.clone());
+                                        dedup_key = Some(unique_hash);
                                     }
-                                    processed_hashes.insert(unique_hash);
                                 }
                             }
-                            // Skip entries without meaningful token usage
-                            if usage.input_tokens.unwrap_or(0) == 0
-                                && usage.output_tokens.unwrap_or(0) == 0
-                                && usage.cache_creation_input_tokens.unwrap_or(0) == 0
-                                && usage.cache_read_input_tokens.unwrap_or(0) == 0
-                            {
-                                continue;
+                            DedupMode::Strict => {
+                                // 任何 token 类型都按消息 ID 去重
+                                if let Some(msg_id) = &message.id {
+                                    let unique_hash = format!("msg:{}", msg_id);
+                                    if !processed_hashes.insert(unique_hash.clone()) {
+                                        continue; // Skip duplicate message
+                                    }
+                                    dedup_key = Some(unique_hash);
+                                }
                             }
+                            DedupMode::None => {}
+                        }
+                        // Skip entries without meaningful token usage
+                        if usage.input_tokens.unwrap_or(0) == 0
+                            && usage.output_tokens.unwrap_or(0) == 0
+                            && usage.cache_creation_input_tokens.unwrap_or(0) == 0
+                            && usage.cache_read_input_tokens.unwrap_or(0) == 0
+                        {
+                            continue;
+                        }
 
-                            let cost = entry.cost_usd.unwrap_or_else(|| {
-                                if let Some(model_str) = &message.model {
-                                    calculate_cost(model_str, usage)
-                                } else {
-                                    0.0
-                                }
-                            });
+                        let calculated_cost = message
+                            .model
+                            .as_deref()
+                            .filter(|model| model_prices(model).is_some())
+                            .map(|model| calculate_cost(model, usage));
+                        let cost = entry.cost_usd.or(calculated_cost).unwrap_or(0.0);
+                        let estimated_cost = entry.cost_usd.and(calculated_cost);
 
-                            // Use actual project path if found, otherwise use encoded name
-                            let project_path = actual_project_path
+                        // Use actual project path if found, otherwise use encoded name
+                        let project_path = actual_project_path
+                            .clone()
+                            .unwrap_or_else(|| encoded_project_name.to_string());
+
+                        entries.push(UsageEntry {
+                            timestamp: entry.timestamp,
+                            model: message
+                                .model
                                 .clone()
-                                .unwrap_or_else(|| encoded_project_name.to_string());
-
-                            entries.push(UsageEntry {
-                                timestamp: entry.timestamp,
-                                model: message
-                                    .model
-                                    .clone()
-                                    .unwrap_or_else(|| "unknown".to_string()),
-                                input_tokens: usage.input_tokens.unwrap_or(0),
-                                output_tokens: usage.output_tokens.unwrap_or(0),
-                                cache_creation_tokens: usage
-                                    .cache_creation_input_tokens
-                                    .unwrap_or(0),
-                                cache_read_tokens: usage.cache_read_input_tokens.unwrap_or(0),
-                                cost,
-                                session_id: entry.session_id.unwrap_or_else(|| session_id.clone()),
-                                project_path,
-                                api_base_url,
-                            });
-                        }
+                                .unwrap_or_else(|| "unknown".to_string()),
+                            input_tokens: usage.input_tokens.unwrap_or(0),
+                            output_tokens: usage.output_tokens.unwrap_or(0),
+                            cache_creation_tokens: usage
+                                .cache_creation_input_tokens
+                                .unwrap_or(0),
+                            cache_read_tokens: usage.cache_read_input_tokens.unwrap_or(0),
+                            cost,
+                            session_id: entry.session_id.unwrap_or_else(|| session_id.clone()),
+                            project_path,
+                            api_base_url: api_base_url.to_string(),
+                            estimated_cost,
+                            cost_reported: entry.cost_usd.is_some(),
+                            dedup_key,
+                        });
                     }
                 }
             }
         }
     }
 
-    entries
+    (entries, actual_project_path)
+}
+
+// Parse a plain `.jsonl` log from byte `offset` on, for logs that have only been
+// appended to since `offset` was recorded. Returns the entries, the offset after
+// the last consumed line and the project cwd. A final line without a newline is
+// only consumed once it parses as JSON, so a half-written line is read again
+// (completed) on the next scan.
+fn parse_jsonl_file_from(
+    path: &Path,
+    offset: u64,
+    encoded_project_name: &str,
+    project_cwd: Option<String>,
+    api_base_url: &str,
+    processed_hashes: &mut HashSet<String>,
+) -> std::io::Result<(Vec<UsageEntry>, u64, Option<String>)> {
+    let mut file = fs::File::open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut reader = BufReader::new(file);
+
+    let mut consumed = offset;
+    let lines = std::iter::from_fn(|| {
+        let mut line = String::new();
+        let read = reader.read_line(&mut line).ok()?;
+        if read == 0 {
+            return None;
+        }
+        if !line.ends_with('\n') && serde_json::from_str::<serde_json::Value>(&line).is_err() {
+            return None;
+        }
+        consumed += read as u64;
+        Some(line.trim_end_matches(['\n', '\r']).to_string())
+    });
+    let (entries, project_cwd) = parse_usage_lines_from(
+        lines,
+        path,
+        encoded_project_name,
+        project_cwd,
+        api_base_url,
+        DedupMode::default(),
+        processed_hashes,
+    );
+
+    Ok((entries, consumed, project_cwd))
+}
+
+// Usage logs are plain `.jsonl` files, or `.jsonl.gz` once compressed for archival
+pub(crate) fn is_usage_log(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .map(|name| name.ends_with(".jsonl") || name.ends_with(".jsonl.gz"))
+        .unwrap_or(false)
+}
+
+// Open a usage log for line-by-line reading, transparently decompressing `.jsonl.gz` files
+pub(crate) fn open_usage_log(path: &Path) -> std::io::Result<Box<dyn BufRead>> {
+    let file = fs::File::open(path)?;
+    if path.extension().and_then(|e| e.to_str()) == Some("gz") {
+        Ok(Box::new(BufReader::new(GzDecoder::new(file))))
+    } else {
+        Ok(Box::new(BufReader::new(file)))
+    }
 }
 
 fn get_earliest_timestamp(path: &PathBuf) -> Option<String> {
-    if let Ok(content) = fs::read_to_string(path) {
+    if let Ok(reader) = open_usage_log(path) {
         let mut earliest_timestamp: Option<String> = None;
-        for line in content.lines() {
-            if let Ok(json_value) = serde_json::from_str::<serde_json::Value>(line) {
+        for line in reader.lines().map_while(Result::ok) {
+            if let Ok(json_value) = serde_json::from_str::<serde_json::Value>(&line) {
                 if let Some(timestamp_str) = json_value.get("timestamp").and_then(|v| v.as_str()) {
                     if let Some(current_earliest) = &earliest_timestamp {
                         if timestamp_str < current_earliest.as_str() {
@@ -387,23 +888,39 @@ fn get_earliest_timestamp(path: &PathBuf) -> Option<String> {
     None
 }
 
-fn get_all_usage_entries(claude_path: &PathBuf) -> Vec<UsageEntry> {
-    let mut all_entries = Vec::new();
-    let mut processed_hashes = HashSet::new();
+// Collect every usage log under ~/.claude/projects with its project name,
+// sorted by earliest timestamp for chronological processing and deterministic
+// deduplication.
+fn collect_usage_files(claude_path: &Path) -> Vec<(PathBuf, String)> {
+    let mut files_to_process = list_usage_files(claude_path, follow_symlinks());
+
+    files_to_process.sort_by_cached_key(|(path, _)| get_earliest_timestamp(path));
+
+    files_to_process
+}
+
+// Every usage log under ~/.claude/projects with its project name, in directory order
+fn list_usage_files(claude_path: &Path, follow_links: bool) -> Vec<(PathBuf, String)> {
     let projects_dir = claude_path.join("projects");
 
     let mut files_to_process: Vec<(PathBuf, String)> = Vec::new();
 
     if let Ok(projects) = fs::read_dir(&projects_dir) {
         for project in projects.flatten() {
-            if project.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+            let is_dir = if follow_links {
+                project.path().is_dir()
+            } else {
+                project.file_type().map(|t| t.is_dir()).unwrap_or(false)
+            };
+            if is_dir {
                 let project_name = project.file_name().to_string_lossy().to_string();
                 let project_path = project.path();
 
                 walkdir::WalkDir::new(&project_path)
+                    .follow_links(follow_links)
                     .into_iter()
                     .filter_map(Result::ok)
-                    .filter(|e| e.path().extension().and_then(|s| s.to_str()) == Some("jsonl"))
+                    .filter(|e| is_usage_log(e.path()))
                     .for_each(|entry| {
                         files_to_process.push((entry.path().to_path_buf(), project_name.clone()));
                     });
@@ -411,14 +928,30 @@ fn get_all_usage_entries(claude_path: &PathBuf) -> Vec<UsageEntry> {
         }
     }
 
-    // Sort files by their earliest timestamp to ensure chronological processing
-    // and deterministic deduplication.
-    files_to_process.sort_by_cached_key(|(path, _)| get_earliest_timestamp(path));
+    files_to_process
+}
+
+fn get_all_usage_entries(claude_path: &Path) -> Vec<UsageEntry> {
+    get_all_usage_entries_with(claude_path, DedupMode::default())
+}
 
-    for (path, project_name) in files_to_process {
-        let entries = parse_jsonl_file(&path, &project_name, &mut processed_hashes);
+fn get_all_usage_entries_with(claude_path: &Path, dedup_mode: DedupMode) -> Vec<UsageEntry> {
+    // The aggregate cache only holds entries deduplicated the default way
+    if dedup_mode == DedupMode::default() {
+        let mut all_entries = get_cached_usage_entries(claude_path, false);
+        apply_provider_pricing(&mut all_entries, &saved_providers());
+        return all_entries;
+    }
+
+    let mut all_entries = Vec::new();
+    let mut processed_hashes = HashSet::new();
+    let api_base_url = get_api_base_url();
+
+    for (path, project_name) in collect_usage_files(claude_path) {
+        let entries = parse_jsonl_file(&path, &project_name, &api_base_url, dedup_mode, &mut processed_hashes);
         all_entries.extend(entries);
     }
+    apply_provider_pricing(&mut all_entries, &saved_providers());
 
     // Sort by timestamp
     all_entries.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
@@ -426,326 +959,618 @@ fn get_all_usage_entries(claude_path: &PathBuf) -> Vec<UsageEntry> {
     all_entries
 }
 
-#[command]
-pub fn get_usage_stats(days: Option<u32>) -> Result<UsageStats, String> {
-    let claude_path = dirs::home_dir()
-        .ok_or("Failed to get home directory")?
-        .join(".claude");
+// Bump whenever parsing, pricing or the cached layout changes so old snapshots are discarded
+const USAGE_AGGREGATE_VERSION: u32 = 4;
 
-    let all_entries = get_all_usage_entries(&claude_path);
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct FileFingerprint {
+    modified_ms: u64,
+    size: u64,
+}
 
-    if all_entries.is_empty() {
-        return Ok(UsageStats {
-            total_cost: 0.0,
-            total_tokens: 0,
-            total_input_tokens: 0,
-            total_output_tokens: 0,
-            total_cache_creation_tokens: 0,
-            total_cache_read_tokens: 0,
-            total_sessions: 0,
-            by_model: vec![],
-            by_date: vec![],
-            by_project: vec![],
-            by_api_base_url: vec![],
-        });
+/// One line of a shard
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedUsageEntry {
+    #[serde(flatten)]
+    entry: UsageEntry,
+    #[serde(default)]
+    estimated_cost: Option<f64>,
+    #[serde(default)]
+    cost_reported: bool,
+    /// Dedup key the entry claimed, seeding incremental scans
+    #[serde(default)]
+    dedup_key: Option<String>,
+}
+
+impl From<UsageEntry> for CachedUsageEntry {
+    fn from(entry: UsageEntry) -> Self {
+        CachedUsageEntry {
+            estimated_cost: entry.estimated_cost,
+            cost_reported: entry.cost_reported,
+            dedup_key: entry.dedup_key.clone(),
+            entry,
+        }
     }
+}
 
-    // Filter by days if specified
-    let filtered_entries = if let Some(days) = days {
-        let cutoff = Local::now().naive_local().date() - chrono::Duration::days(days as i64);
-        all_entries
-            .into_iter()
-            .filter(|e| {
-                if let Ok(dt) = DateTime::parse_from_rfc3339(&e.timestamp) {
-                    dt.naive_local().date() >= cutoff
-                } else {
-                    false
-                }
-            })
-            .collect()
-    } else {
-        all_entries
-    };
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedUsageFile {
+    fingerprint: FileFingerprint,
+    /// Bytes of a plain log already parsed; appended lines are read from here.
+    /// `None` for compressed logs, which are always parsed whole.
+    #[serde(default)]
+    offset: Option<u64>,
+    /// cwd recorded earlier in the log, needed to attribute appended lines
+    #[serde(default)]
+    project_cwd: Option<String>,
+    /// Shard file under `.usage_aggregate/` holding this log's entries
+    shard: String,
+    /// Shard bytes that belong to this snapshot; anything past it is from an
+    /// interrupted scan and is dropped
+    shard_len: u64,
+}
 
-    // Calculate aggregated stats
-    let mut total_cost = 0.0;
-    let mut total_input_tokens = 0u64;
-    let mut total_output_tokens = 0u64;
-    let mut total_cache_creation_tokens = 0u64;
-    let mut total_cache_read_tokens = 0u64;
+/// Index of the usage aggregate, stored in `.usage_aggregate.json` and keyed by log
+/// path so repeat scans only parse logs that are new or have grown. Each log's
+/// parsed entries live in their own NDJSON shard, so a grown log only appends to
+/// its shard and the index never holds entries.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct UsageAggregate {
+    version: u32,
+    #[serde(default)]
+    files: BTreeMap<String, CachedUsageFile>,
+}
 
-    let mut model_stats: HashMap<String, ModelUsage> = HashMap::new();
-    let mut daily_stats: HashMap<String, DailyUsage> = HashMap::new();
-    let mut project_stats: HashMap<String, ProjectUsage> = HashMap::new();
-    let mut api_base_url_stats: HashMap<String, ApiBaseUrlUsage> = HashMap::new();
-    
-    // Track unique sessions for accurate counting
-    let mut unique_sessions: HashSet<String> = HashSet::new();
-    let mut model_sessions: HashMap<String, HashSet<String>> = HashMap::new();
-    let mut project_sessions: HashMap<String, HashSet<String>> = HashMap::new();
-    let mut api_sessions: HashMap<String, HashSet<String>> = HashMap::new();
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UsageAggregateSummary {
+    file_count: usize,
+    entry_count: usize,
+}
 
-    for entry in &filtered_entries {
-        // Update totals
-        total_cost += entry.cost;
-        total_input_tokens += entry.input_tokens;
-        total_output_tokens += entry.output_tokens;
-        total_cache_creation_tokens += entry.cache_creation_tokens;
-        total_cache_read_tokens += entry.cache_read_tokens;
+// Held for a whole cached scan so concurrent scans don't parse the same logs twice
+// or race on writing the snapshot
+static USAGE_AGGREGATE_LOCK: Mutex<()> = Mutex::new(());
 
-        // Track unique sessions
-        unique_sessions.insert(entry.session_id.clone());
-        
-        // Track sessions per model
-        model_sessions
-            .entry(entry.model.clone())
-            .or_insert_with(HashSet::new)
-            .insert(entry.session_id.clone());
-            
-        // Track sessions per project
-        project_sessions
-            .entry(entry.project_path.clone())
-            .or_insert_with(HashSet::new)
-            .insert(entry.session_id.clone());
-            
-        // Track sessions per API base URL
-        api_sessions
-            .entry(entry.api_base_url.clone())
-            .or_insert_with(HashSet::new)
-            .insert(entry.session_id.clone());
+fn usage_aggregate_path(claude_path: &Path) -> PathBuf {
+    claude_path.join(".usage_aggregate.json")
+}
 
-        // Update model stats
-        let model_stat = model_stats
-            .entry(entry.model.clone())
-            .or_insert(ModelUsage {
-                model: entry.model.clone(),
-                total_cost: 0.0,
-                total_tokens: 0,
-                input_tokens: 0,
-                output_tokens: 0,
-                cache_creation_tokens: 0,
-                cache_read_tokens: 0,
-                session_count: 0,
-            });
-        model_stat.total_cost += entry.cost;
-        model_stat.input_tokens += entry.input_tokens;
-        model_stat.output_tokens += entry.output_tokens;
-        model_stat.cache_creation_tokens += entry.cache_creation_tokens;
-        model_stat.cache_read_tokens += entry.cache_read_tokens;
-        model_stat.total_tokens = model_stat.input_tokens + model_stat.output_tokens + model_stat.cache_creation_tokens + model_stat.cache_read_tokens;
-        // Session count will be set later from unique session tracking
+fn usage_aggregate_shard_dir(claude_path: &Path) -> PathBuf {
+    claude_path.join(".usage_aggregate")
+}
 
-        // Update daily stats
-        let date = entry
-            .timestamp
-            .split('T')
-            .next()
-            .unwrap_or(&entry.timestamp)
-            .to_string();
-        let daily_stat = daily_stats.entry(date.clone()).or_insert(DailyUsage {
-            date,
-            total_cost: 0.0,
-            total_tokens: 0,
-            models_used: vec![],
-        });
-        daily_stat.total_cost += entry.cost;
-        daily_stat.total_tokens += entry.input_tokens
-            + entry.output_tokens
-            + entry.cache_creation_tokens
-            + entry.cache_read_tokens;
-        if !daily_stat.models_used.contains(&entry.model) {
-            daily_stat.models_used.push(entry.model.clone());
-        }
+fn usage_aggregate_shard_name(key: &str) -> String {
+    let digest = Sha256::digest(key.as_bytes());
+    let hex: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}.ndjson", hex)
+}
 
-        // Update project stats
-        let project_stat =
-            project_stats
-                .entry(entry.project_path.clone())
-                .or_insert(ProjectUsage {
-                    project_path: entry.project_path.clone(),
-                    project_name: entry
-                        .project_path
-                        .split('/')
-                        .last()
-                        .unwrap_or(&entry.project_path)
-                        .to_string(),
-                    total_cost: 0.0,
-                    total_tokens: 0,
-                    session_count: 0,
-                    last_used: entry.timestamp.clone(),
-                });
-        project_stat.total_cost += entry.cost;
-        project_stat.total_tokens += entry.input_tokens
-            + entry.output_tokens
-            + entry.cache_creation_tokens
-            + entry.cache_read_tokens;
-        // Session count will be set later from unique session tracking
-        if entry.timestamp > project_stat.last_used {
-            project_stat.last_used = entry.timestamp.clone();
-        }
+fn file_fingerprint(path: &Path) -> Option<FileFingerprint> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(std::time::UNIX_EPOCH).ok()?;
+    Some(FileFingerprint {
+        modified_ms: modified.as_millis() as u64,
+        size: metadata.len(),
+    })
+}
 
-        // Update API base URL stats
-        let api_base_url_stat = api_base_url_stats
-            .entry(entry.api_base_url.clone())
-            .or_insert(ApiBaseUrlUsage {
-                api_base_url: entry.api_base_url.clone(),
-                total_cost: 0.0,
-                total_tokens: 0,
-                input_tokens: 0,
-                output_tokens: 0,
-                cache_creation_tokens: 0,
-                cache_read_tokens: 0,
-                session_count: 0,
-            });
-        api_base_url_stat.total_cost += entry.cost;
-        api_base_url_stat.input_tokens += entry.input_tokens;
-        api_base_url_stat.output_tokens += entry.output_tokens;
-        api_base_url_stat.cache_creation_tokens += entry.cache_creation_tokens;
-        api_base_url_stat.cache_read_tokens += entry.cache_read_tokens;
-        api_base_url_stat.total_tokens = api_base_url_stat.input_tokens + api_base_url_stat.output_tokens + api_base_url_stat.cache_creation_tokens + api_base_url_stat.cache_read_tokens;
-        // Session count will be set later from unique session tracking
+// A missing, unreadable or outdated snapshot is just an empty one
+fn load_usage_aggregate(claude_path: &Path) -> UsageAggregate {
+    let path = usage_aggregate_path(claude_path);
+    let aggregate = match fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+            log::warn!("Ignoring invalid usage aggregate {}: {}", path.display(), e);
+            UsageAggregate::default()
+        }),
+        Err(_) => UsageAggregate::default(),
+    };
+
+    if aggregate.version == USAGE_AGGREGATE_VERSION {
+        aggregate
+    } else {
+        UsageAggregate::default()
     }
+}
 
-    let total_tokens = total_input_tokens
-        + total_output_tokens
-        + total_cache_creation_tokens
-        + total_cache_read_tokens;
-    let total_sessions = unique_sessions.len() as u64;
+fn save_usage_aggregate(claude_path: &Path, aggregate: &UsageAggregate) -> Result<(), CommandError> {
+    let content = serde_json::to_vec(aggregate)
+        .map_err(|e| CommandError::Parse(format!("Failed to serialize usage aggregate: {}", e)))?;
+    write_usage_file_atomic(&usage_aggregate_path(claude_path), &content)
+}
 
-    // Set correct session counts and convert hashmaps to sorted vectors
-    let mut by_model: Vec<ModelUsage> = model_stats.into_iter().map(|(model, mut stat)| {
-        stat.session_count = model_sessions.get(&model).map(|s| s.len()).unwrap_or(0) as u64;
-        stat
-    }).collect();
-    by_model.sort_by(|a, b| b.total_cost.partial_cmp(&a.total_cost).unwrap());
+fn shard_lines(entries: &[CachedUsageEntry]) -> Result<Vec<u8>, CommandError> {
+    let mut content = Vec::new();
+    for entry in entries {
+        serde_json::to_writer(&mut content, entry)
+            .map_err(|e| CommandError::Parse(format!("Failed to serialize usage aggregate: {}", e)))?;
+        content.push(b'\n');
+    }
+    Ok(content)
+}
 
-    let mut by_date: Vec<DailyUsage> = daily_stats.into_values().collect();
-    by_date.sort_by(|a, b| b.date.cmp(&a.date));
+// The first `shard_len` bytes of a shard, or None when it is missing or damaged
+fn read_usage_shard(shard_dir: &Path, cached: &CachedUsageFile) -> Option<Vec<CachedUsageEntry>> {
+    let file = fs::File::open(shard_dir.join(&cached.shard)).ok()?;
+    if file.metadata().ok()?.len() < cached.shard_len {
+        return None;
+    }
+    BufReader::new(std::io::Read::take(file, cached.shard_len))
+        .lines()
+        .map(|line| serde_json::from_str(&line.ok()?).ok())
+        .collect()
+}
 
-    let mut by_project: Vec<ProjectUsage> = project_stats.into_iter().map(|(project_path, mut stat)| {
-        stat.session_count = project_sessions.get(&project_path).map(|s| s.len()).unwrap_or(0) as u64;
-        stat
-    }).collect();
-    by_project.sort_by(|a, b| b.total_cost.partial_cmp(&a.total_cost).unwrap());
+// Write a freshly parsed log's shard and return its length
+fn write_usage_shard(shard_dir: &Path, shard: &str, entries: &[CachedUsageEntry]) -> Result<u64, CommandError> {
+    let content = shard_lines(entries)?;
+    write_usage_file_atomic(&shard_dir.join(shard), &content)?;
+    Ok(content.len() as u64)
+}
 
-    let mut by_api_base_url: Vec<ApiBaseUrlUsage> = api_base_url_stats.into_iter().map(|(api_url, mut stat)| {
-        stat.session_count = api_sessions.get(&api_url).map(|s| s.len()).unwrap_or(0) as u64;
-        stat
-    }).collect();
-    by_api_base_url.sort_by(|a, b| b.total_cost.partial_cmp(&a.total_cost).unwrap());
+// Append a grown log's new entries to its shard and return the new length
+fn append_usage_shard(shard_dir: &Path, cached: &CachedUsageFile, entries: &[CachedUsageEntry]) -> Result<u64, CommandError> {
+    if entries.is_empty() {
+        return Ok(cached.shard_len);
+    }
+    let path = shard_dir.join(&cached.shard);
+    let content = shard_lines(entries)?;
+    fs::OpenOptions::new()
+        .write(true)
+        .open(&path)
+        .and_then(|mut file| {
+            // Drop whatever an interrupted scan wrote past the indexed length
+            file.set_len(cached.shard_len)?;
+            file.seek(SeekFrom::End(0))?;
+            file.write_all(&content)?;
+            file.sync_data()
+        })
+        .map_err(|e| CommandError::FileWrite(format!("Failed to append to {}: {}", path.display(), e)))?;
+    Ok(cached.shard_len + content.len() as u64)
+}
 
-    Ok(UsageStats {
-        total_cost,
-        total_tokens,
-        total_input_tokens,
-        total_output_tokens,
-        total_cache_creation_tokens,
-        total_cache_read_tokens,
-        total_sessions,
-        by_model,
-        by_date,
-        by_project,
-        by_api_base_url,
-    })
+fn remove_usage_shard(shard_dir: &Path, cached: &CachedUsageFile) {
+    let path = shard_dir.join(&cached.shard);
+    if let Err(e) = fs::remove_file(&path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            log::warn!("Failed to remove usage shard {}: {}", path.display(), e);
+        }
+    }
 }
 
-#[command]
-pub fn get_usage_by_date_range(start_date: String, end_date: String) -> Result<UsageStats, String> {
-    let claude_path = dirs::home_dir()
-        .ok_or("Failed to get home directory")?
-        .join(".claude");
+// Shards no log in the index points at, left behind by rebuilds or failed removals
+fn prune_usage_shards(shard_dir: &Path, aggregate: &UsageAggregate) {
+    let referenced: HashSet<&str> = aggregate.files.values().map(|f| f.shard.as_str()).collect();
+    let stale = fs::read_dir(shard_dir)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter(|entry| !referenced.contains(entry.file_name().to_string_lossy().as_ref()));
+    for entry in stale {
+        if let Err(e) = fs::remove_file(entry.path()) {
+            log::warn!("Failed to remove stale usage shard {}: {}", entry.path().display(), e);
+        }
+    }
+}
 
-    let all_entries = get_all_usage_entries(&claude_path);
+// Default-dedup entries for every usage log, served from the aggregate snapshot.
+// Unchanged logs are not read at all. Logs are append-only, so a plain log that
+// grew is read from its saved byte offset and only its new entries are appended
+// to its shard, and new logs are parsed whole; both are deduped against the
+// persisted keys of every cached log. A log that was removed (e.g. archived) just
+// drops out with its shard, and one that shrank or whose metadata can't be read
+// is parsed whole again; the other logs stay cached. `force_rebuild` (via
+// `rebuild_usage_aggregate`) rescans everything chronologically.
+//
+// Incremental folds dedup a new log against older cached ones even when the new
+// log's lines are earlier, so which copy of a duplicate is kept can differ from a
+// full rescan; totals are the same. Copies that lost to a since-removed log stay
+// hidden until the next full rebuild.
+fn get_cached_usage_entries(claude_path: &Path, force_rebuild: bool) -> Vec<UsageEntry> {
+    get_cached_usage_entries_in(claude_path, force_rebuild, &get_api_base_url(), follow_symlinks())
+}
 
-    // Parse dates
-    let start = NaiveDate::parse_from_str(&start_date, "%Y-%m-%d").or_else(|_| {
-        // Try parsing ISO datetime format
-        DateTime::parse_from_rfc3339(&start_date)
-            .map(|dt| dt.naive_local().date())
-            .map_err(|e| format!("Invalid start date: {}", e))
-    })?;
-    let end = NaiveDate::parse_from_str(&end_date, "%Y-%m-%d").or_else(|_| {
-        // Try parsing ISO datetime format
-        DateTime::parse_from_rfc3339(&end_date)
-            .map(|dt| dt.naive_local().date())
-            .map_err(|e| format!("Invalid end date: {}", e))
-    })?;
+fn get_cached_usage_entries_in(claude_path: &Path, force_rebuild: bool, api_base_url: &str, follow_links: bool) -> Vec<UsageEntry> {
+    let _guard = USAGE_AGGREGATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let api_base_url = api_base_url.to_string();
+    let shard_dir = usage_aggregate_shard_dir(claude_path);
 
-    // Filter entries by date range
-    let filtered_entries: Vec<_> = all_entries
+    let files: Vec<(PathBuf, String, String, Option<FileFingerprint>)> = list_usage_files(claude_path, follow_links)
         .into_iter()
-        .filter(|e| {
-            if let Ok(dt) = DateTime::parse_from_rfc3339(&e.timestamp) {
-                let date = dt.naive_local().date();
-                date >= start && date <= end
+        .map(|(path, project_name)| {
+            let key = path.to_string_lossy().to_string();
+            let fingerprint = file_fingerprint(&path);
+            (path, project_name, key, fingerprint)
+        })
+        .collect();
+
+    let mut aggregate = if force_rebuild {
+        UsageAggregate::default()
+    } else {
+        load_usage_aggregate(claude_path)
+    };
+    let prune = force_rebuild || aggregate.version != USAGE_AGGREGATE_VERSION;
+    aggregate.version = USAGE_AGGREGATE_VERSION;
+
+    let current_keys: HashSet<&str> = files.iter().map(|(_, _, key, _)| key.as_str()).collect();
+    let removed: Vec<String> = aggregate
+        .files
+        .keys()
+        .filter(|key| !current_keys.contains(key.as_str()))
+        .cloned()
+        .collect();
+    let dirty = !removed.is_empty();
+    for key in removed {
+        if let Some(cached) = aggregate.files.remove(&key) {
+            remove_usage_shard(&shard_dir, &cached);
+        }
+    }
+
+    // A log whose shard is missing or damaged is parsed again like a new one
+    let mut cached_entries: HashMap<String, Vec<CachedUsageEntry>> = HashMap::new();
+    aggregate.files.retain(|key, cached| match read_usage_shard(&shard_dir, cached) {
+        Some(entries) => {
+            cached_entries.insert(key.clone(), entries);
+            true
+        }
+        None => false,
+    });
+
+    let changed: Vec<(PathBuf, String, String, Option<FileFingerprint>)> = files
+        .into_iter()
+        .filter(|(_, _, key, fingerprint)| {
+            aggregate.files.get(key).map(|cached| Some(cached.fingerprint)) != Some(*fingerprint)
+                || fingerprint.is_none()
+        })
+        .collect();
+
+    // Entries that couldn't be stored (unreadable metadata or a failed shard write)
+    // are still returned, and their logs are parsed again on the next scan
+    let mut uncached_entries = Vec::new();
+    if !changed.is_empty() {
+        // Grown plain logs keep their cached entries and keys and resume at their offset
+        let (mut resumable, mut reparse): (Vec<_>, Vec<_>) = changed.into_iter().partition(|(_, _, key, fingerprint)| {
+            match (aggregate.files.get(key), fingerprint) {
+                (Some(cached), Some(fingerprint)) => cached.offset.is_some_and(|offset| offset <= fingerprint.size),
+                _ => false,
+            }
+        });
+        for (_, _, key, fingerprint) in &reparse {
+            // Unfingerprinted logs aren't stored again, so their old shard goes now
+            if let Some(cached) = aggregate.files.remove(key).filter(|_| fingerprint.is_none()) {
+                remove_usage_shard(&shard_dir, &cached);
+            }
+            cached_entries.remove(key);
+        }
+        let mut processed_hashes: HashSet<String> = cached_entries
+            .values()
+            .flatten()
+            .filter_map(|cached| cached.dedup_key.clone())
+            .collect();
+
+        resumable.sort_by(|a, b| a.2.cmp(&b.2));
+        for (path, project_name, key, fingerprint) in resumable {
+            let (cached, fingerprint) = match (aggregate.files.get_mut(&key), fingerprint) {
+                (Some(cached), Some(fingerprint)) => (cached, fingerprint),
+                _ => continue,
+            };
+            let offset = cached.offset.unwrap_or(0);
+            let parsed = parse_jsonl_file_from(&path, offset, &project_name, cached.project_cwd.clone(), &api_base_url, &mut processed_hashes)
+                .map_err(|e| CommandError::FileRead(format!("Failed to resume {}: {}", path.display(), e)));
+            let new_entries: Vec<CachedUsageEntry>;
+            let stored = match parsed {
+                Ok((entries, offset, project_cwd)) => {
+                    new_entries = entries.into_iter().map(CachedUsageEntry::from).collect();
+                    append_usage_shard(&shard_dir, cached, &new_entries).map(|shard_len| (offset, project_cwd, shard_len))
+                }
+                Err(e) => {
+                    new_entries = Vec::new();
+                    Err(e)
+                }
+            };
+            match stored {
+                Ok((offset, project_cwd, shard_len)) => {
+                    cached.fingerprint = fingerprint;
+                    cached.offset = Some(offset);
+                    cached.project_cwd = project_cwd;
+                    cached.shard_len = shard_len;
+                    if let Some(entries) = cached_entries.get_mut(&key) {
+                        entries.extend(new_entries);
+                    }
+                }
+                Err(e) => {
+                    // Parse it whole on the next scan
+                    log::warn!("Failed to update usage aggregate for {}: {}", path.display(), e);
+                    aggregate.files.remove(&key);
+                    if let Some(entries) = cached_entries.remove(&key) {
+                        uncached_entries.extend(entries.into_iter().chain(new_entries));
+                    }
+                }
+            }
+        }
+
+        reparse.sort_by_cached_key(|(path, _, _, _)| get_earliest_timestamp(path));
+        for (path, project_name, key, fingerprint) in reparse {
+            let is_plain = path.extension().and_then(|e| e.to_str()) == Some("jsonl");
+            let (entries, offset, project_cwd) = if is_plain {
+                match parse_jsonl_file_from(&path, 0, &project_name, None, &api_base_url, &mut processed_hashes) {
+                    Ok((entries, offset, project_cwd)) => (entries, Some(offset), project_cwd),
+                    Err(_) => (Vec::new(), None, None),
+                }
             } else {
-                false
+                (parse_jsonl_file(&path, &project_name, &api_base_url, DedupMode::default(), &mut processed_hashes), None, None)
+            };
+            let entries: Vec<CachedUsageEntry> = entries.into_iter().map(CachedUsageEntry::from).collect();
+            let fingerprint = match fingerprint {
+                Some(fingerprint) => fingerprint,
+                None => {
+                    uncached_entries.extend(entries);
+                    continue;
+                }
+            };
+
+            let shard = usage_aggregate_shard_name(&key);
+            match write_usage_shard(&shard_dir, &shard, &entries) {
+                Ok(shard_len) => {
+                    aggregate.files.insert(
+                        key.clone(),
+                        CachedUsageFile { fingerprint, offset, project_cwd, shard, shard_len },
+                    );
+                    cached_entries.insert(key, entries);
+                }
+                Err(e) => {
+                    log::warn!("Failed to save usage aggregate for {}: {}", path.display(), e);
+                    uncached_entries.extend(entries);
+                }
             }
+        }
+
+        if let Err(e) = save_usage_aggregate(claude_path, &aggregate) {
+            log::warn!("Failed to save usage aggregate: {}", e);
+        }
+    } else if prune || dirty {
+        if let Err(e) = save_usage_aggregate(claude_path, &aggregate) {
+            log::warn!("Failed to save usage aggregate: {}", e);
+        }
+    }
+    if prune {
+        prune_usage_shards(&shard_dir, &aggregate);
+    }
+
+    let mut all_entries: Vec<UsageEntry> = cached_entries
+        .into_values()
+        .flatten()
+        .chain(uncached_entries)
+        .map(|cached| UsageEntry {
+            api_base_url: api_base_url.clone(),
+            estimated_cost: cached.estimated_cost,
+            cost_reported: cached.cost_reported,
+            dedup_key: cached.dedup_key,
+            ..cached.entry
         })
         .collect();
+    all_entries.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
 
-    if filtered_entries.is_empty() {
-        return Ok(UsageStats {
-            total_cost: 0.0,
-            total_tokens: 0,
-            total_input_tokens: 0,
-            total_output_tokens: 0,
-            total_cache_creation_tokens: 0,
-            total_cache_read_tokens: 0,
-            total_sessions: 0,
-            by_model: vec![],
-            by_date: vec![],
-            by_project: vec![],
-            by_api_base_url: vec![],
-        });
+    all_entries
+}
+
+/// Discard the usage aggregate snapshot and rebuild it from every usage log
+#[command]
+pub fn rebuild_usage_aggregate() -> Result<UsageAggregateSummary, CommandError> {
+    let claude_path = get_claude_config_dir()?;
+    let follow_links = follow_symlinks();
+    let entries = get_cached_usage_entries_in(&claude_path, true, &get_api_base_url(), follow_links);
+
+    Ok(UsageAggregateSummary {
+        file_count: list_usage_files(&claude_path, follow_links).len(),
+        entry_count: entries.len(),
+    })
+}
+
+// Filter entries to those within the last `days` days (local date), or keep all when `days` is None
+fn filter_entries_by_days(entries: Vec<UsageEntry>, days: Option<u32>) -> Vec<UsageEntry> {
+    if let Some(days) = days {
+        let cutoff = Local::now().naive_local().date() - chrono::Duration::days(days as i64);
+        entries
+            .into_iter()
+            .filter(|e| {
+                if let Some(dt) = parse_entry_time(&e.timestamp) {
+                    dt.naive_local().date() >= cutoff
+                } else {
+                    false
+                }
+            })
+            .collect()
+    } else {
+        entries
+    }
+}
+
+// Strip trailing separators so "/a/b/" and "/a/b" group together; a bare root is kept
+fn trim_project_path(path: &str) -> &str {
+    let trimmed = path.trim_end_matches(['/', '\\']);
+    if trimmed.is_empty() { &path[..path.len().min(1)] } else { trimmed }
+}
+
+// Grouping key for a project path; Windows paths are case-insensitive
+fn project_key(path: &str) -> String {
+    if cfg!(target_os = "windows") {
+        path.to_lowercase()
+    } else {
+        path.to_string()
+    }
+}
+
+// Claude Code names project dirs by replacing every non-alphanumeric character with '-'
+pub(crate) fn encode_project_dir_name(path: &str) -> String {
+    path.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+// An encoded dir name (used when a log has no cwd) never contains a path separator
+fn is_encoded_project_name(path: &str) -> bool {
+    !path.contains(['/', '\\'])
+}
+
+fn project_aliases_path(claude_path: &Path) -> PathBuf {
+    claude_path.join("project_aliases.json")
+}
+
+fn load_project_aliases() -> BTreeMap<String, String> {
+    match get_claude_config_dir() {
+        Ok(claude_path) => load_project_aliases_in(&claude_path),
+        Err(_) => BTreeMap::new(),
+    }
+}
+
+// Project path (or path prefix) -> alias; a missing or unreadable file means no aliases
+fn load_project_aliases_in(claude_path: &Path) -> BTreeMap<String, String> {
+    let path = project_aliases_path(claude_path);
+    match fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+            log::warn!("Ignoring invalid project aliases {}: {}", path.display(), e);
+            BTreeMap::new()
+        }),
+        Err(_) => BTreeMap::new(),
+    }
+}
+
+// Alias for a project path: an exact match or the longest prefix ending at a path separator
+fn project_alias<'a>(aliases: &'a BTreeMap<String, String>, project_path: &str) -> Option<&'a str> {
+    let path = project_key(project_path);
+    aliases
+        .iter()
+        .filter(|(prefix, _)| {
+            let prefix = project_key(trim_project_path(prefix));
+            path == prefix
+                || (path.starts_with(&prefix)
+                    && (prefix.ends_with(['/', '\\']) || path[prefix.len()..].starts_with(['/', '\\'])))
+        })
+        .max_by_key(|(prefix, _)| trim_project_path(prefix).len())
+        .map(|(_, alias)| alias.as_str())
+}
+
+/// Project aliases from `project_aliases.json`, keyed by path or path prefix
+#[command]
+pub fn get_project_aliases() -> Result<BTreeMap<String, String>, CommandError> {
+    Ok(load_project_aliases())
+}
+
+/// Show `path` (and every project under it) as `alias` in the project breakdown.
+/// Projects sharing an alias are merged, e.g. a repo before and after a move.
+/// An empty alias removes the mapping.
+#[command]
+pub fn set_project_alias(path: String, alias: String) -> Result<Option<String>, CommandError> {
+    let path = trim_project_path(path.trim()).to_string();
+    if path.is_empty() {
+        return Err(CommandError::Validation("Project path cannot be empty".to_string()));
+    }
+    let alias = alias.trim().to_string();
+    let claude_path = get_claude_config_dir()?;
+
+    let _guard = lock_usage_files()?;
+    let mut aliases = load_project_aliases_in(&claude_path);
+    if alias.is_empty() {
+        aliases.remove(&path);
+    } else {
+        aliases.insert(path, alias.clone());
     }
 
-    // Calculate aggregated stats (same logic as get_usage_stats)
+    let content = serde_json::to_string_pretty(&aliases)
+        .map_err(|e| CommandError::Parse(format!("Failed to serialize project aliases: {}", e)))?;
+    write_usage_file_atomic(&project_aliases_path(&claude_path), content.as_bytes())?;
+
+    Ok(Some(alias).filter(|a| !a.is_empty()))
+}
+
+// Saved providers used to label API base URLs; an unreadable providers file
+// just means no labels
+fn saved_providers() -> Vec<ProviderConfig> {
+    load_providers_from_file().unwrap_or_default()
+}
+
+// Entries whose model is missing ("unknown") or unpriced and that carry no reported cost
+fn is_uncosted(entry: &UsageEntry) -> bool {
+    entry.cost == 0.0 && model_prices(&entry.model).is_none()
+}
+
+// Aggregate entries into the full UsageStats breakdown shared by all stats commands
+fn aggregate_usage_stats(entries: &[UsageEntry]) -> UsageStats {
+    aggregate_usage_stats_with(entries, false, &load_project_aliases())
+}
+
+fn aggregate_usage_stats_with(
+    entries: &[UsageEntry],
+    count_only_billed_sessions: bool,
+    aliases: &BTreeMap<String, String>,
+) -> UsageStats {
+    // Calculate aggregated stats
     let mut total_cost = 0.0;
     let mut total_input_tokens = 0u64;
     let mut total_output_tokens = 0u64;
     let mut total_cache_creation_tokens = 0u64;
     let mut total_cache_read_tokens = 0u64;
+    let mut unknown_model_token_count = 0u64;
 
     let mut model_stats: HashMap<String, ModelUsage> = HashMap::new();
     let mut daily_stats: HashMap<String, DailyUsage> = HashMap::new();
     let mut project_stats: HashMap<String, ProjectUsage> = HashMap::new();
     let mut api_base_url_stats: HashMap<String, ApiBaseUrlUsage> = HashMap::new();
     
-    // Track unique sessions for accurate counting  
+    // Track unique sessions for accurate counting
     let mut unique_sessions: HashSet<String> = HashSet::new();
     let mut model_sessions: HashMap<String, HashSet<String>> = HashMap::new();
     let mut project_sessions: HashMap<String, HashSet<String>> = HashMap::new();
     let mut api_sessions: HashMap<String, HashSet<String>> = HashMap::new();
 
-    for entry in &filtered_entries {
+    // Logs without a cwd fall back to the encoded project dir name; map those back
+    // to the real path seen in other entries so both forms land in one row
+    let decoded_projects: HashMap<String, &str> = entries
+        .iter()
+        .map(|entry| trim_project_path(&entry.project_path))
+        .filter(|path| !is_encoded_project_name(path))
+        .map(|path| (encode_project_dir_name(path), path))
+        .collect();
+
+    for entry in entries {
+        let project_path = trim_project_path(&entry.project_path);
+        let project_path = decoded_projects.get(project_path).copied().unwrap_or(project_path);
+        // Aliased projects group under the alias, merging every path that maps to it
+        let alias = project_alias(aliases, project_path);
+        let project_key = match alias {
+            Some(alias) => format!("alias:{}", alias),
+            None => project_key(project_path),
+        };
+
         // Update totals
         total_cost += entry.cost;
-        total_input_tokens += entry.input_tokens;
-        total_output_tokens += entry.output_tokens;
-        total_cache_creation_tokens += entry.cache_creation_tokens;
-        total_cache_read_tokens += entry.cache_read_tokens;
+        total_input_tokens = total_input_tokens.saturating_add(entry.input_tokens);
+        total_output_tokens = total_output_tokens.saturating_add(entry.output_tokens);
+        total_cache_creation_tokens = total_cache_creation_tokens.saturating_add(entry.cache_creation_tokens);
+        total_cache_read_tokens = total_cache_read_tokens.saturating_add(entry.cache_read_tokens);
 
-        // Track unique sessions
-        unique_sessions.insert(entry.session_id.clone());
-        
-        // Track sessions per model
-        model_sessions
-            .entry(entry.model.clone())
-            .or_insert_with(HashSet::new)
-            .insert(entry.session_id.clone());
-            
-        // Track sessions per project
-        project_sessions
-            .entry(entry.project_path.clone())
-            .or_insert_with(HashSet::new)
-            .insert(entry.session_id.clone());
+        // Track unique sessions (only through billed entries when requested)
+        if !count_only_billed_sessions || entry.cost > 0.0 {
+            unique_sessions.insert(entry.session_id.clone());
             
-        // Track sessions per API base URL
-        api_sessions
-            .entry(entry.api_base_url.clone())
-            .or_insert_with(HashSet::new)
-            .insert(entry.session_id.clone());
+            // Track sessions per model
+            model_sessions
+                .entry(entry.model.clone())
+                .or_insert_with(HashSet::new)
+                .insert(entry.session_id.clone());
+                
+            // Track sessions per project
+            project_sessions
+                .entry(project_key.clone())
+                .or_insert_with(HashSet::new)
+                .insert(entry.session_id.clone());
+                
+            // Track sessions per API base URL
+            api_sessions
+                .entry(entry.api_base_url.clone())
+                .or_insert_with(HashSet::new)
+                .insert(entry.session_id.clone());
+        }
 
         // Update model stats
         let model_stat = model_stats
@@ -759,15 +1584,20 @@ pub fn get_usage_by_date_range(start_date: String, end_date: String) -> Result<U
                 cache_creation_tokens: 0,
                 cache_read_tokens: 0,
                 session_count: 0,
+                uncosted: false,
             });
         model_stat.total_cost += entry.cost;
-        model_stat.input_tokens += entry.input_tokens;
-        model_stat.output_tokens += entry.output_tokens;
-        model_stat.cache_creation_tokens += entry.cache_creation_tokens;
-        model_stat.cache_read_tokens += entry.cache_read_tokens;
-        model_stat.total_tokens = model_stat.input_tokens + model_stat.output_tokens + model_stat.cache_creation_tokens + model_stat.cache_read_tokens;
+        model_stat.input_tokens = model_stat.input_tokens.saturating_add(entry.input_tokens);
+        model_stat.output_tokens = model_stat.output_tokens.saturating_add(entry.output_tokens);
+        model_stat.cache_creation_tokens = model_stat.cache_creation_tokens.saturating_add(entry.cache_creation_tokens);
+        model_stat.cache_read_tokens = model_stat.cache_read_tokens.saturating_add(entry.cache_read_tokens);
+        model_stat.total_tokens = sum_tokens([model_stat.input_tokens, model_stat.output_tokens, model_stat.cache_creation_tokens, model_stat.cache_read_tokens]);
         // Session count will be set later from unique session tracking
 
+        if is_uncosted(entry) {
+            unknown_model_token_count = unknown_model_token_count.saturating_add(entry.total_tokens());
+        }
+
         // Update daily stats
         let date = entry
             .timestamp
@@ -775,46 +1605,35 @@ pub fn get_usage_by_date_range(start_date: String, end_date: String) -> Result<U
             .next()
             .unwrap_or(&entry.timestamp)
             .to_string();
-        let daily_stat = daily_stats.entry(date.clone()).or_insert(DailyUsage {
-            date,
-            total_cost: 0.0,
-            total_tokens: 0,
-            models_used: vec![],
-        });
-        daily_stat.total_cost += entry.cost;
-        daily_stat.total_tokens += entry.input_tokens
-            + entry.output_tokens
-            + entry.cache_creation_tokens
-            + entry.cache_read_tokens;
-        if !daily_stat.models_used.contains(&entry.model) {
-            daily_stat.models_used.push(entry.model.clone());
-        }
+        let daily_stat = daily_stats
+            .entry(date.clone())
+            .or_insert_with(|| empty_daily_usage(date));
+        add_to_daily_usage(daily_stat, entry);
 
         // Update project stats
         let project_stat =
             project_stats
-                .entry(entry.project_path.clone())
+                .entry(project_key)
                 .or_insert(ProjectUsage {
-                    project_path: entry.project_path.clone(),
-                    project_name: entry
-                        .project_path
-                        .split('/')
-                        .last()
-                        .unwrap_or(&entry.project_path)
+                    project_path: project_path.to_string(),
+                    project_name: alias
+                        .unwrap_or_else(|| project_path.split('/').next_back().unwrap_or(project_path))
                         .to_string(),
                     total_cost: 0.0,
                     total_tokens: 0,
                     session_count: 0,
                     last_used: entry.timestamp.clone(),
+                    tokens_per_dollar: 0.0,
+                    session_id: None,
+                    tags: Vec::new(),
                 });
         project_stat.total_cost += entry.cost;
-        project_stat.total_tokens += entry.input_tokens
-            + entry.output_tokens
-            + entry.cache_creation_tokens
-            + entry.cache_read_tokens;
+        project_stat.total_tokens = project_stat.total_tokens.saturating_add(entry.total_tokens());
         // Session count will be set later from unique session tracking
         if entry.timestamp > project_stat.last_used {
             project_stat.last_used = entry.timestamp.clone();
+            // A merged alias row points at the most recently used path
+            project_stat.project_path = project_path.to_string();
         }
 
         // Update API base URL stats
@@ -829,25 +1648,29 @@ pub fn get_usage_by_date_range(start_date: String, end_date: String) -> Result<U
                 cache_creation_tokens: 0,
                 cache_read_tokens: 0,
                 session_count: 0,
+                provider_name: None,
             });
         api_base_url_stat.total_cost += entry.cost;
-        api_base_url_stat.input_tokens += entry.input_tokens;
-        api_base_url_stat.output_tokens += entry.output_tokens;
-        api_base_url_stat.cache_creation_tokens += entry.cache_creation_tokens;
-        api_base_url_stat.cache_read_tokens += entry.cache_read_tokens;
-        api_base_url_stat.total_tokens = api_base_url_stat.input_tokens + api_base_url_stat.output_tokens + api_base_url_stat.cache_creation_tokens + api_base_url_stat.cache_read_tokens;
+        api_base_url_stat.input_tokens = api_base_url_stat.input_tokens.saturating_add(entry.input_tokens);
+        api_base_url_stat.output_tokens = api_base_url_stat.output_tokens.saturating_add(entry.output_tokens);
+        api_base_url_stat.cache_creation_tokens = api_base_url_stat.cache_creation_tokens.saturating_add(entry.cache_creation_tokens);
+        api_base_url_stat.cache_read_tokens = api_base_url_stat.cache_read_tokens.saturating_add(entry.cache_read_tokens);
+        api_base_url_stat.total_tokens = sum_tokens([api_base_url_stat.input_tokens, api_base_url_stat.output_tokens, api_base_url_stat.cache_creation_tokens, api_base_url_stat.cache_read_tokens]);
         // Session count will be set later from unique session tracking
     }
 
-    let total_tokens = total_input_tokens
-        + total_output_tokens
-        + total_cache_creation_tokens
-        + total_cache_read_tokens;
+    let total_tokens = sum_tokens([
+        total_input_tokens,
+        total_output_tokens,
+        total_cache_creation_tokens,
+        total_cache_read_tokens,
+    ]);
     let total_sessions = unique_sessions.len() as u64;
 
     // Set correct session counts and convert hashmaps to sorted vectors
     let mut by_model: Vec<ModelUsage> = model_stats.into_iter().map(|(model, mut stat)| {
         stat.session_count = model_sessions.get(&model).map(|s| s.len()).unwrap_or(0) as u64;
+        stat.uncosted = stat.total_cost == 0.0 && model_prices(&model).is_none();
         stat
     }).collect();
     by_model.sort_by(|a, b| b.total_cost.partial_cmp(&a.total_cost).unwrap());
@@ -855,19 +1678,22 @@ pub fn get_usage_by_date_range(start_date: String, end_date: String) -> Result<U
     let mut by_date: Vec<DailyUsage> = daily_stats.into_values().collect();
     by_date.sort_by(|a, b| b.date.cmp(&a.date));
 
-    let mut by_project: Vec<ProjectUsage> = project_stats.into_iter().map(|(project_path, mut stat)| {
-        stat.session_count = project_sessions.get(&project_path).map(|s| s.len()).unwrap_or(0) as u64;
+    let mut by_project: Vec<ProjectUsage> = project_stats.into_iter().map(|(project_key, mut stat)| {
+        stat.session_count = project_sessions.get(&project_key).map(|s| s.len()).unwrap_or(0) as u64;
+        stat.tokens_per_dollar = tokens_per_dollar(stat.total_tokens, stat.total_cost);
         stat
     }).collect();
     by_project.sort_by(|a, b| b.total_cost.partial_cmp(&a.total_cost).unwrap());
 
+    let providers = saved_providers();
     let mut by_api_base_url: Vec<ApiBaseUrlUsage> = api_base_url_stats.into_iter().map(|(api_url, mut stat)| {
         stat.session_count = api_sessions.get(&api_url).map(|s| s.len()).unwrap_or(0) as u64;
+        stat.provider_name = find_provider_by_base_url(&providers, &api_url).map(|p| p.name.clone());
         stat
     }).collect();
     by_api_base_url.sort_by(|a, b| b.total_cost.partial_cmp(&a.total_cost).unwrap());
 
-    Ok(UsageStats {
+    UsageStats {
         total_cost,
         total_tokens,
         total_input_tokens,
@@ -875,21 +1701,546 @@ pub fn get_usage_by_date_range(start_date: String, end_date: String) -> Result<U
         total_cache_creation_tokens,
         total_cache_read_tokens,
         total_sessions,
+        unknown_model_token_count,
         by_model,
         by_date,
         by_project,
         by_api_base_url,
-    })
+    }
+}
+
+/// Whether the projects directory exists and holds at least one usage log,
+/// so the UI can tell "no usage yet" apart from an empty dashboard
+#[command]
+pub fn usage_data_available() -> Result<bool, CommandError> {
+    let claude_path = get_claude_config_dir()?;
+    let projects_dir = claude_path.join("projects");
+
+    if !projects_dir.is_dir() {
+        return Ok(false);
+    }
+
+    Ok(walkdir::WalkDir::new(&projects_dir)
+        .follow_links(follow_symlinks())
+        .into_iter()
+        .filter_map(Result::ok)
+        .any(|e| e.file_type().is_file() && is_usage_log(e.path())))
+}
+
+// Keep entries of one project, matching the same path variants that
+// aggregation merges (trailing separators, encoded dir name, case on Windows)
+fn filter_entries_by_project(entries: Vec<UsageEntry>, project_path: &str) -> Vec<UsageEntry> {
+    let target = trim_project_path(project_path);
+    let target_key = project_key(target);
+    let target_encoded = encode_project_dir_name(target);
+
+    entries
+        .into_iter()
+        .filter(|entry| {
+            let path = trim_project_path(&entry.project_path);
+            project_key(path) == target_key || (is_encoded_project_name(path) && path == target_encoded)
+        })
+        .collect()
+}
+
+// Replace reported `costUSD` values with token-based estimates, for gateways that
+// report 0 for usage that is actually billed
+fn recompute_reported_costs(entries: &mut [UsageEntry]) {
+    for entry in entries {
+        if let Some(estimated) = entry.estimated_cost {
+            entry.cost = estimated;
+        }
+    }
+}
+
+/// Usage stats for the last `days` days, optionally limited to one project.
+///
+/// `trust_reported_cost` defaults to true: a `costUSD` in the log (including 0 from
+/// free-tier gateways) is taken as-is. Pass false to price every entry from its
+/// tokens instead; entries for unpriced models keep their reported cost.
+///
+/// `count_only_billed_sessions` defaults to false; when true, session counts skip
+/// sessions without any nonzero-cost entry (see `UsageStats::total_sessions`).
+///
+/// `dedup_mode` defaults to `DedupMode::Loose`, the strategy every other command uses.
+#[command]
+pub fn get_usage_stats(
+    days: Option<u32>,
+    project_path: Option<String>,
+    trust_reported_cost: Option<bool>,
+    count_only_billed_sessions: Option<bool>,
+    dedup_mode: Option<DedupMode>,
+) -> Result<UsageStats, CommandError> {
+    let claude_path = get_claude_config_dir()?;
+
+    Ok(get_usage_stats_in(
+        &claude_path,
+        days,
+        project_path,
+        trust_reported_cost,
+        count_only_billed_sessions,
+        dedup_mode,
+    ))
+}
+
+fn get_usage_stats_in(
+    claude_path: &Path,
+    days: Option<u32>,
+    project_path: Option<String>,
+    trust_reported_cost: Option<bool>,
+    count_only_billed_sessions: Option<bool>,
+    dedup_mode: Option<DedupMode>,
+) -> UsageStats {
+    let mut all_entries = get_all_usage_entries_with(claude_path, dedup_mode.unwrap_or_default());
+    if let Some(project_path) = project_path.as_deref() {
+        all_entries = filter_entries_by_project(all_entries, project_path);
+    }
+    if !trust_reported_cost.unwrap_or(true) {
+        recompute_reported_costs(&mut all_entries);
+    }
+    let filtered_entries = filter_entries_by_days(all_entries, days);
+
+    aggregate_usage_stats_with(
+        &filtered_entries,
+        count_only_billed_sessions.unwrap_or(false),
+        &load_project_aliases_in(claude_path),
+    )
+}
+
+// Fields that identify a copy of the same logged request in another config directory
+type EntryFingerprint = (String, String, String, u64, u64, u64, u64);
+
+fn entry_fingerprint(entry: &UsageEntry) -> EntryFingerprint {
+    (
+        entry.session_id.clone(),
+        entry.timestamp.clone(),
+        entry.model.clone(),
+        entry.input_tokens,
+        entry.output_tokens,
+        entry.cache_creation_tokens,
+        entry.cache_read_tokens,
+    )
+}
+
+/// Combined stats across several Claude config directories (e.g. work and personal).
+/// All logs are processed in one chronological pass with shared deduplication, and an
+/// entry identical to one already read from another directory (a session copied between
+/// them) is counted once.
+#[command]
+pub fn get_usage_stats_multi(config_dirs: Vec<String>, days: Option<u32>) -> Result<UsageStats, CommandError> {
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    for dir in &config_dirs {
+        let dir = PathBuf::from(dir.trim())
+            .canonicalize()
+            .map_err(|e| CommandError::NotFound(format!("Config directory not found: {} ({})", dir, e)))?;
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+    if dirs.is_empty() {
+        return Err(CommandError::Validation("At least one config directory is required".to_string()));
+    }
+
+    let follow_links = follow_symlinks();
+    let mut files: Vec<(PathBuf, String, usize)> = dirs
+        .iter()
+        .enumerate()
+        .flat_map(|(index, dir)| {
+            list_usage_files(dir, follow_links)
+                .into_iter()
+                .map(move |(path, project_name)| (path, project_name, index))
+        })
+        .collect();
+    files.sort_by_cached_key(|(path, _, _)| get_earliest_timestamp(path));
+
+    let api_base_url = get_api_base_url();
+    let mut processed_hashes = HashSet::new();
+    let mut seen: HashMap<EntryFingerprint, usize> = HashMap::new();
+    let mut all_entries = Vec::new();
+    for (path, project_name, dir_index) in files {
+        for entry in parse_jsonl_file(&path, &project_name, &api_base_url, DedupMode::default(), &mut processed_hashes) {
+            // Identical entries within one directory are left to the hash-based dedup above
+            let first_dir = *seen.entry(entry_fingerprint(&entry)).or_insert(dir_index);
+            if first_dir == dir_index {
+                all_entries.push(entry);
+            }
+        }
+    }
+    apply_provider_pricing(&mut all_entries, &saved_providers());
+    all_entries.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+
+    let filtered_entries = filter_entries_by_days(all_entries, days);
+
+    Ok(aggregate_usage_stats(&filtered_entries))
+}
+
+// How many cross-session message ids `get_dedup_diagnostics` lists as examples
+const DEDUP_DIAGNOSTIC_EXAMPLES: usize = 20;
+
+#[derive(Debug, Serialize)]
+pub struct CrossSessionMessageId {
+    message_id: String,
+    sessions: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DedupDiagnostics {
+    total_message_ids: u64,  // distinct ids on entries that carry usage
+    cross_session_message_ids: u64,  // ids seen in more than one session directory
+    examples: Vec<CrossSessionMessageId>,  // the ids spanning the most sessions
+}
+
+/// Data-quality check for the dedup keys: IO tokens are deduplicated per session
+/// (`io:{session}:{id}`) but cache tokens globally (`cache:{id}:{request}`), which is
+/// only consistent if message ids don't recur across sessions (e.g. on resume).
+/// Sessions are identified by their directory, as in the IO key.
+#[command]
+pub fn get_dedup_diagnostics() -> Result<DedupDiagnostics, CommandError> {
+    let claude_path = get_claude_config_dir()?;
+
+    let mut sessions_by_id: HashMap<String, BTreeSet<String>> = HashMap::new();
+    for (path, _) in collect_usage_files(&claude_path) {
+        let session_id = path
+            .parent()
+            .and_then(|p| p.file_name())
+            .and_then(|n| n.to_str())
+            .unwrap_or("unknown")
+            .to_string();
+        let reader = match open_usage_log(&path) {
+            Ok(reader) => reader,
+            Err(_) => continue,
+        };
+
+        for line in reader.lines().map_while(Result::ok) {
+            let message_id = serde_json::from_str::<JsonlEntry>(&line)
+                .ok()
+                .and_then(|entry| entry.message)
+                .filter(|message| message.usage.is_some())
+                .and_then(|message| message.id);
+            if let Some(message_id) = message_id {
+                sessions_by_id.entry(message_id).or_default().insert(session_id.clone());
+            }
+        }
+    }
+
+    let mut cross_session: Vec<CrossSessionMessageId> = sessions_by_id
+        .iter()
+        .filter(|(_, sessions)| sessions.len() > 1)
+        .map(|(message_id, sessions)| CrossSessionMessageId {
+            message_id: message_id.clone(),
+            sessions: sessions.iter().cloned().collect(),
+        })
+        .collect();
+    let cross_session_message_ids = cross_session.len() as u64;
+    cross_session.sort_by(|a, b| {
+        b.sessions.len().cmp(&a.sessions.len()).then_with(|| a.message_id.cmp(&b.message_id))
+    });
+    cross_session.truncate(DEDUP_DIAGNOSTIC_EXAMPLES);
+
+    Ok(DedupDiagnostics {
+        total_message_ids: sessions_by_id.len() as u64,
+        cross_session_message_ids,
+        examples: cross_session,
+    })
+}
+
+// Replaces project paths and API hosts with placeholders for shareable exports.
+// Placeholders hash the original with a per-export salt, so they are consistent
+// within one export (grouping still works) but can't be matched across exports.
+struct ExportAnonymizer {
+    salt: String,
+}
+
+impl ExportAnonymizer {
+    fn new() -> Self {
+        Self { salt: uuid::Uuid::new_v4().to_string() }
+    }
+
+    fn short_hash(&self, value: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.salt.as_bytes());
+        hasher.update(value.as_bytes());
+        format!("{:x}", hasher.finalize())[..8].to_string()
+    }
+
+    fn project(&self, project_path: &str) -> String {
+        format!("project-{}", self.short_hash(trim_project_path(project_path)))
+    }
+
+    // Keep the scheme so http vs https is still visible, drop host, port and path
+    fn api_base_url(&self, url: &str) -> String {
+        match url.split_once("://") {
+            Some((scheme, rest)) => format!("{}://host-{}", scheme, self.short_hash(rest.trim_end_matches('/'))),
+            None => format!("host-{}", self.short_hash(url.trim_end_matches('/'))),
+        }
+    }
+
+    fn entry(&self, entry: &mut UsageEntry) {
+        entry.project_path = self.project(&entry.project_path);
+        entry.api_base_url = self.api_base_url(&entry.api_base_url);
+    }
+
+    fn stats(&self, stats: &mut UsageStats) {
+        for project in &mut stats.by_project {
+            project.project_path = self.project(&project.project_path);
+            project.project_name = project.project_path.clone();
+        }
+        for api in &mut stats.by_api_base_url {
+            api.api_base_url = self.api_base_url(&api.api_base_url);
+            // Provider names are user labels and often name the gateway
+            api.provider_name = None;
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageExportResult {
+    bytes_written: u64,
+    file_path: String,
+}
+
+/// Write the usage stats for the last `days` days to `path` as JSON. With `anonymize`,
+/// project paths and API hosts are replaced by placeholders; numbers are unchanged.
+#[command]
+pub fn export_usage_json(days: Option<u32>, path: String, anonymize: Option<bool>) -> Result<UsageExportResult, CommandError> {
+    let mut stats = get_usage_stats(days, None, None, None, None)?;
+    if anonymize.unwrap_or(false) {
+        ExportAnonymizer::new().stats(&mut stats);
+    }
+
+    let content = serde_json::to_string_pretty(&stats)
+        .map_err(|e| CommandError::Parse(format!("Failed to serialize usage stats: {}", e)))?;
+
+    let file_path = PathBuf::from(&path);
+    if let Some(parent) = file_path.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)
+                .map_err(|e| CommandError::FileWrite(format!("Failed to create export directory: {}", e)))?;
+        }
+    }
+
+    fs::write(&file_path, &content)
+        .map_err(|e| CommandError::FileWrite(format!("Failed to write export file: {}", e)))?;
+
+    // Return a clean absolute path, removing the Windows long path prefix \\?\
+    let absolute_path = file_path.canonicalize().unwrap_or(file_path);
+    let mut path_str = absolute_path.to_string_lossy().to_string();
+    if path_str.starts_with("\\\\?\\") {
+        path_str = path_str[4..].to_string();
+    }
+
+    Ok(UsageExportResult {
+        bytes_written: content.len() as u64,
+        file_path: path_str,
+    })
+}
+
+/// Stream usage entries to `path` as NDJSON (one `UsageEntry` per line).
+/// Entries are written file by file as they are parsed, so archiving years of
+/// history never holds the full set in memory. Returns the number of entries written.
+/// `anonymize` replaces project paths and API hosts like `export_usage_json` does.
+#[command]
+pub fn export_usage_ndjson(path: String, days: Option<u32>, anonymize: Option<bool>) -> Result<u64, CommandError> {
+    let claude_path = get_claude_config_dir()?;
+
+    let file_path = PathBuf::from(&path);
+    if let Some(parent) = file_path.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)
+                .map_err(|e| CommandError::FileWrite(format!("Failed to create export directory: {}", e)))?;
+        }
+    }
+
+    let file = fs::File::create(&file_path)
+        .map_err(|e| CommandError::FileWrite(format!("Failed to create export file: {}", e)))?;
+    let mut writer = BufWriter::new(file);
+
+    let mut processed_hashes = HashSet::new();
+    let mut written: u64 = 0;
+    let api_base_url = get_api_base_url();
+    let anonymizer = anonymize.unwrap_or(false).then(ExportAnonymizer::new);
+    let providers = saved_providers();
+
+    for (log_path, project_name) in collect_usage_files(&claude_path) {
+        let mut entries = parse_jsonl_file(&log_path, &project_name, &api_base_url, DedupMode::default(), &mut processed_hashes);
+        apply_provider_pricing(&mut entries, &providers);
+        for mut entry in filter_entries_by_days(entries, days) {
+            if let Some(anonymizer) = &anonymizer {
+                anonymizer.entry(&mut entry);
+            }
+            serde_json::to_writer(&mut writer, &entry)
+                .map_err(|e| CommandError::Parse(format!("Failed to serialize usage entry: {}", e)))?;
+            writer
+                .write_all(b"\n")
+                .map_err(|e| CommandError::FileWrite(format!("Failed to write export file: {}", e)))?;
+            written += 1;
+        }
+    }
+
+    writer
+        .flush()
+        .map_err(|e| CommandError::FileWrite(format!("Failed to write export file: {}", e)))?;
+
+    Ok(written)
+}
+
+#[command]
+pub fn get_usage_by_date_range(start_date: String, end_date: String) -> Result<UsageStats, CommandError> {
+    let claude_path = get_claude_config_dir()?;
+
+    let all_entries = get_all_usage_entries(&claude_path);
+
+    // Parse dates
+    let start = NaiveDate::parse_from_str(&start_date, "%Y-%m-%d").or_else(|_| {
+        // Try parsing ISO datetime format
+        DateTime::parse_from_rfc3339(&start_date)
+            .map(|dt| dt.naive_local().date())
+            .map_err(|e| CommandError::Validation(format!("Invalid start date: {}", e)))
+    })?;
+    let end = NaiveDate::parse_from_str(&end_date, "%Y-%m-%d").or_else(|_| {
+        // Try parsing ISO datetime format
+        DateTime::parse_from_rfc3339(&end_date)
+            .map(|dt| dt.naive_local().date())
+            .map_err(|e| CommandError::Validation(format!("Invalid end date: {}", e)))
+    })?;
+
+    // Filter entries by date range
+    let filtered_entries: Vec<_> = all_entries
+        .into_iter()
+        .filter(|e| {
+            if let Some(dt) = parse_entry_time(&e.timestamp) {
+                let date = dt.naive_local().date();
+                date >= start && date <= end
+            } else {
+                false
+            }
+        })
+        .collect();
+
+    Ok(aggregate_usage_stats(&filtered_entries))
+}
+
+#[derive(Debug, Serialize)]
+pub struct MonthlyStatement {
+    year: i32,
+    month: u32,
+    period_start: String,  // YYYY-MM-DD, first day of the month
+    period_end: String,  // YYYY-MM-DD, last day of the month
+    total_cost: f64,
+    total_tokens: u64,
+    session_count: u64,
+    line_items: Vec<ModelUsage>,  // per model, highest cost first
+    by_project: Vec<ProjectUsage>,
+    daily_subtotals: Vec<DailyUsage>,  // every day of the month, zero-filled
+}
+
+/// Statement for one calendar month (local time) with per-model line items,
+/// per-project breakdown and daily subtotals. Months without usage return zeros.
+#[command]
+pub fn get_monthly_statement(year: i32, month: u32) -> Result<MonthlyStatement, CommandError> {
+    let period_start = NaiveDate::from_ymd_opt(year, month, 1)
+        .ok_or_else(|| CommandError::Validation(format!("Invalid month: {}-{:02}", year, month)))?;
+    let next_month = if month == 12 {
+        NaiveDate::from_ymd_opt(year + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd_opt(year, month + 1, 1)
+    }
+    .ok_or_else(|| CommandError::Validation(format!("Invalid month: {}-{:02}", year, month)))?;
+    let period_end = next_month - Duration::days(1);
+
+    let claude_path = get_claude_config_dir()?;
+
+    let entries: Vec<UsageEntry> = get_all_usage_entries(&claude_path)
+        .into_iter()
+        .filter(|e| {
+            parse_entry_time(&e.timestamp)
+                .map(|dt| {
+                    let date = dt.with_timezone(&Local).date_naive();
+                    date >= period_start && date <= period_end
+                })
+                .unwrap_or(false)
+        })
+        .collect();
+
+    let stats = aggregate_usage_stats(&entries);
+    let days_in_month = (period_end - period_start).num_days() as u32 + 1;
+
+    Ok(MonthlyStatement {
+        year,
+        month,
+        period_start: period_start.format("%Y-%m-%d").to_string(),
+        period_end: period_end.format("%Y-%m-%d").to_string(),
+        total_cost: stats.total_cost,
+        total_tokens: stats.total_tokens,
+        session_count: stats.total_sessions,
+        line_items: stats.by_model,
+        by_project: stats.by_project,
+        daily_subtotals: build_daily_series(&entries, days_in_month, period_end)?,
+    })
+}
+
+/// Newest `limit` entries, newest first. Logs are read in order of modification
+/// time and the scan stops once no remaining file can hold a newer entry, so
+/// polling this doesn't re-read the whole history.
+#[command]
+pub fn get_recent_usage(limit: usize) -> Result<Vec<UsageEntry>, CommandError> {
+    if limit == 0 {
+        return Ok(Vec::new());
+    }
+    let claude_path = get_claude_config_dir()?;
+
+    let mut files: Vec<(PathBuf, String, Option<DateTime<Local>>)> = list_usage_files(&claude_path, follow_symlinks())
+        .into_iter()
+        .map(|(path, project_name)| {
+            let modified = fs::metadata(&path)
+                .and_then(|m| m.modified())
+                .ok()
+                .map(DateTime::<Local>::from);
+            (path, project_name, modified)
+        })
+        .collect();
+    // Files without a readable mtime sort last but are still scanned if needed
+    files.sort_by_key(|f| Reverse(f.2));
+
+    let mut entries: Vec<UsageEntry> = Vec::new();
+    let mut processed_hashes = HashSet::new();
+    let api_base_url = get_api_base_url();
+    for (path, project_name, modified) in files {
+        if entries.len() >= limit {
+            // Entries can't be newer than the last write to their file
+            let oldest_kept = parse_entry_time(&entries[limit - 1].timestamp);
+            if let (Some(modified), Some(oldest_kept)) = (modified, oldest_kept) {
+                if modified < oldest_kept {
+                    break;
+                }
+            }
+        }
+
+        entries.extend(parse_jsonl_file(&path, &project_name, &api_base_url, DedupMode::default(), &mut processed_hashes));
+        entries.sort_by(|a, b| {
+            parse_entry_time(&b.timestamp)
+                .cmp(&parse_entry_time(&a.timestamp))
+                .then_with(|| b.timestamp.cmp(&a.timestamp))
+        });
+        entries.truncate(limit);
+    }
+    apply_provider_pricing(&mut entries, &saved_providers());
+
+    Ok(entries)
 }
 
 #[command]
 pub fn get_usage_details(
     project_path: Option<String>,
     date: Option<String>,
-) -> Result<Vec<UsageEntry>, String> {
-    let claude_path = dirs::home_dir()
-        .ok_or("Failed to get home directory")?
-        .join(".claude");
+    month: Option<String>,
+    week: Option<String>,
+) -> Result<Vec<UsageEntry>, CommandError> {
+    let claude_path = get_claude_config_dir()?;
+
+    // Validate the period filters before scanning the logs
+    let month_filter = month.as_deref().map(parse_month_filter).transpose()?;
+    let week_filter = week.as_deref().map(parse_iso_week_filter).transpose()?;
 
     let mut all_entries = get_all_usage_entries(&claude_path);
 
@@ -903,14 +2254,50 @@ pub fn get_usage_details(
         all_entries.retain(|e| e.timestamp.starts_with(&date));
     }
 
+    // Month and week membership is checked on the local date, not the raw timestamp prefix
+    if month_filter.is_some() || week_filter.is_some() {
+        all_entries.retain(|e| {
+            let local_date = match parse_entry_time(&e.timestamp) {
+                Some(dt) => dt.with_timezone(&Local).date_naive(),
+                None => return false,
+            };
+            let in_month = month_filter
+                .is_none_or(|(year, month)| local_date.year() == year && local_date.month() == month);
+            let in_week = week_filter.is_none_or(|(year, week)| {
+                let iso = local_date.iso_week();
+                iso.year() == year && iso.week() == week
+            });
+            in_month && in_week
+        });
+    }
+
     Ok(all_entries)
 }
 
+// Parse a "YYYY-MM" month filter into (year, month)
+fn parse_month_filter(month: &str) -> Result<(i32, u32), CommandError> {
+    NaiveDate::parse_from_str(&format!("{}-01", month.trim()), "%Y-%m-%d")
+        .map(|d| (d.year(), d.month()))
+        .map_err(|_| CommandError::Validation(format!("Invalid month '{}', expected YYYY-MM", month)))
+}
+
+// Parse an ISO year-week filter such as "2024-W07" into (iso_year, week)
+fn parse_iso_week_filter(week: &str) -> Result<(i32, u32), CommandError> {
+    let invalid = || CommandError::Validation(format!("Invalid ISO week '{}', expected YYYY-Www", week));
+
+    let (year, week_num) = week.trim().split_once("-W").ok_or_else(invalid)?;
+    let year: i32 = year.parse().map_err(|_| invalid())?;
+    let week_num: u32 = week_num.parse().map_err(|_| invalid())?;
+
+    // Rejects week 53 in years that only have 52 ISO weeks
+    NaiveDate::from_isoywd_opt(year, week_num, chrono::Weekday::Mon).ok_or_else(invalid)?;
+
+    Ok((year, week_num))
+}
+
 #[command]
-pub fn get_today_usage_stats() -> Result<UsageStats, String> {
-    let claude_path = dirs::home_dir()
-        .ok_or("Failed to get home directory")?
-        .join(".claude");
+pub fn get_today_usage_stats() -> Result<UsageStats, CommandError> {
+    let claude_path = get_claude_config_dir()?;
 
     let all_entries = get_all_usage_entries(&claude_path);
 
@@ -921,7 +2308,7 @@ pub fn get_today_usage_stats() -> Result<UsageStats, String> {
     let today_entries: Vec<_> = all_entries
         .into_iter()
         .filter(|e| {
-            if let Ok(dt) = DateTime::parse_from_rfc3339(&e.timestamp) {
+            if let Some(dt) = parse_entry_time(&e.timestamp) {
                 dt.naive_local().date() == today
             } else {
                 false
@@ -929,280 +2316,1806 @@ pub fn get_today_usage_stats() -> Result<UsageStats, String> {
         })
         .collect();
 
-    if today_entries.is_empty() {
-        return Ok(UsageStats {
-            total_cost: 0.0,
-            total_tokens: 0,
-            total_input_tokens: 0,
-            total_output_tokens: 0,
-            total_cache_creation_tokens: 0,
-            total_cache_read_tokens: 0,
-            total_sessions: 0,
-            by_model: vec![],
-            by_date: vec![],
-            by_project: vec![],
-            by_api_base_url: vec![],
-        });
-    }
+    Ok(aggregate_usage_stats(&today_entries))
+}
+
+/// Event emitted with the `DailyCapStatus` when today's spend first exceeds the daily cap
+pub const DAILY_CAP_EXCEEDED_EVENT: &str = "daily-cap-exceeded";
+
+// Day the cap was last reported as exceeded, so polling emits once per day
+static DAILY_CAP_NOTIFIED: Mutex<Option<NaiveDate>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyCapStatus {
+    date: String,  // YYYY-MM-DD, local
+    cap_usd: Option<f64>,  // None when no cap is configured
+    spent_usd: f64,
+    exceeded: bool,
+    over_by_usd: f64,  // 0 when within the cap
+    percent_used: Option<f64>,
+}
+
+#[command]
+pub fn get_daily_cap() -> Result<Option<f64>, CommandError> {
+    Ok(load_usage_prefs().daily_cap_usd)
+}
+
+/// Set the daily spending cap checked by `check_daily_cap`; `None` removes it
+#[command]
+pub fn set_daily_cap(usd: Option<f64>) -> Result<Option<f64>, CommandError> {
+    if let Some(cap) = usd {
+        if !cap.is_finite() || cap <= 0.0 {
+            return Err(CommandError::Validation("Daily cap must be greater than zero".to_string()));
+        }
+    }
+
+    let _guard = lock_usage_files()?;
+    let mut prefs = load_usage_prefs();
+    prefs.daily_cap_usd = usd;
+    save_usage_prefs(&prefs)?;
+
+    Ok(usd)
+}
+
+/// Compare today's spend with the daily cap. The first check of a day that finds
+/// the cap exceeded emits `DAILY_CAP_EXCEEDED_EVENT`, so the UI can poll this.
+#[command]
+pub fn check_daily_cap(app: AppHandle) -> Result<DailyCapStatus, CommandError> {
+    let cap = load_usage_prefs().daily_cap_usd.filter(|cap| *cap > 0.0);
+    let today = Local::now().date_naive();
+    let spent = get_today_usage_stats()?.total_cost;
+
+    let exceeded = cap.is_some_and(|cap| spent > cap);
+    let status = DailyCapStatus {
+        date: today.format("%Y-%m-%d").to_string(),
+        cap_usd: cap,
+        spent_usd: spent,
+        exceeded,
+        over_by_usd: cap.map(|cap| (spent - cap).max(0.0)).unwrap_or(0.0),
+        percent_used: cap.map(|cap| spent / cap * 100.0),
+    };
+
+    if exceeded {
+        let mut notified = DAILY_CAP_NOTIFIED.lock().unwrap_or_else(|e| e.into_inner());
+        if *notified != Some(today) {
+            *notified = Some(today);
+            if let Err(e) = app.emit(DAILY_CAP_EXCEEDED_EVENT, status.clone()) {
+                log::warn!("Failed to emit {}: {}", DAILY_CAP_EXCEEDED_EVENT, e);
+            }
+        }
+    }
+
+    Ok(status)
+}
+
+#[command]
+pub fn get_session_stats(
+    since: Option<String>,
+    until: Option<String>,
+    order: Option<String>,
+) -> Result<Vec<ProjectUsage>, CommandError> {
+    let claude_path = get_claude_config_dir()?;
+
+    let all_entries = get_all_usage_entries(&claude_path);
+
+    let since_date = since.and_then(|s| NaiveDate::parse_from_str(&s, "%Y%m%d").ok());
+    let until_date = until.and_then(|s| NaiveDate::parse_from_str(&s, "%Y%m%d").ok());
+
+    let filtered_entries: Vec<_> = all_entries
+        .into_iter()
+        .filter(|e| {
+            if let Some(dt) = parse_entry_time(&e.timestamp) {
+                let date = dt.date_naive();
+                let is_after_since = since_date.map_or(true, |s| date >= s);
+                let is_before_until = until_date.map_or(true, |u| date <= u);
+                is_after_since && is_before_until
+            } else {
+                false
+            }
+        })
+        .collect();
+
+    let session_tags = load_session_tags();
+    let mut session_stats: HashMap<String, ProjectUsage> = HashMap::new();
+    for entry in &filtered_entries {
+        let session_key = format!("{}/{}", entry.project_path, entry.session_id);
+        let project_stat = session_stats
+            .entry(session_key)
+            .or_insert_with(|| ProjectUsage {
+                project_path: entry.project_path.clone(),
+                project_name: entry
+                    .project_path
+                    .split('/')
+                    .next_back()
+                    .unwrap_or(&entry.project_path)
+                    .to_string(),
+                total_cost: 0.0,
+                total_tokens: 0,
+                session_count: 0, // In this context, this counts entries per session
+                last_used: " ".to_string(),
+                tokens_per_dollar: 0.0,
+                session_id: Some(entry.session_id.clone()),
+                tags: session_tags.get(&entry.session_id).cloned().unwrap_or_default(),
+            });
+
+        project_stat.total_cost += entry.cost;
+        project_stat.total_tokens = project_stat.total_tokens.saturating_add(entry.total_tokens());
+        project_stat.session_count += 1;
+        if entry.timestamp > project_stat.last_used {
+            project_stat.last_used = entry.timestamp.clone();
+        }
+    }
+
+    let mut by_session: Vec<ProjectUsage> = session_stats
+        .into_values()
+        .map(|mut stat| {
+            stat.tokens_per_dollar = tokens_per_dollar(stat.total_tokens, stat.total_cost);
+            stat
+        })
+        .collect();
+
+    // Sort by last_used date
+    if let Some(order_str) = order {
+        if order_str == "asc" {
+            by_session.sort_by(|a, b| a.last_used.cmp(&b.last_used));
+        } else {
+            by_session.sort_by(|a, b| b.last_used.cmp(&a.last_used));
+        }
+    } else {
+        // Default to descending
+        by_session.sort_by(|a, b| b.last_used.cmp(&a.last_used));
+    }
+
+    Ok(by_session)
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionSummary {
+    session_id: String,
+    project_path: String,  // project of the session's earliest entry
+    first_timestamp: String,
+    last_timestamp: String,
+    entry_count: u64,
+    total_tokens: u64,
+    total_cost: f64,
+}
+
+/// One row per session with its time span and totals, most recently active first.
+/// `since`/`until` are inclusive `YYYYMMDD` dates, as in `get_session_stats`.
+#[command]
+pub fn list_sessions(since: Option<String>, until: Option<String>) -> Result<Vec<SessionSummary>, CommandError> {
+    let claude_path = get_claude_config_dir()?;
+
+    let all_entries = get_all_usage_entries(&claude_path);
+
+    let since_date = since.and_then(|s| NaiveDate::parse_from_str(&s, "%Y%m%d").ok());
+    let until_date = until.and_then(|s| NaiveDate::parse_from_str(&s, "%Y%m%d").ok());
+
+    // session id -> (summary, earliest and latest entry times)
+    let mut sessions: HashMap<String, (SessionSummary, DateTime<FixedOffset>, DateTime<FixedOffset>)> = HashMap::new();
+    for entry in &all_entries {
+        let time = match parse_entry_time(&entry.timestamp) {
+            Some(time) => time,
+            None => continue,
+        };
+        let date = time.date_naive();
+        if since_date.is_some_and(|s| date < s) || until_date.is_some_and(|u| date > u) {
+            continue;
+        }
+
+        let (summary, first, last) = sessions.entry(entry.session_id.clone()).or_insert_with(|| {
+            (
+                SessionSummary {
+                    session_id: entry.session_id.clone(),
+                    project_path: entry.project_path.clone(),
+                    first_timestamp: entry.timestamp.clone(),
+                    last_timestamp: entry.timestamp.clone(),
+                    entry_count: 0,
+                    total_tokens: 0,
+                    total_cost: 0.0,
+                },
+                time,
+                time,
+            )
+        });
+        summary.entry_count += 1;
+        summary.total_tokens = summary.total_tokens.saturating_add(entry.total_tokens());
+        summary.total_cost += entry.cost;
+        if time < *first {
+            *first = time;
+            summary.first_timestamp = entry.timestamp.clone();
+            summary.project_path = entry.project_path.clone();
+        }
+        if time > *last {
+            *last = time;
+            summary.last_timestamp = entry.timestamp.clone();
+        }
+    }
+
+    let mut sessions: Vec<(SessionSummary, DateTime<FixedOffset>, DateTime<FixedOffset>)> = sessions.into_values().collect();
+    sessions.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.session_id.cmp(&b.0.session_id)));
+
+    Ok(sessions.into_iter().map(|(summary, _, _)| summary).collect())
+}
+
+// Bucket for entries whose session has no tags
+const UNTAGGED_BUCKET: &str = "untagged";
+
+fn session_tags_path() -> Result<PathBuf, CommandError> {
+    Ok(get_claude_config_dir()?.join("session_tags.json"))
+}
+
+// Session id -> tags; a missing or unreadable file means no tags
+fn load_session_tags() -> HashMap<String, Vec<String>> {
+    let path = match session_tags_path() {
+        Ok(path) => path,
+        Err(_) => return HashMap::new(),
+    };
+
+    match fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+            log::warn!("Ignoring invalid session tags {}: {}", path.display(), e);
+            HashMap::new()
+        }),
+        Err(_) => HashMap::new(),
+    }
+}
+
+/// Replace a session's tags (trimmed, de-duplicated); an empty list removes them
+#[command]
+pub fn set_session_tags(session_id: String, tags: Vec<String>) -> Result<Vec<String>, CommandError> {
+    let mut cleaned: Vec<String> = Vec::new();
+    for tag in tags.iter().map(|t| t.trim()).filter(|t| !t.is_empty()) {
+        if !cleaned.iter().any(|c| c == tag) {
+            cleaned.push(tag.to_string());
+        }
+    }
+
+    let _guard = lock_usage_files()?;
+    // BTreeMap keeps the file diff-friendly
+    let mut all_tags: BTreeMap<String, Vec<String>> = load_session_tags().into_iter().collect();
+    if cleaned.is_empty() {
+        all_tags.remove(&session_id);
+    } else {
+        all_tags.insert(session_id, cleaned.clone());
+    }
+
+    let content = serde_json::to_string_pretty(&all_tags)
+        .map_err(|e| CommandError::Parse(format!("Failed to serialize session tags: {}", e)))?;
+    write_usage_file_atomic(&session_tags_path()?, content.as_bytes())?;
+
+    Ok(cleaned)
+}
+
+#[derive(Debug, Serialize)]
+pub struct TagUsage {
+    tag: String,
+    total_cost: f64,
+    total_tokens: u64,
+    session_count: u64,
+}
+
+/// Cost and tokens per session tag. A session with several tags counts towards
+/// each of them; sessions without tags are grouped under "untagged".
+#[command]
+pub fn get_usage_by_tag() -> Result<Vec<TagUsage>, CommandError> {
+    let claude_path = get_claude_config_dir()?;
+
+    let all_entries = get_all_usage_entries(&claude_path);
+    let session_tags = load_session_tags();
+    let untagged = vec![UNTAGGED_BUCKET.to_string()];
+
+    let mut tag_stats: HashMap<&str, TagUsage> = HashMap::new();
+    let mut tag_sessions: HashMap<&str, HashSet<&str>> = HashMap::new();
+    for entry in &all_entries {
+        let tags = session_tags
+            .get(&entry.session_id)
+            .filter(|tags| !tags.is_empty())
+            .unwrap_or(&untagged);
+        for tag in tags {
+            let stat = tag_stats.entry(tag.as_str()).or_insert_with(|| TagUsage {
+                tag: tag.clone(),
+                total_cost: 0.0,
+                total_tokens: 0,
+                session_count: 0,
+            });
+            stat.total_cost += entry.cost;
+            stat.total_tokens = stat.total_tokens.saturating_add(entry.total_tokens());
+            tag_sessions.entry(tag.as_str()).or_default().insert(entry.session_id.as_str());
+        }
+    }
+
+    let mut by_tag: Vec<TagUsage> = tag_stats
+        .into_iter()
+        .map(|(tag, mut stat)| {
+            stat.session_count = tag_sessions.get(tag).map(|s| s.len()).unwrap_or(0) as u64;
+            stat
+        })
+        .collect();
+    by_tag.sort_by(|a, b| b.total_cost.partial_cmp(&a.total_cost).unwrap_or(std::cmp::Ordering::Equal));
+
+    Ok(by_tag)
+}
+
+// Build a zero-filled daily series of `days` consecutive local dates ending at `end_date`
+fn build_daily_series(entries: &[UsageEntry], days: u32, end_date: NaiveDate) -> Result<Vec<DailyUsage>, CommandError> {
+    if days == 0 {
+        return Ok(vec![]);
+    }
+
+    let start_date = end_date
+        .checked_sub_signed(Duration::days(days as i64 - 1))
+        .ok_or_else(|| CommandError::Validation(format!("{} days is out of range", days)))?;
+    let mut daily_stats: HashMap<NaiveDate, DailyUsage> = HashMap::new();
+
+    for entry in entries {
+        let date = match parse_entry_time(&entry.timestamp) {
+            Some(dt) => dt.with_timezone(&Local).date_naive(),
+            None => continue,
+        };
+        if date < start_date || date > end_date {
+            continue;
+        }
+
+        let daily_stat = daily_stats
+            .entry(date)
+            .or_insert_with(|| empty_daily_usage(date.format("%Y-%m-%d").to_string()));
+        add_to_daily_usage(daily_stat, entry);
+    }
+
+    // Fill days without activity with zero rows, chronologically ascending
+    Ok(start_date
+        .iter_days()
+        .take(days as usize)
+        .map(|date| {
+            daily_stats
+                .remove(&date)
+                .unwrap_or_else(|| empty_daily_usage(date.format("%Y-%m-%d").to_string()))
+        })
+        .collect())
+}
+
+#[command]
+pub fn get_daily_series(days: u32) -> Result<Vec<DailyUsage>, CommandError> {
+    let claude_path = get_claude_config_dir()?;
+
+    let all_entries = get_all_usage_entries(&claude_path);
+    let today = Local::now().date_naive();
+
+    build_daily_series(&all_entries, days, today)
+}
+
+#[derive(Debug, Serialize)]
+pub struct CacheRatioPoint {
+    date: String,
+    input_tokens: u64,
+    cache_read_tokens: u64,
+    cache_hit_ratio: f64,  // cache_read / (input + cache_read); 0.0 on days without input
+}
+
+/// Daily prompt-cache hit ratio (ascending, idle days zero-filled) for the last `days`
+/// days, or since the first recorded activity when `days` is None
+#[command]
+pub fn get_cache_hit_ratio(days: Option<u32>) -> Result<Vec<CacheRatioPoint>, CommandError> {
+    let claude_path = get_claude_config_dir()?;
+
+    let all_entries = get_all_usage_entries(&claude_path);
+    let today = Local::now().date_naive();
+    let days = match days {
+        Some(days) => days,
+        None => all_entries
+            .iter()
+            .filter_map(|e| parse_entry_time(&e.timestamp))
+            .map(|dt| dt.with_timezone(&Local).date_naive())
+            .min()
+            .map(|first| (today - first).num_days().max(0) as u32 + 1)
+            .unwrap_or(0),
+    };
+
+    Ok(build_daily_series(&all_entries, days, today)?
+        .into_iter()
+        .map(|day| {
+            let prompt_tokens = day.input_tokens.saturating_add(day.cache_read_tokens);
+            CacheRatioPoint {
+                cache_hit_ratio: if prompt_tokens > 0 {
+                    day.cache_read_tokens as f64 / prompt_tokens as f64
+                } else {
+                    0.0
+                },
+                date: day.date,
+                input_tokens: day.input_tokens,
+                cache_read_tokens: day.cache_read_tokens,
+            }
+        })
+        .collect())
+}
+
+#[derive(Debug, Serialize)]
+pub struct SmoothedDailyUsage {
+    date: String,
+    total_cost: f64,
+    total_tokens: u64,
+    moving_avg_cost: Option<f64>,  // trailing `window`-day mean; None until enough days exist
+}
+
+// Attach a trailing moving average of daily cost to a chronological series
+fn smooth_daily_series(series: Vec<DailyUsage>, window: usize) -> Vec<SmoothedDailyUsage> {
+    let costs: Vec<f64> = series.iter().map(|day| day.total_cost).collect();
+
+    series
+        .into_iter()
+        .enumerate()
+        .map(|(i, day)| SmoothedDailyUsage {
+            moving_avg_cost: (i + 1 >= window)
+                .then(|| costs[i + 1 - window..=i].iter().sum::<f64>() / window as f64),
+            date: day.date,
+            total_cost: day.total_cost,
+            total_tokens: day.total_tokens,
+        })
+        .collect()
+}
+
+/// Daily series for the last `days` days (ascending) with a `window`-day trailing average of cost
+#[command]
+pub fn get_daily_series_smoothed(days: u32, window: u32) -> Result<Vec<SmoothedDailyUsage>, CommandError> {
+    if window == 0 {
+        return Err(CommandError::Validation("Smoothing window must be at least 1 day".to_string()));
+    }
+
+    let claude_path = get_claude_config_dir()?;
+
+    let all_entries = get_all_usage_entries(&claude_path);
+    let today = Local::now().date_naive();
+
+    Ok(smooth_daily_series(build_daily_series(&all_entries, days, today)?, window as usize))
+}
+
+#[derive(Debug, Serialize)]
+pub struct ForecastPoint {
+    date: String,
+    predicted_cost: f64,
+    lower_bound: f64,  // ~95% band from the residual standard deviation, clamped at 0
+    upper_bound: f64,
+}
+
+// z-score for a ~95% band around the regression line
+const FORECAST_BAND_Z: f64 = 1.96;
+
+// Least-squares line through (day index, cost); returns (slope, intercept, residual stddev)
+fn fit_linear_trend(costs: &[f64]) -> (f64, f64, f64) {
+    let n = costs.len() as f64;
+    let mean_x = (n - 1.0) / 2.0;
+    let mean_y = costs.iter().sum::<f64>() / n;
+
+    let (mut sxy, mut sxx) = (0.0, 0.0);
+    for (x, y) in costs.iter().enumerate() {
+        let dx = x as f64 - mean_x;
+        sxy += dx * (y - mean_y);
+        sxx += dx * dx;
+    }
+    let slope = if sxx > 0.0 { sxy / sxx } else { 0.0 };
+    let intercept = mean_y - slope * mean_x;
+
+    let residual_ss: f64 = costs
+        .iter()
+        .enumerate()
+        .map(|(x, y)| (y - (intercept + slope * x as f64)).powi(2))
+        .sum();
+    let residual_stddev = if n > 2.0 { (residual_ss / (n - 2.0)).sqrt() } else { 0.0 };
+
+    (slope, intercept, residual_stddev)
+}
+
+/// Project daily cost `forecast_days` ahead from a linear trend over the last
+/// `history_days` (zero-filled) days, starting tomorrow
+#[command]
+pub fn forecast_cost(history_days: u32, forecast_days: u32) -> Result<Vec<ForecastPoint>, CommandError> {
+    if history_days < 2 {
+        return Err(CommandError::Validation("Forecast needs at least 2 days of history".to_string()));
+    }
+
+    let claude_path = get_claude_config_dir()?;
+
+    let all_entries = get_all_usage_entries(&claude_path);
+    let today = Local::now().date_naive();
+
+    let costs: Vec<f64> = build_daily_series(&all_entries, history_days, today)?
+        .iter()
+        .map(|day| day.total_cost)
+        .collect();
+    let (slope, intercept, residual_stddev) = fit_linear_trend(&costs);
+    let band = FORECAST_BAND_Z * residual_stddev;
+
+    Ok((1..=forecast_days as i64)
+        .map(|offset| {
+            let x = (history_days as i64 - 1 + offset) as f64;
+            let predicted = intercept + slope * x;
+            ForecastPoint {
+                date: (today + Duration::days(offset)).format("%Y-%m-%d").to_string(),
+                predicted_cost: predicted.max(0.0),
+                lower_bound: (predicted - band).max(0.0),
+                upper_bound: (predicted + band).max(0.0),
+            }
+        })
+        .collect())
+}
+
+#[derive(Debug, Serialize)]
+pub struct HourlyUsage {
+    hour: u32,
+    total_cost: f64,
+    total_tokens: u64,
+}
+
+/// Cost and tokens for each local hour (0-23) of a single day, zero-filled
+#[command]
+pub fn get_day_hourly(date: String) -> Result<Vec<HourlyUsage>, CommandError> {
+    let day = NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+        .map_err(|e| CommandError::Validation(format!("Invalid date format: {}", e)))?;
+
+    let claude_path = get_claude_config_dir()?;
+
+    let all_entries = get_all_usage_entries(&claude_path);
+
+    let mut hourly: Vec<HourlyUsage> = (0..24)
+        .map(|hour| HourlyUsage {
+            hour,
+            total_cost: 0.0,
+            total_tokens: 0,
+        })
+        .collect();
+
+    for entry in &all_entries {
+        let local_time = match parse_entry_time(&entry.timestamp) {
+            Some(dt) => dt.with_timezone(&Local),
+            None => continue,
+        };
+        if local_time.date_naive() != day {
+            continue;
+        }
+
+        let bucket = &mut hourly[local_time.hour() as usize];
+        bucket.total_cost += entry.cost;
+        bucket.total_tokens = bucket.total_tokens.saturating_add(entry.total_tokens());
+    }
+
+    Ok(hourly)
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnomalyDay {
+    date: String,
+    cost: f64,
+    z_score: f64,
+}
+
+/// Flag days whose cost exceeds mean + z_threshold * stddev over the last `days` days
+#[command]
+pub fn detect_cost_anomalies(days: u32, z_threshold: f64) -> Result<Vec<AnomalyDay>, CommandError> {
+    let claude_path = get_claude_config_dir()?;
+
+    let all_entries = get_all_usage_entries(&claude_path);
+    let today = Local::now().date_naive();
+
+    // Zero-filled so that quiet days pull the mean down
+    let series = build_daily_series(&all_entries, days, today)?;
+    if series.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let n = series.len() as f64;
+    let mean = series.iter().map(|d| d.total_cost).sum::<f64>() / n;
+    let variance = series
+        .iter()
+        .map(|d| (d.total_cost - mean).powi(2))
+        .sum::<f64>()
+        / n;
+    let stddev = variance.sqrt();
+
+    // A flat series has no outliers
+    if stddev == 0.0 {
+        return Ok(vec![]);
+    }
+
+    Ok(series
+        .into_iter()
+        .filter(|d| d.total_cost > mean + z_threshold * stddev)
+        .map(|d| AnomalyDay {
+            z_score: (d.total_cost - mean) / stddev,
+            date: d.date,
+            cost: d.total_cost,
+        })
+        .collect())
+}
+
+#[command]
+pub fn reveal_project_dir(project_path: String) -> Result<(), CommandError> {
+    let path = PathBuf::from(&project_path);
+
+    // Entries without a recorded cwd fall back to the encoded directory name
+    // (e.g. "-Users-me-project"), which is not a real filesystem path
+    if !path.is_absolute() {
+        return Err(CommandError::Validation(format!(
+            "Project path is not a real directory path (still encoded?): {}",
+            project_path
+        )));
+    }
+
+    if !path.is_dir() {
+        return Err(CommandError::NotFound(format!(
+            "Project directory no longer exists: {}",
+            project_path
+        )));
+    }
+
+    #[cfg(target_os = "windows")]
+    let mut cmd = {
+        use std::os::windows::process::CommandExt;
+        let mut cmd = std::process::Command::new("explorer");
+        cmd.creation_flags(0x08000000); // CREATE_NO_WINDOW
+        cmd
+    };
+    #[cfg(target_os = "macos")]
+    let mut cmd = std::process::Command::new("open");
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let mut cmd = std::process::Command::new("xdg-open");
+
+    cmd.arg(&path)
+        .spawn()
+        .map_err(|e| CommandError::Other(format!("Failed to open project directory: {}", e)))?;
+
+    Ok(())
+}
+
+// Whether a log belongs to `session_id`, by file or directory name, or else by the
+// first line whose `sessionId` matches. Lines are only parsed when they mention the id.
+fn log_mentions_session(path: &Path, session_id: &str) -> bool {
+    let named_after_session = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .map(|name| name.trim_end_matches(".gz").trim_end_matches(".jsonl") == session_id)
+        .unwrap_or(false)
+        || path
+            .parent()
+            .and_then(|p| p.file_name())
+            .and_then(|n| n.to_str())
+            == Some(session_id);
+    if named_after_session {
+        return true;
+    }
+
+    match open_usage_log(path) {
+        Ok(reader) => reader
+            .lines()
+            .map_while(Result::ok)
+            .filter(|line| line.contains(session_id))
+            .any(|line| {
+                serde_json::from_str::<serde_json::Value>(&line)
+                    .ok()
+                    .and_then(|value| value.get("sessionId").and_then(|v| v.as_str()).map(|id| id == session_id))
+                    .unwrap_or(false)
+            }),
+        Err(_) => false,
+    }
+}
+
+/// Paths of every usage log under `projects/` that belongs to `session_id`, sorted.
+/// A session can span several files, e.g. after a resume.
+#[command]
+pub fn locate_session_file(session_id: String) -> Result<Vec<String>, CommandError> {
+    let session_id = session_id.trim();
+    if session_id.is_empty() {
+        return Err(CommandError::Validation("Session id cannot be empty".to_string()));
+    }
+    let claude_path = get_claude_config_dir()?;
+
+    let mut paths: Vec<String> = list_usage_files(&claude_path, follow_symlinks())
+        .into_iter()
+        .filter(|(path, _)| log_mentions_session(path, session_id))
+        .map(|(path, _)| path.to_string_lossy().to_string())
+        .collect();
+    paths.sort();
+
+    Ok(paths)
+}
+
+#[derive(Debug, Serialize)]
+pub struct IdlePeriod {
+    start: String,
+    end: String,
+    seconds: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LatencyStats {
+    session_id: String,
+    message_count: u64,
+    // Gaps between consecutive usage entries no longer than the session window;
+    // all None when the session has fewer than two such entries
+    active_gap_count: u64,
+    min_gap_seconds: Option<f64>,
+    median_gap_seconds: Option<f64>,
+    max_gap_seconds: Option<f64>,
+    mean_gap_seconds: Option<f64>,
+    // Gaps longer than the session window, reported apart as idle time
+    idle_periods: Vec<IdlePeriod>,
+    total_idle_seconds: f64,
+}
+
+fn median(sorted: &[f64]) -> Option<f64> {
+    let mid = sorted.len() / 2;
+    match sorted.len() {
+        0 => None,
+        len if len % 2 == 0 => Some((sorted[mid - 1] + sorted[mid]) / 2.0),
+        _ => Some(sorted[mid]),
+    }
+}
+
+fn build_latency_stats(session_id: &str, entries: &[UsageEntry], window_hours: i64) -> LatencyStats {
+    let mut times: Vec<(DateTime<FixedOffset>, &str)> = entries
+        .iter()
+        .filter_map(|e| parse_entry_time(&e.timestamp).map(|dt| (dt, e.timestamp.as_str())))
+        .collect();
+    times.sort_by_key(|(dt, _)| *dt);
+
+    let idle_threshold = (window_hours * 3600) as f64;
+    let mut gaps = Vec::new();
+    let mut idle_periods = Vec::new();
+    for pair in times.windows(2) {
+        let seconds = (pair[1].0 - pair[0].0).num_milliseconds() as f64 / 1000.0;
+        if seconds > idle_threshold {
+            idle_periods.push(IdlePeriod {
+                start: pair[0].1.to_string(),
+                end: pair[1].1.to_string(),
+                seconds,
+            });
+        } else {
+            gaps.push(seconds);
+        }
+    }
+    gaps.sort_by(|a, b| a.total_cmp(b));
+
+    LatencyStats {
+        session_id: session_id.to_string(),
+        message_count: times.len() as u64,
+        active_gap_count: gaps.len() as u64,
+        min_gap_seconds: gaps.first().copied(),
+        median_gap_seconds: median(&gaps),
+        max_gap_seconds: gaps.last().copied(),
+        mean_gap_seconds: (!gaps.is_empty()).then(|| gaps.iter().sum::<f64>() / gaps.len() as f64),
+        total_idle_seconds: idle_periods.iter().map(|p| p.seconds).sum(),
+        idle_periods,
+    }
+}
+
+/// Rough interactivity metric for a session: the time between consecutive usage
+/// entries. Gaps longer than the session window count as idle, not latency.
+#[command]
+pub fn get_session_latency(session_id: String) -> Result<LatencyStats, CommandError> {
+    let session_id = session_id.trim();
+    if session_id.is_empty() {
+        return Err(CommandError::Validation("Session id cannot be empty".to_string()));
+    }
+    let claude_path = get_claude_config_dir()?;
+
+    let entries: Vec<UsageEntry> = get_all_usage_entries(&claude_path)
+        .into_iter()
+        .filter(|e| e.session_id == session_id)
+        .collect();
+    if entries.is_empty() {
+        return Err(CommandError::NotFound(format!("No usage found for session {}", session_id)));
+    }
+
+    Ok(build_latency_stats(session_id, &entries, session_window_hours()))
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrphanedSession {
+    path: String,
+    project_name: String,  // encoded project dir the session lives under
+    file_count: u64,  // usage logs in the directory (and below), none of which produced entries
+}
+
+// Every usage log at or below `dir`
+fn usage_logs_under(dir: &Path, follow_links: bool) -> Vec<PathBuf> {
+    walkdir::WalkDir::new(dir)
+        .follow_links(follow_links)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|e| is_usage_log(e.path()))
+        .map(|e| e.path().to_path_buf())
+        .collect()
+}
+
+// A session dir is orphaned when none of its logs yields a usage entry
+fn has_usage_entries(logs: &[PathBuf], project_name: &str) -> bool {
+    let mut processed_hashes = HashSet::new();
+    // Only emptiness matters here, so don't resolve the base URL per directory
+    logs.iter()
+        .any(|log| !parse_jsonl_file(log, project_name, "", DedupMode::default(), &mut processed_hashes).is_empty())
+}
+
+fn project_name_under(projects_dir: &Path, dir: &Path) -> String {
+    dir.strip_prefix(projects_dir)
+        .ok()
+        .and_then(|relative| relative.components().next())
+        .map(|component| component.as_os_str().to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+// Whether `path` was written within the last `window_hours`; unknown mtimes count as recent
+fn recently_modified(path: &Path, now: &DateTime<Local>, window_hours: i64) -> bool {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .map(|modified| now.signed_duration_since(DateTime::<Local>::from(modified)).num_hours() < window_hours)
+        .unwrap_or(true)
+}
+
+/// Session directories whose logs are empty or entirely unparseable. Logs written
+/// within the session window are skipped, since a running session has no usage
+/// lines until its first reply.
+#[command]
+pub fn find_orphaned_sessions() -> Result<Vec<OrphanedSession>, CommandError> {
+    find_orphaned_sessions_in(
+        &get_claude_config_dir()?.join("projects"),
+        Local::now(),
+        session_window_hours(),
+        follow_symlinks(),
+    )
+}
+
+fn find_orphaned_sessions_in(
+    projects_dir: &Path,
+    now: DateTime<Local>,
+    window_hours: i64,
+    follow_links: bool,
+) -> Result<Vec<OrphanedSession>, CommandError> {
+    if !projects_dir.exists() {
+        return Ok(vec![]);
+    }
+
+    // Directories that directly hold a log; nested logs count towards every ancestor
+    let session_dirs: BTreeSet<PathBuf> = usage_logs_under(projects_dir, follow_links)
+        .into_iter()
+        .filter_map(|log| log.parent().map(Path::to_path_buf))
+        .filter(|dir| dir != projects_dir)
+        .collect();
+
+    Ok(session_dirs
+        .into_iter()
+        .filter_map(|dir| {
+            let logs = usage_logs_under(&dir, follow_links);
+            if logs.iter().any(|log| recently_modified(log, &now, window_hours)) {
+                return None;
+            }
+            let project_name = project_name_under(projects_dir, &dir);
+            if has_usage_entries(&logs, &project_name) {
+                return None;
+            }
+            Some(OrphanedSession {
+                path: dir.to_string_lossy().to_string(),
+                project_name,
+                file_count: logs.len() as u64,
+            })
+        })
+        .collect())
+}
+
+/// Delete a directory reported by `find_orphaned_sessions`. Refuses anything outside
+/// ~/.claude/projects, the projects dir itself, or a directory that has usage data or
+/// was written within the session window.
+#[command]
+pub fn delete_orphaned_session(path: String) -> Result<(), CommandError> {
+    delete_orphaned_session_in(
+        &get_claude_config_dir()?.join("projects"),
+        &path,
+        Local::now(),
+        session_window_hours(),
+        follow_symlinks(),
+    )
+}
+
+fn delete_orphaned_session_in(
+    projects_dir: &Path,
+    path: &str,
+    now: DateTime<Local>,
+    window_hours: i64,
+    follow_links: bool,
+) -> Result<(), CommandError> {
+    let projects_dir = projects_dir
+        .canonicalize()
+        .map_err(|e| CommandError::NotFound(format!("Projects directory not found: {}", e)))?;
+    let dir = PathBuf::from(path)
+        .canonicalize()
+        .map_err(|e| CommandError::NotFound(format!("Session directory not found: {}", e)))?;
+
+    if dir == projects_dir || !dir.starts_with(&projects_dir) || !dir.is_dir() {
+        return Err(CommandError::Validation(format!(
+            "Not a session directory under {}: {}",
+            projects_dir.display(),
+            path
+        )));
+    }
+
+    let logs = usage_logs_under(&dir, follow_links);
+    if logs.iter().any(|log| recently_modified(log, &now, window_hours)) {
+        return Err(CommandError::Validation(format!(
+            "Session directory was written within the session window: {}",
+            path
+        )));
+    }
+    if has_usage_entries(&logs, &project_name_under(&projects_dir, &dir)) {
+        return Err(CommandError::Validation(format!(
+            "Session directory still contains usage data: {}",
+            path
+        )));
+    }
+
+    fs::remove_dir_all(&dir)
+        .map_err(|e| CommandError::FileWrite(format!("Failed to delete session directory: {}", e)))
+}
+
+#[derive(Debug, Serialize)]
+pub struct ArchiveReport {
+    archive_dir: String,
+    files_archived: u64,
+    entries_archived: u64,
+    /// Old enough logs left in place because their session is still active
+    skipped_active: u64,
+    /// Per-file failures; the original is kept whenever its archive could not be verified
+    errors: Vec<String>,
+}
+
+// Newest entry timestamp in a log, or None when it has no timestamped lines
+fn get_latest_timestamp(path: &Path) -> Option<DateTime<FixedOffset>> {
+    let reader = open_usage_log(path).ok()?;
+    reader
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(&line).ok())
+        .filter_map(|value| value.get("timestamp").and_then(|v| v.as_str()).and_then(parse_entry_time))
+        .max()
+}
+
+// Gzip `path` to `archive_path` and only delete the original once the archive
+// decompresses back to identical bytes
+fn archive_log_file(path: &Path, archive_path: &Path) -> Result<(), CommandError> {
+    if archive_path.exists() {
+        return Err(CommandError::Validation(format!(
+            "Archive already exists: {}",
+            archive_path.display()
+        )));
+    }
+
+    let original = fs::read(path)
+        .map_err(|e| CommandError::FileRead(format!("Failed to read {}: {}", path.display(), e)))?;
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    let compressed = encoder
+        .write_all(&original)
+        .and_then(|_| encoder.finish())
+        .map_err(|e| CommandError::FileWrite(format!("Failed to compress {}: {}", path.display(), e)))?;
+    write_usage_file_atomic(archive_path, &compressed)?;
+
+    let mut restored = Vec::new();
+    let verified = fs::File::open(archive_path)
+        .and_then(|file| std::io::Read::read_to_end(&mut GzDecoder::new(file), &mut restored))
+        .map(|_| restored == original)
+        .unwrap_or(false);
+    if !verified {
+        let _ = fs::remove_file(archive_path);
+        return Err(CommandError::FileWrite(format!(
+            "Archive verification failed for {}",
+            path.display()
+        )));
+    }
+
+    fs::remove_file(path)
+        .map_err(|e| CommandError::FileWrite(format!("Failed to remove {}: {}", path.display(), e)))
+}
+
+/// Move `.jsonl` logs whose newest entry is older than `older_than_days` days into
+/// `archive_dir` as `.jsonl.gz`, keeping their path relative to `projects/`.
+/// Archived logs are no longer included in usage stats.
+#[command]
+pub fn archive_old_logs(older_than_days: u32, archive_dir: String) -> Result<ArchiveReport, CommandError> {
+    archive_old_logs_in(
+        &get_claude_config_dir()?,
+        older_than_days,
+        &archive_dir,
+        Local::now(),
+        session_window_hours(),
+    )
+}
+
+fn archive_old_logs_in(
+    claude_path: &Path,
+    older_than_days: u32,
+    archive_dir: &str,
+    now: DateTime<Local>,
+    window_hours: i64,
+) -> Result<ArchiveReport, CommandError> {
+    let projects_dir = claude_path
+        .join("projects")
+        .canonicalize()
+        .map_err(|e| CommandError::NotFound(format!("Projects directory not found: {}", e)))?;
+
+    let archive_root = PathBuf::from(archive_dir.trim());
+    if !archive_root.is_absolute() {
+        return Err(CommandError::Validation("Archive directory must be an absolute path".to_string()));
+    }
+    fs::create_dir_all(&archive_root)
+        .map_err(|e| CommandError::FileWrite(format!("Failed to create archive directory: {}", e)))?;
+    let archive_root = archive_root
+        .canonicalize()
+        .map_err(|e| CommandError::FileRead(format!("Failed to resolve archive directory: {}", e)))?;
+    if archive_root.starts_with(&projects_dir) || projects_dir.starts_with(&archive_root) {
+        return Err(CommandError::Validation(format!(
+            "Archive directory must be outside {}",
+            projects_dir.display()
+        )));
+    }
+
+    let cutoff = now - Duration::days(older_than_days as i64);
+    let active_sessions: HashSet<String> = track_active_sessions(&get_all_usage_entries(claude_path))
+        .into_iter()
+        .filter(|(_, start)| is_session_active(&start.to_rfc3339(), &now, window_hours))
+        .map(|(session_id, _)| session_id)
+        .collect();
+
+    let mut report = ArchiveReport {
+        archive_dir: archive_root.to_string_lossy().to_string(),
+        files_archived: 0,
+        entries_archived: 0,
+        skipped_active: 0,
+        errors: Vec::new(),
+    };
+
+    let api_base_url = get_api_base_url();
+    for (path, project_name) in list_usage_files(claude_path, follow_symlinks()) {
+        if path.extension().and_then(|e| e.to_str()) != Some("jsonl") {
+            continue;
+        }
+        match get_latest_timestamp(&path) {
+            Some(latest) if latest < cutoff => {}
+            _ => continue,
+        }
+
+        let entries = parse_jsonl_file(&path, &project_name, &api_base_url, DedupMode::default(), &mut HashSet::new());
+        if recently_modified(&path, &now, window_hours) || entries.iter().any(|e| active_sessions.contains(&e.session_id)) {
+            report.skipped_active += 1;
+            continue;
+        }
+
+        let relative = path
+            .canonicalize()
+            .ok()
+            .and_then(|p| p.strip_prefix(&projects_dir).map(Path::to_path_buf).ok());
+        let relative = match relative {
+            Some(relative) => relative,
+            None => {
+                report.errors.push(format!("Log is outside the projects directory: {}", path.display()));
+                continue;
+            }
+        };
+        let mut archive_name = relative.into_os_string();
+        archive_name.push(".gz");
+        let archive_path = archive_root.join(archive_name);
+
+        match archive_log_file(&path, &archive_path) {
+            Ok(()) => {
+                report.files_archived += 1;
+                report.entries_archived += entries.len() as u64;
+            }
+            Err(e) => report.errors.push(e.to_string()),
+        }
+    }
+
+    Ok(report)
+}
+
+#[derive(Debug, Serialize)]
+pub struct LifetimeSummary {
+    first_activity: Option<String>,
+    last_activity: Option<String>,
+    active_days: u64,
+    total_cost: f64,
+    total_tokens: u64,
+    avg_cost_per_active_day: f64,
+}
+
+#[command]
+pub fn get_lifetime_summary() -> Result<LifetimeSummary, CommandError> {
+    let claude_path = get_claude_config_dir()?;
+
+    // Entries are returned sorted by timestamp
+    let all_entries = get_all_usage_entries(&claude_path);
+
+    let mut active_dates: HashSet<NaiveDate> = HashSet::new();
+    let mut total_cost = 0.0;
+    let mut total_tokens = 0u64;
+
+    for entry in &all_entries {
+        total_cost += entry.cost;
+        total_tokens = total_tokens.saturating_add(entry.total_tokens());
+
+        if let Some(dt) = parse_entry_time(&entry.timestamp) {
+            active_dates.insert(dt.with_timezone(&Local).date_naive());
+        }
+    }
+
+    let active_days = active_dates.len() as u64;
+    let avg_cost_per_active_day = if active_days > 0 {
+        total_cost / active_days as f64
+    } else {
+        0.0
+    };
+
+    Ok(LifetimeSummary {
+        first_activity: all_entries.first().map(|e| e.timestamp.clone()),
+        last_activity: all_entries.last().map(|e| e.timestamp.clone()),
+        active_days,
+        total_cost,
+        total_tokens,
+        avg_cost_per_active_day,
+    })
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsagePatterns {
+    avg_cost_by_weekday: [f64; 7],  // Monday first
+}
+
+/// Average daily cost per weekday over the selected window
+#[command]
+pub fn get_usage_patterns(days: Option<u32>) -> Result<UsagePatterns, CommandError> {
+    let claude_path = get_claude_config_dir()?;
+
+    let all_entries = get_all_usage_entries(&claude_path);
+    let today = Local::now().date_naive();
+
+    Ok(build_usage_patterns(&all_entries, days, today))
+}
+
+// Weekdays are assigned in local time; each sum is divided by how many times that
+// weekday occurs in the window (idle days included), not by the number of weeks
+fn build_usage_patterns(entries: &[UsageEntry], days: Option<u32>, end_date: NaiveDate) -> UsagePatterns {
+    let mut cost_by_date: HashMap<NaiveDate, f64> = HashMap::new();
+    for entry in entries {
+        if let Some(dt) = parse_entry_time(&entry.timestamp) {
+            *cost_by_date.entry(dt.with_timezone(&Local).date_naive()).or_insert(0.0) += entry.cost;
+        }
+    }
+
+    // Same cutoff as filter_entries_by_days; without a window, start at the first activity
+    let start_date = match days {
+        Some(days) => end_date - Duration::days(days as i64),
+        None => match cost_by_date.keys().min() {
+            Some(first) => *first,
+            None => end_date,
+        },
+    };
+
+    let mut cost_sums = [0.0f64; 7];
+    let mut weekday_counts = [0u32; 7];
+    for date in start_date.iter_days().take_while(|date| *date <= end_date) {
+        let weekday = date.weekday().num_days_from_monday() as usize;
+        weekday_counts[weekday] += 1;
+        cost_sums[weekday] += cost_by_date.get(&date).copied().unwrap_or(0.0);
+    }
+
+    let mut avg_cost_by_weekday = [0.0f64; 7];
+    for ((avg, sum), count) in avg_cost_by_weekday.iter_mut().zip(cost_sums).zip(weekday_counts) {
+        if count > 0 {
+            *avg = sum / count as f64;
+        }
+    }
+
+    UsagePatterns { avg_cost_by_weekday }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CostConcentration {
+    total_cost: f64,
+    top_hour: Option<u32>,  // local hour of day, 0-23
+    top_hour_share: f64,
+    top_weekday: Option<u32>,  // 0 = Monday
+    top_weekday_share: f64,
+    top_day: Option<String>,
+    top_day_share: f64,
+    gini: f64,  // over daily cost, idle days included: 0 = perfectly steady, near 1 = all in one day
+}
+
+#[command]
+pub fn get_cost_concentration(days: Option<u32>) -> Result<CostConcentration, CommandError> {
+    let claude_path = get_claude_config_dir()?;
+
+    let entries = filter_entries_by_days(get_all_usage_entries(&claude_path), days);
+    let today = Local::now().date_naive();
+
+    Ok(build_cost_concentration(&entries, days, today))
+}
+
+// Bucket with the largest cost and its share of the total; None when nothing was spent
+fn top_share<K: Clone>(buckets: impl IntoIterator<Item = (K, f64)>, total: f64) -> (Option<K>, f64) {
+    if total <= 0.0 {
+        return (None, 0.0);
+    }
+    buckets
+        .into_iter()
+        .fold(None, |best: Option<(K, f64)>, (key, cost)| match best {
+            Some((_, best_cost)) if best_cost >= cost => best,
+            _ => Some((key, cost)),
+        })
+        .map(|(key, cost)| (Some(key), cost / total))
+        .unwrap_or((None, 0.0))
+}
+
+// Gini coefficient of non-negative values
+fn gini_coefficient(values: &[f64]) -> f64 {
+    let total: f64 = values.iter().sum();
+    if values.is_empty() || total <= 0.0 {
+        return 0.0;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let n = sorted.len() as f64;
+    let weighted: f64 = sorted.iter().enumerate().map(|(i, v)| (i as f64 + 1.0) * v).sum();
+    (2.0 * weighted / (n * total) - (n + 1.0) / n).max(0.0)
+}
+
+// Entries must already be limited to the window; the daily series uses the same
+// window as build_usage_patterns so idle days count towards the Gini coefficient
+fn build_cost_concentration(entries: &[UsageEntry], days: Option<u32>, end_date: NaiveDate) -> CostConcentration {
+    let mut cost_by_hour = [0.0f64; 24];
+    let mut cost_by_weekday = [0.0f64; 7];
+    let mut first_date: Option<NaiveDate> = None;
+    for entry in entries {
+        if let Some(dt) = parse_entry_time(&entry.timestamp) {
+            let local = dt.with_timezone(&Local);
+            cost_by_hour[local.hour() as usize] += entry.cost;
+            cost_by_weekday[local.weekday().num_days_from_monday() as usize] += entry.cost;
+            first_date = Some(first_date.map_or(local.date_naive(), |d| d.min(local.date_naive())));
+        }
+    }
+
+    let start_date = match days {
+        Some(days) => end_date - Duration::days(days as i64),
+        None => first_date.unwrap_or(end_date).min(end_date),
+    };
+    let series_days = (end_date - start_date).num_days() as u32 + 1;
+    // start_date is already a valid date, so the series can't go out of range
+    let daily = build_daily_series(entries, series_days, end_date).unwrap_or_default();
+    let daily_costs: Vec<f64> = daily.iter().map(|d| d.total_cost).collect();
+    let total_cost: f64 = cost_by_hour.iter().sum();
+
+    let (top_hour, top_hour_share) = top_share((0u32..).zip(cost_by_hour), total_cost);
+    let (top_weekday, top_weekday_share) = top_share((0u32..).zip(cost_by_weekday), total_cost);
+    let (top_day, top_day_share) = top_share(daily.iter().map(|d| (d.date.clone(), d.total_cost)), total_cost);
+
+    CostConcentration {
+        total_cost,
+        top_hour,
+        top_hour_share,
+        top_weekday,
+        top_weekday_share,
+        top_day,
+        top_day_share,
+        gini: gini_coefficient(&daily_costs),
+    }
+}
+
+// Average month length used to scale a window's cost to a monthly figure
+const AVG_DAYS_PER_MONTH: f64 = 365.25 / 12.0;
+
+#[derive(Debug, Serialize)]
+pub struct BreakevenReport {
+    days: u32,
+    api_cost: f64,  // API-equivalent cost of priced entries in the window
+    monthly_api_cost: f64,  // api_cost scaled to an average month
+    plan_monthly_usd: f64,
+    monthly_savings: f64,  // monthly_api_cost - plan price; negative when the plan costs more
+    subscription_saves_money: bool,
+}
+
+/// Compare a monthly subscription price with the API-equivalent cost of the last
+/// `days` days of usage, scaled to a month. Unpriced entries count as $0.
+#[command]
+pub fn get_subscription_breakeven(plan_monthly_usd: f64, days: u32) -> Result<BreakevenReport, CommandError> {
+    if !plan_monthly_usd.is_finite() || plan_monthly_usd < 0.0 {
+        return Err(CommandError::Validation("Plan price must be a non-negative amount".to_string()));
+    }
+    if days == 0 {
+        return Err(CommandError::Validation("Days must be at least 1".to_string()));
+    }
+
+    let claude_path = get_claude_config_dir()?;
+
+    let all_entries = get_all_usage_entries(&claude_path);
+    let entries = filter_entries_by_days(all_entries, Some(days));
+    if entries.is_empty() {
+        return Ok(BreakevenReport {
+            days,
+            api_cost: 0.0,
+            monthly_api_cost: 0.0,
+            plan_monthly_usd,
+            monthly_savings: 0.0,
+            subscription_saves_money: false,
+        });
+    }
+
+    let api_cost: f64 = entries.iter().map(|entry| entry.cost).sum();
+    let monthly_api_cost = api_cost / days as f64 * AVG_DAYS_PER_MONTH;
+    let monthly_savings = monthly_api_cost - plan_monthly_usd;
+
+    Ok(BreakevenReport {
+        days,
+        api_cost,
+        monthly_api_cost,
+        plan_monthly_usd,
+        monthly_savings,
+        subscription_saves_money: monthly_savings > 0.0,
+    })
+}
+
+/// Billing plan guessed by `detect_plan`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClaudePlan {
+    Api,
+    Pro,
+    Max,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlanConfidence {
+    Low,
+    Medium,
+    High,
+}
+
+// Monthly list prices used as break-even defaults (Max is its lowest tier)
+const PRO_PLAN_MONTHLY_USD: f64 = 20.0;
+const MAX_PLAN_MONTHLY_USD: f64 = 100.0;
+
+#[derive(Debug, Serialize)]
+pub struct PlanInfo {
+    plan: ClaudePlan,
+    confidence: PlanConfidence,
+    signals: Vec<String>,  // human-readable reasons behind the guess
+    plan_monthly_usd: Option<f64>,  // default for get_subscription_breakeven; None for API billing
+    session_window_hours: Option<i64>,  // default for set_session_window_hours; None when windows don't apply
+}
+
+impl PlanInfo {
+    fn new(plan: ClaudePlan, confidence: PlanConfidence, signals: Vec<String>) -> Self {
+        let (plan_monthly_usd, session_window_hours) = match plan {
+            ClaudePlan::Pro => (Some(PRO_PLAN_MONTHLY_USD), Some(DEFAULT_SESSION_WINDOW_HOURS)),
+            ClaudePlan::Max => (Some(MAX_PLAN_MONTHLY_USD), Some(DEFAULT_SESSION_WINDOW_HOURS)),
+            ClaudePlan::Api | ClaudePlan::Unknown => (None, None),
+        };
+        PlanInfo { plan, confidence, signals, plan_monthly_usd, session_window_hours }
+    }
+}
+
+// Markers in settings.json: API key settings mean API billing, `forceLoginMethod`
+// names the login type, and a `subscriptionType` (when present) names the plan.
+// Returns the plan those markers imply, if any, and whether it is a subscription
+// of unknown tier.
+fn plan_from_settings(settings: &serde_json::Value, signals: &mut Vec<String>) -> (Option<ClaudePlan>, bool) {
+    if let Some(subscription) = settings.get("subscriptionType").and_then(|v| v.as_str()) {
+        let subscription = subscription.to_lowercase();
+        if subscription.contains("max") {
+            signals.push(format!("settings.json subscriptionType is '{}'", subscription));
+            return (Some(ClaudePlan::Max), false);
+        }
+        if subscription.contains("pro") {
+            signals.push(format!("settings.json subscriptionType is '{}'", subscription));
+            return (Some(ClaudePlan::Pro), false);
+        }
+    }
+
+    if settings.get("apiKeyHelper").is_some_and(|v| !v.is_null()) {
+        signals.push("settings.json configures apiKeyHelper".to_string());
+        return (Some(ClaudePlan::Api), false);
+    }
+    let env_vars = settings.get("env").and_then(|v| v.as_object());
+    for key in ["ANTHROPIC_API_KEY", "ANTHROPIC_AUTH_TOKEN"] {
+        if env_vars.is_some_and(|env| env.contains_key(key)) {
+            signals.push(format!("settings.json sets {}", key));
+            return (Some(ClaudePlan::Api), false);
+        }
+    }
+
+    match settings.get("forceLoginMethod").and_then(|v| v.as_str()) {
+        Some("console") => {
+            signals.push("settings.json forces Console (API) login".to_string());
+            (Some(ClaudePlan::Api), false)
+        }
+        Some("claudeai") => {
+            signals.push("settings.json forces Claude.ai (subscription) login".to_string());
+            (None, true)
+        }
+        _ => (None, false),
+    }
+}
+
+// Settings markers win; otherwise fall back to usage patterns. Claude Code writes
+// `costUSD` for API billing, so most entries reporting it points at the API. Opus
+// usage on a subscription points at Max.
+fn build_plan_info(settings: Option<&serde_json::Value>, entries: &[UsageEntry]) -> PlanInfo {
+    let mut signals = Vec::new();
+    let (settings_plan, subscription_login) = match settings {
+        Some(settings) => plan_from_settings(settings, &mut signals),
+        None => (None, false),
+    };
+    if let Some(plan) = settings_plan {
+        return PlanInfo::new(plan, PlanConfidence::High, signals);
+    }
+
+    let reported = entries.iter().filter(|e| e.cost_reported).count();
+    let uses_opus = entries.iter().any(|e| e.model.contains("opus"));
+    if !entries.is_empty() && reported * 2 >= entries.len() {
+        signals.push(format!("{} of {} usage entries report costUSD", reported, entries.len()));
+        let confidence = if subscription_login { PlanConfidence::Low } else { PlanConfidence::Medium };
+        return PlanInfo::new(ClaudePlan::Api, confidence, signals);
+    }
+
+    if subscription_login {
+        if uses_opus {
+            signals.push("Opus models appear in usage".to_string());
+            return PlanInfo::new(ClaudePlan::Max, PlanConfidence::Medium, signals);
+        }
+        return PlanInfo::new(ClaudePlan::Pro, PlanConfidence::Low, signals);
+    }
+
+    if entries.is_empty() {
+        signals.push("No usage logs found".to_string());
+    } else {
+        signals.push("Usage logs don't report costUSD".to_string());
+    }
+    PlanInfo::new(ClaudePlan::Unknown, PlanConfidence::Low, signals)
+}
+
+/// Guess whether the user is on API billing, Pro or Max from settings.json markers,
+/// falling back to usage patterns. Returns "unknown" when nothing points either way.
+#[command]
+pub fn detect_plan() -> Result<PlanInfo, CommandError> {
+    let claude_path = get_claude_config_dir()?;
+
+    // A missing or malformed settings.json just leaves the guess to usage patterns
+    let settings = fs::read_to_string(claude_path.join("settings.json"))
+        .ok()
+        .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok());
+    let entries = get_all_usage_entries(&claude_path);
+
+    Ok(build_plan_info(settings.as_ref(), &entries))
+}
+
+#[command]
+pub fn get_monthly_budget() -> Result<Option<f64>, CommandError> {
+    Ok(load_usage_prefs().monthly_budget_usd)
+}
+
+/// Set the monthly budget used by `get_budget_runway`; `None` removes it
+#[command]
+pub fn set_monthly_budget(budget_usd: Option<f64>) -> Result<Option<f64>, CommandError> {
+    if let Some(budget) = budget_usd {
+        if !budget.is_finite() || budget <= 0.0 {
+            return Err(CommandError::Validation("Budget must be greater than zero".to_string()));
+        }
+    }
+
+    let _guard = lock_usage_files()?;
+    let mut prefs = load_usage_prefs();
+    prefs.monthly_budget_usd = budget_usd;
+    save_usage_prefs(&prefs)?;
+
+    Ok(budget_usd)
+}
+
+// Days of recent spend (including today) averaged to project the runway
+const RUNWAY_AVERAGE_DAYS: i64 = 7;
+
+#[derive(Debug, Serialize)]
+pub struct BudgetRunway {
+    monthly_budget_usd: f64,
+    month_to_date_cost: f64,
+    remaining_budget: f64,  // negative once the budget is overspent
+    daily_average_cost: f64,  // over the last RUNWAY_AVERAGE_DAYS days
+    exhausted: bool,  // month-to-date spend already reached the budget
+    exhaustion_date: Option<String>,  // YYYY-MM-DD; when it was or will be reached, None with no spend
+    days_until_exhausted: Option<f64>,  // None when already exhausted or nothing is being spent
+    within_budget: bool,  // the projection stays under budget through the end of the month
+}
+
+/// Project when this month's budget runs out from month-to-date spend and the
+/// recent daily average. Zero recent spend means the runway is unlimited.
+#[command]
+pub fn get_budget_runway() -> Result<BudgetRunway, CommandError> {
+    let budget = load_usage_prefs()
+        .monthly_budget_usd
+        .ok_or_else(|| CommandError::NotFound("No monthly budget configured".to_string()))?;
+    let claude_path = get_claude_config_dir()?;
 
-    // Calculate aggregated stats for today
-    let mut total_cost = 0.0;
-    let mut total_input_tokens = 0u64;
-    let mut total_output_tokens = 0u64;
-    let mut total_cache_creation_tokens = 0u64;
-    let mut total_cache_read_tokens = 0u64;
+    let all_entries = get_all_usage_entries(&claude_path);
 
-    let mut model_stats: HashMap<String, ModelUsage> = HashMap::new();
-    let mut daily_stats: HashMap<String, DailyUsage> = HashMap::new();
-    let mut project_stats: HashMap<String, ProjectUsage> = HashMap::new();
-    let mut api_base_url_stats: HashMap<String, ApiBaseUrlUsage> = HashMap::new();
-    
-    // Track unique sessions for accurate counting
-    let mut unique_sessions: HashSet<String> = HashSet::new();
-    let mut model_sessions: HashMap<String, HashSet<String>> = HashMap::new();
-    let mut project_sessions: HashMap<String, HashSet<String>> = HashMap::new();
-    let mut api_sessions: HashMap<String, HashSet<String>> = HashMap::new();
+    Ok(build_budget_runway(&all_entries, budget, Local::now().date_naive()))
+}
 
-    for entry in &today_entries {
-        // Update totals
-        total_cost += entry.cost;
-        total_input_tokens += entry.input_tokens;
-        total_output_tokens += entry.output_tokens;
-        total_cache_creation_tokens += entry.cache_creation_tokens;
-        total_cache_read_tokens += entry.cache_read_tokens;
+fn build_budget_runway(entries: &[UsageEntry], budget: f64, today: NaiveDate) -> BudgetRunway {
+    let month_start = today.with_day(1).unwrap_or(today);
+    let month_end = (month_start + Duration::days(32)).with_day(1).unwrap_or(today) - Duration::days(1);
+    let average_start = today - Duration::days(RUNWAY_AVERAGE_DAYS - 1);
 
-        // Track unique sessions
-        unique_sessions.insert(entry.session_id.clone());
-        
-        // Track sessions per model
-        model_sessions
-            .entry(entry.model.clone())
-            .or_insert_with(HashSet::new)
-            .insert(entry.session_id.clone());
-            
-        // Track sessions per project
-        project_sessions
-            .entry(entry.project_path.clone())
-            .or_insert_with(HashSet::new)
-            .insert(entry.session_id.clone());
-            
-        // Track sessions per API base URL
-        api_sessions
-            .entry(entry.api_base_url.clone())
-            .or_insert_with(HashSet::new)
-            .insert(entry.session_id.clone());
+    let mut month_daily: BTreeMap<NaiveDate, f64> = BTreeMap::new();
+    let mut recent_cost = 0.0;
+    for entry in entries {
+        let date = match parse_entry_time(&entry.timestamp) {
+            Some(time) => time.with_timezone(&Local).date_naive(),
+            None => continue,
+        };
+        if date > today {
+            continue;
+        }
+        if date >= month_start {
+            *month_daily.entry(date).or_insert(0.0) += entry.cost;
+        }
+        if date >= average_start {
+            recent_cost += entry.cost;
+        }
+    }
 
-        // Update model stats
-        let model_stat = model_stats
-            .entry(entry.model.clone())
-            .or_insert(ModelUsage {
-                model: entry.model.clone(),
-                total_cost: 0.0,
-                total_tokens: 0,
-                input_tokens: 0,
-                output_tokens: 0,
-                cache_creation_tokens: 0,
-                cache_read_tokens: 0,
-                session_count: 0,
-            });
-        model_stat.total_cost += entry.cost;
-        model_stat.input_tokens += entry.input_tokens;
-        model_stat.output_tokens += entry.output_tokens;
-        model_stat.cache_creation_tokens += entry.cache_creation_tokens;
-        model_stat.cache_read_tokens += entry.cache_read_tokens;
-        model_stat.total_tokens = model_stat.input_tokens + model_stat.output_tokens + model_stat.cache_creation_tokens + model_stat.cache_read_tokens;
-        // Session count will be set later from unique session tracking
+    let month_to_date_cost: f64 = month_daily.values().sum();
+    let remaining_budget = budget - month_to_date_cost;
+    let daily_average_cost = recent_cost / RUNWAY_AVERAGE_DAYS as f64;
 
-        // Update daily stats
-        let date = entry
-            .timestamp
-            .split('T')
-            .next()
-            .unwrap_or(&entry.timestamp)
-            .to_string();
-        let daily_stat = daily_stats.entry(date.clone()).or_insert(DailyUsage {
-            date,
-            total_cost: 0.0,
-            total_tokens: 0,
-            models_used: vec![],
+    let (exhausted, exhaustion_date, days_until_exhausted) = if remaining_budget <= 0.0 {
+        // The day cumulative spend crossed the budget
+        let mut cumulative = 0.0;
+        let crossed = month_daily.iter().find_map(|(date, cost)| {
+            cumulative += cost;
+            (cumulative >= budget).then_some(*date)
         });
-        daily_stat.total_cost += entry.cost;
-        daily_stat.total_tokens += entry.input_tokens
-            + entry.output_tokens
-            + entry.cache_creation_tokens
-            + entry.cache_read_tokens;
-        if !daily_stat.models_used.contains(&entry.model) {
-            daily_stat.models_used.push(entry.model.clone());
-        }
+        (true, crossed, None)
+    } else if daily_average_cost > 0.0 {
+        let days = remaining_budget / daily_average_cost;
+        (false, Some(today + Duration::days(days.ceil() as i64)), Some(days))
+    } else {
+        (false, None, None)
+    };
 
-        // Update project stats
-        let project_stat =
-            project_stats
-                .entry(entry.project_path.clone())
-                .or_insert(ProjectUsage {
-                    project_path: entry.project_path.clone(),
-                    project_name: entry
-                        .project_path
-                        .split('/')
-                        .last()
-                        .unwrap_or(&entry.project_path)
-                        .to_string(),
-                    total_cost: 0.0,
-                    total_tokens: 0,
-                    session_count: 0,
-                    last_used: entry.timestamp.clone(),
-                });
-        project_stat.total_cost += entry.cost;
-        project_stat.total_tokens += entry.input_tokens
-            + entry.output_tokens
-            + entry.cache_creation_tokens
-            + entry.cache_read_tokens;
-        // Session count will be set later from unique session tracking
-        if entry.timestamp > project_stat.last_used {
-            project_stat.last_used = entry.timestamp.clone();
-        }
+    BudgetRunway {
+        monthly_budget_usd: budget,
+        month_to_date_cost,
+        remaining_budget,
+        daily_average_cost,
+        exhausted,
+        exhaustion_date: exhaustion_date.map(|date| date.format("%Y-%m-%d").to_string()),
+        days_until_exhausted,
+        within_budget: !exhausted && !matches!(exhaustion_date, Some(date) if date <= month_end),
+    }
+}
 
-        // Update API base URL stats
-        let api_base_url_stat = api_base_url_stats
-            .entry(entry.api_base_url.clone())
-            .or_insert(ApiBaseUrlUsage {
-                api_base_url: entry.api_base_url.clone(),
-                total_cost: 0.0,
-                total_tokens: 0,
-                input_tokens: 0,
-                output_tokens: 0,
-                cache_creation_tokens: 0,
-                cache_read_tokens: 0,
-                session_count: 0,
-            });
-        api_base_url_stat.total_cost += entry.cost;
-        api_base_url_stat.input_tokens += entry.input_tokens;
-        api_base_url_stat.output_tokens += entry.output_tokens;
-        api_base_url_stat.cache_creation_tokens += entry.cache_creation_tokens;
-        api_base_url_stat.cache_read_tokens += entry.cache_read_tokens;
-        api_base_url_stat.total_tokens = api_base_url_stat.input_tokens + api_base_url_stat.output_tokens + api_base_url_stat.cache_creation_tokens + api_base_url_stat.cache_read_tokens;
-        // Session count will be set later from unique session tracking
+/// Estimate the cost of a hypothetical request using the same pricing as the usage logs
+#[command]
+pub fn estimate_cost(
+    model: String,
+    input_tokens: u64,
+    output_tokens: u64,
+    cache_creation_tokens: u64,
+    cache_read_tokens: u64,
+) -> Result<f64, CommandError> {
+    // calculate_cost silently prices unknown models at 0, which would look like a free request
+    if model_prices(&model).is_none() {
+        return Err(CommandError::NotFound(format!("No pricing available for model: {}", model)));
     }
 
-    let total_tokens = total_input_tokens
-        + total_output_tokens
-        + total_cache_creation_tokens
-        + total_cache_read_tokens;
-    let total_sessions = unique_sessions.len() as u64;
+    let usage = UsageData {
+        input_tokens: Some(input_tokens),
+        output_tokens: Some(output_tokens),
+        cache_creation_input_tokens: Some(cache_creation_tokens),
+        cache_read_input_tokens: Some(cache_read_tokens),
+        cache_creation: None,
+    };
 
-    // Set correct session counts and convert hashmaps to sorted vectors
-    let mut by_model: Vec<ModelUsage> = model_stats.into_iter().map(|(model, mut stat)| {
-        stat.session_count = model_sessions.get(&model).map(|s| s.len()).unwrap_or(0) as u64;
-        stat
-    }).collect();
-    by_model.sort_by(|a, b| b.total_cost.partial_cmp(&a.total_cost).unwrap());
+    Ok(calculate_cost(&model, &usage))
+}
 
-    let mut by_date: Vec<DailyUsage> = daily_stats.into_values().collect();
-    by_date.sort_by(|a, b| b.date.cmp(&a.date));
+#[derive(Debug, Serialize)]
+pub struct PricingCheck {
+    provider_id: String,
+    model: Option<String>,  // None when the provider leaves the model to Claude Code's default
+    priced: bool,
+    input_price: Option<f64>,  // per million tokens, when priced
+    output_price: Option<f64>,
+    suggestion: Option<String>,
+}
 
-    let mut by_project: Vec<ProjectUsage> = project_stats.into_iter().map(|(project_path, mut stat)| {
-        stat.session_count = project_sessions.get(&project_path).map(|s| s.len()).unwrap_or(0) as u64;
-        stat
-    }).collect();
-    by_project.sort_by(|a, b| b.total_cost.partial_cmp(&a.total_cost).unwrap());
+/// Check that a saved provider's model matches the pricing used for usage costs.
+/// Unpriced models are silently counted as $0, so this warns before that happens.
+#[command]
+pub fn check_provider_model_pricing(id: String) -> Result<PricingCheck, CommandError> {
+    let provider = load_providers_from_file()?
+        .into_iter()
+        .find(|p| p.id == id)
+        .ok_or_else(|| CommandError::NotFound(format!("Provider not found: {}", id)))?;
 
-    let mut by_api_base_url: Vec<ApiBaseUrlUsage> = api_base_url_stats.into_iter().map(|(api_url, mut stat)| {
-        stat.session_count = api_sessions.get(&api_url).map(|s| s.len()).unwrap_or(0) as u64;
-        stat
-    }).collect();
-    by_api_base_url.sort_by(|a, b| b.total_cost.partial_cmp(&a.total_cost).unwrap());
+    let model = provider.model.clone().filter(|m| !m.trim().is_empty());
+    // A provider's own rates apply whatever the model
+    let prices = match provider.pricing_override {
+        Some(pricing) => Some((pricing.input, pricing.output)),
+        None => model.as_deref().and_then(model_prices).map(|p| (p.0, p.1)),
+    };
+    // Claude Code's default models are all priced
+    let priced = model.is_none() || prices.is_some();
+    let suggestion = match (&model, prices) {
+        (Some(model), None) => Some(format!(
+            "Model '{}' matches no known pricing, so usage through '{}' is counted as $0. \
+             Use a Claude model name or set a pricing_override on the provider.",
+            model, provider.name
+        )),
+        _ => None,
+    };
 
-    Ok(UsageStats {
-        total_cost,
-        total_tokens,
-        total_input_tokens,
-        total_output_tokens,
-        total_cache_creation_tokens,
-        total_cache_read_tokens,
-        total_sessions,
-        by_model,
-        by_date,
-        by_project,
-        by_api_base_url,
+    Ok(PricingCheck {
+        provider_id: provider.id,
+        model,
+        priced,
+        input_price: prices.map(|p| p.0),
+        output_price: prices.map(|p| p.1),
+        suggestion,
     })
 }
 
+#[derive(Debug, Serialize)]
+pub struct ProviderCostEstimate {
+    provider_id: Option<String>,  // None for the official Anthropic API row
+    provider_name: String,
+    base_url: String,
+    cost: Option<f64>,  // None when the price is unknown
+    pricing_source: String,  // "override", "model" or "unknown"
+}
+
+/// What each saved provider would charge for the same workload. Providers with a
+/// `pricing_override` use their own rates; the official API uses model pricing.
+/// Providers without an override are reported as unknown. Cheapest first.
 #[command]
-pub fn get_session_stats(
-    since: Option<String>,
-    until: Option<String>,
-    order: Option<String>,
-) -> Result<Vec<ProjectUsage>, String> {
-    let claude_path = dirs::home_dir()
-        .ok_or("Failed to get home directory")?
-        .join(".claude");
+pub fn compare_provider_costs(
+    input_tokens: u64,
+    output_tokens: u64,
+    cache_creation_tokens: u64,
+    cache_read_tokens: u64,
+    model: String,
+) -> Result<Vec<ProviderCostEstimate>, CommandError> {
+    let usage = UsageData {
+        input_tokens: Some(input_tokens),
+        output_tokens: Some(output_tokens),
+        cache_creation_input_tokens: Some(cache_creation_tokens),
+        cache_read_input_tokens: Some(cache_read_tokens),
+        cache_creation: None,
+    };
+    let official_cost = model_prices(&model).map(|_| calculate_cost(&model, &usage));
 
-    let all_entries = get_all_usage_entries(&claude_path);
+    let mut estimates = vec![ProviderCostEstimate {
+        provider_id: None,
+        provider_name: "Anthropic".to_string(),
+        base_url: "https://api.anthropic.com".to_string(),
+        cost: official_cost,
+        pricing_source: if official_cost.is_some() { "model" } else { "unknown" }.to_string(),
+    }];
+    estimates.extend(load_providers_from_file()?.into_iter().map(|provider| {
+        let cost = provider
+            .pricing_override
+            .map(|pricing| pricing.cost(input_tokens, output_tokens, cache_creation_tokens, cache_read_tokens));
+        ProviderCostEstimate {
+            provider_id: Some(provider.id),
+            provider_name: provider.name,
+            base_url: provider.base_url,
+            cost,
+            pricing_source: if cost.is_some() { "override" } else { "unknown" }.to_string(),
+        }
+    }));
 
-    let since_date = since.and_then(|s| NaiveDate::parse_from_str(&s, "%Y%m%d").ok());
-    let until_date = until.and_then(|s| NaiveDate::parse_from_str(&s, "%Y%m%d").ok());
+    // Known costs ascending, unknown ones last
+    estimates.sort_by(|a, b| match (a.cost, b.cost) {
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    });
 
-    let filtered_entries: Vec<_> = all_entries
-        .into_iter()
-        .filter(|e| {
-            if let Ok(dt) = DateTime::parse_from_rfc3339(&e.timestamp) {
-                let date = dt.date_naive();
-                let is_after_since = since_date.map_or(true, |s| date >= s);
-                let is_before_until = until_date.map_or(true, |u| date <= u);
-                is_after_since && is_before_until
-            } else {
-                false
-            }
-        })
-        .collect();
+    Ok(estimates)
+}
 
-    let mut session_stats: HashMap<String, ProjectUsage> = HashMap::new();
-    for entry in &filtered_entries {
-        let session_key = format!("{}/{}", entry.project_path, entry.session_id);
-        let project_stat = session_stats
-            .entry(session_key)
-            .or_insert_with(|| ProjectUsage {
-                project_path: entry.project_path.clone(),
-                project_name: entry.session_id.clone(), // Using session_id as project_name for session view
-                total_cost: 0.0,
-                total_tokens: 0,
-                session_count: 0, // In this context, this will count entries per session
-                last_used: " ".to_string(),
-            });
+// Rough Claude tokenizer ratios: English-like ASCII text averages ~3.5 characters
+// per token, while CJK and other non-ASCII characters are close to one token each
+const ASCII_CHARS_PER_TOKEN: f64 = 3.5;
+const NON_ASCII_TOKENS_PER_CHAR: f64 = 1.0;
 
-        project_stat.total_cost += entry.cost;
-        project_stat.total_tokens += entry.input_tokens
-            + entry.output_tokens
-            + entry.cache_creation_tokens
-            + entry.cache_read_tokens;
-        // Session count will be set later from unique session tracking
-        if entry.timestamp > project_stat.last_used {
-            project_stat.last_used = entry.timestamp.clone();
+/// Approximate the number of tokens Claude would count for `text`.
+/// This is a character-based heuristic, not the real tokenizer, so treat the
+/// result as an estimate (typically within ~20% for prose and code).
+#[command]
+pub fn estimate_tokens(text: String, model: Option<String>) -> Result<u64, CommandError> {
+    // Current Claude models share a tokenizer; the model only has to be one we know
+    if let Some(model) = model.as_deref() {
+        if model_prices(model).is_none() {
+            return Err(CommandError::NotFound(format!("Unknown model: {}", model)));
         }
     }
 
-    let mut by_session: Vec<ProjectUsage> = session_stats.into_values().collect();
+    let (ascii, non_ascii) = text
+        .chars()
+        .fold((0u64, 0u64), |(ascii, non_ascii), c| {
+            if c.is_ascii() { (ascii + 1, non_ascii) } else { (ascii, non_ascii + 1) }
+        });
+    let estimate = ascii as f64 / ASCII_CHARS_PER_TOKEN + non_ascii as f64 * NON_ASCII_TOKENS_PER_CHAR;
 
-    // Sort by last_used date
-    if let Some(order_str) = order {
-        if order_str == "asc" {
-            by_session.sort_by(|a, b| a.last_used.cmp(&b.last_used));
-        } else {
-            by_session.sort_by(|a, b| b.last_used.cmp(&a.last_used));
+    Ok(estimate.ceil() as u64)
+}
+
+#[derive(Debug, Serialize)]
+pub struct SettingsValidation {
+    path: String,
+    exists: bool,
+    valid: bool,
+    env_keys: Vec<String>,  // sorted keys of the `env` object
+    error: Option<String>,
+    line: Option<usize>,  // 1-based position of the parse error
+    column: Option<usize>,
+}
+
+/// Check that settings.json parses the way base URL resolution expects, so a
+/// malformed file doesn't silently fall back to the default endpoint
+#[command]
+pub fn validate_claude_settings() -> Result<SettingsValidation, CommandError> {
+    let settings_path = get_claude_config_dir()?.join("settings.json");
+    let mut validation = SettingsValidation {
+        path: settings_path.to_string_lossy().to_string(),
+        exists: settings_path.exists(),
+        valid: false,
+        env_keys: Vec::new(),
+        error: None,
+        line: None,
+        column: None,
+    };
+
+    if !validation.exists {
+        return Ok(validation);
+    }
+
+    let content = fs::read_to_string(&settings_path)
+        .map_err(|e| CommandError::FileRead(format!("Failed to read settings.json: {}", e)))?;
+
+    match serde_json::from_str::<ClaudeSettings>(&content) {
+        Ok(settings) => {
+            validation.valid = true;
+            validation.env_keys = settings.env.map(|env| env.into_keys().collect()).unwrap_or_default();
+            validation.env_keys.sort();
+        }
+        Err(e) => {
+            validation.error = Some(e.to_string());
+            if e.line() > 0 {
+                validation.line = Some(e.line());
+                validation.column = Some(e.column());
+            }
         }
-    } else {
-        // Default to descending
-        by_session.sort_by(|a, b| b.last_used.cmp(&a.last_used));
     }
 
-    Ok(by_session)
+    Ok(validation)
+}
+
+/// The base URL usage is attributed to, and which source (env, settings.json or default) provided it
+#[command]
+pub fn resolve_api_base_url() -> Result<ResolvedConfig, CommandError> {
+    Ok(resolve_api_base_url_with_source())
 }
 
 #[command]
-pub fn get_usage_by_api_base_url() -> Result<Vec<ApiBaseUrlUsage>, String> {
-    let claude_path = dirs::home_dir()
-        .ok_or("Failed to get home directory")?
-        .join(".claude");
+pub fn get_usage_by_api_base_url() -> Result<Vec<ApiBaseUrlUsage>, CommandError> {
+    let claude_path = get_claude_config_dir()?;
 
     let all_entries = get_all_usage_entries(&claude_path);
 
@@ -1232,19 +4145,22 @@ pub fn get_usage_by_api_base_url() -> Result<Vec<ApiBaseUrlUsage>, String> {
                 cache_creation_tokens: 0,
                 cache_read_tokens: 0,
                 session_count: 0,
+                provider_name: None,
             });
 
         api_base_url_stat.total_cost += entry.cost;
-        api_base_url_stat.input_tokens += entry.input_tokens;
-        api_base_url_stat.output_tokens += entry.output_tokens;
-        api_base_url_stat.cache_creation_tokens += entry.cache_creation_tokens;
-        api_base_url_stat.cache_read_tokens += entry.cache_read_tokens;
-        api_base_url_stat.total_tokens = api_base_url_stat.input_tokens + api_base_url_stat.output_tokens + api_base_url_stat.cache_creation_tokens + api_base_url_stat.cache_read_tokens;
+        api_base_url_stat.input_tokens = api_base_url_stat.input_tokens.saturating_add(entry.input_tokens);
+        api_base_url_stat.output_tokens = api_base_url_stat.output_tokens.saturating_add(entry.output_tokens);
+        api_base_url_stat.cache_creation_tokens = api_base_url_stat.cache_creation_tokens.saturating_add(entry.cache_creation_tokens);
+        api_base_url_stat.cache_read_tokens = api_base_url_stat.cache_read_tokens.saturating_add(entry.cache_read_tokens);
+        api_base_url_stat.total_tokens = sum_tokens([api_base_url_stat.input_tokens, api_base_url_stat.output_tokens, api_base_url_stat.cache_creation_tokens, api_base_url_stat.cache_read_tokens]);
         // Session count will be set later from unique session tracking
     }
 
+    let providers = saved_providers();
     let mut by_api_base_url: Vec<ApiBaseUrlUsage> = api_base_url_stats.into_iter().map(|(api_url, mut stat)| {
         stat.session_count = api_sessions.get(&api_url).map(|s| s.len()).unwrap_or(0) as u64;
+        stat.provider_name = find_provider_by_base_url(&providers, &api_url).map(|p| p.name.clone());
         stat
     }).collect();
     by_api_base_url.sort_by(|a, b| b.total_cost.partial_cmp(&a.total_cost).unwrap());
@@ -1255,7 +4171,8 @@ pub fn get_usage_by_api_base_url() -> Result<Vec<ApiBaseUrlUsage>, String> {
 #[derive(Debug, Serialize)]
 pub struct ActiveSessionInfo {
     session_id: String,
-    project_path: String,
+    project_path: String,  // project of the session's earliest entry
+    project_paths: Vec<String>,  // every project the session wrote to, sorted
     start_time: String,
     last_activity: String,
     total_tokens: u64,
@@ -1265,67 +4182,163 @@ pub struct ActiveSessionInfo {
 }
 
 #[command]
-pub fn get_active_sessions() -> Result<Vec<ActiveSessionInfo>, String> {
-    let claude_path = dirs::home_dir()
-        .ok_or("Failed to get home directory")?
-        .join(".claude");
+pub fn get_session_window_hours() -> Result<i64, CommandError> {
+    Ok(session_window_hours())
+}
 
-    let all_entries = get_all_usage_entries(&claude_path);
-    if all_entries.is_empty() {
-        return Ok(vec![]);
+#[command]
+pub fn set_session_window_hours(hours: i64) -> Result<i64, CommandError> {
+    if !(1..=MAX_SESSION_WINDOW_HOURS).contains(&hours) {
+        return Err(CommandError::Validation(format!(
+            "Session window must be between 1 and {} hours",
+            MAX_SESSION_WINDOW_HOURS
+        )));
+    }
+
+    let _guard = lock_usage_files()?;
+    let mut prefs = load_usage_prefs();
+    prefs.session_window_hours = Some(hours);
+    save_usage_prefs(&prefs)?;
+
+    Ok(hours)
+}
+
+#[command]
+pub fn get_window_token_cap() -> Result<Option<u64>, CommandError> {
+    Ok(load_usage_prefs().window_token_cap)
+}
+
+/// Set the per-window token cap used by burn rate analysis; `None` removes it
+#[command]
+pub fn set_window_token_cap(cap: Option<u64>) -> Result<Option<u64>, CommandError> {
+    if cap == Some(0) {
+        return Err(CommandError::Validation("Token cap must be greater than zero".to_string()));
     }
 
-    let session_starts = track_active_sessions(&all_entries);
-    let current_time = Local::now();
-    
+    let _guard = lock_usage_files()?;
+    let mut prefs = load_usage_prefs();
+    prefs.window_token_cap = cap;
+    save_usage_prefs(&prefs)?;
+
+    Ok(cap)
+}
+
+#[command]
+pub fn get_follow_symlinks() -> Result<bool, CommandError> {
+    Ok(follow_symlinks())
+}
+
+/// Whether usage scans follow symlinked project and session directories
+#[command]
+pub fn set_follow_symlinks(enabled: bool) -> Result<bool, CommandError> {
+    let _guard = lock_usage_files()?;
+    let mut prefs = load_usage_prefs();
+    prefs.follow_symlinks = Some(enabled);
+    save_usage_prefs(&prefs)?;
+
+    Ok(enabled)
+}
+
+#[command]
+pub fn get_active_sessions() -> Result<Vec<ActiveSessionInfo>, CommandError> {
+    let claude_path = get_claude_config_dir()?;
+
+    let all_entries = get_all_usage_entries(&claude_path);
+
+    Ok(build_active_sessions(&all_entries))
+}
+
+// Per-session totals collected by build_active_sessions
+struct SessionAccumulator {
+    total_tokens: u64,
+    total_cost: f64,
+    first_entry: (Option<DateTime<FixedOffset>>, String),  // earliest entry time and its project
+    last_entry: (Option<DateTime<FixedOffset>>, String),  // latest entry time and its raw timestamp
+    project_paths: BTreeSet<String>,
+}
+
+fn build_active_sessions(all_entries: &[UsageEntry]) -> Vec<ActiveSessionInfo> {
+    build_active_sessions_at(all_entries, Local::now(), session_window_hours())
+}
+
+// Scans return entries sorted by timestamp, but nothing here relies on it: start
+// time, project and last activity are all picked by comparing entry times (ties
+// broken by content), and the output is fully ordered, so the same entries always
+// give the same sessions in the same order.
+fn build_active_sessions_at(
+    all_entries: &[UsageEntry],
+    current_time: DateTime<Local>,
+    window_hours: i64,
+) -> Vec<ActiveSessionInfo> {
     // Group entries by session
-    let mut session_data: HashMap<String, (u64, f64, String, String)> = HashMap::new();
+    let mut session_data: HashMap<String, SessionAccumulator> = HashMap::new();
     
-    for entry in &all_entries {
+    for entry in all_entries {
+        let entry_time = parse_entry_time(&entry.timestamp);
         let session_stats = session_data
             .entry(entry.session_id.clone())
-            .or_insert((0, 0.0, entry.project_path.clone(), entry.timestamp.clone()));
+            .or_insert_with(|| SessionAccumulator {
+                total_tokens: 0,
+                total_cost: 0.0,
+                first_entry: (entry_time, entry.project_path.clone()),
+                last_entry: (entry_time, entry.timestamp.clone()),
+                project_paths: BTreeSet::new(),
+            });
             
-        session_stats.0 += entry.input_tokens + entry.output_tokens + entry.cache_creation_tokens + entry.cache_read_tokens;
-        session_stats.1 += entry.cost;
+        session_stats.total_tokens = session_stats.total_tokens.saturating_add(entry.total_tokens());
+        session_stats.total_cost += entry.cost;
+        session_stats.project_paths.insert(entry.project_path.clone());
+        
+        // A session that moved between project directories is attributed to the
+        // project of its earliest entry, independent of input order
+        let candidate = (entry_time, entry.project_path.clone());
+        if entry_time.is_some()
+            && (session_stats.first_entry.0.is_none() || candidate < session_stats.first_entry)
+        {
+            session_stats.first_entry = candidate;
+        }
         
         // Update last activity if this entry is more recent
-        if entry.timestamp > session_stats.3 {
-            session_stats.3 = entry.timestamp.clone();
+        let candidate = (entry_time, entry.timestamp.clone());
+        if candidate > session_stats.last_entry {
+            session_stats.last_entry = candidate;
         }
     }
     
     let mut active_sessions = Vec::new();
     
-    for (session_id, (total_tokens, total_cost, project_path, last_activity)) in session_data {
-        if let Some(start_time) = session_starts.get(&session_id) {
-            let elapsed_hours = current_time.signed_duration_since(*start_time).num_hours() as f64;
-            let time_remaining = (SESSION_WINDOW_HOURS as f64) - elapsed_hours;
+    for (session_id, stats) in session_data {
+        // Sessions without a single parseable timestamp have no window to report
+        if let Some(start_time) = stats.first_entry.0.map(|time| time.with_timezone(&Local)) {
+            let elapsed_hours = current_time.signed_duration_since(start_time).num_hours() as f64;
+            let time_remaining = (window_hours as f64) - elapsed_hours;
             let is_active = time_remaining > 0.0;
             
             active_sessions.push(ActiveSessionInfo {
                 session_id,
-                project_path,
+                project_path: stats.first_entry.1,
+                project_paths: stats.project_paths.into_iter().collect(),
                 start_time: start_time.to_rfc3339(),
-                last_activity,
-                total_tokens,
-                total_cost,
+                last_activity: stats.last_entry.1,
+                total_tokens: stats.total_tokens,
+                total_cost: stats.total_cost,
                 time_remaining_hours: time_remaining.max(0.0),
                 is_active,
             });
         }
     }
     
-    // Sort by remaining time (active sessions first, then by time remaining)
+    // Active sessions first, then by time remaining; ties by start time and id
+    // so HashMap iteration order never leaks into the result
     active_sessions.sort_by(|a, b| {
-        match (a.is_active, b.is_active) {
-            (true, false) => std::cmp::Ordering::Less,
-            (false, true) => std::cmp::Ordering::Greater,
-            _ => b.time_remaining_hours.partial_cmp(&a.time_remaining_hours).unwrap(),
-        }
+        b.is_active
+            .cmp(&a.is_active)
+            .then_with(|| b.time_remaining_hours.total_cmp(&a.time_remaining_hours))
+            .then_with(|| a.start_time.cmp(&b.start_time))
+            .then_with(|| a.session_id.cmp(&b.session_id))
     });
     
-    Ok(active_sessions)
+    active_sessions
 }
 
 #[derive(Debug, Serialize)]
@@ -1334,32 +4347,184 @@ pub struct BurnRateInfo {
     estimated_depletion_time: Option<String>,  // when tokens will run out
     session_utilization: f64,  // percentage of session time used
     recommendations: Vec<String>,
+    burn_rate_series: Vec<BurnRatePoint>,  // per-bucket history, oldest first
+    window_token_cap: Option<u64>,  // configured cap, if any
+    tokens_used_this_window: Option<u64>,  // busiest active session's tokens, only with a cap
+    percent_of_cap: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BurnRatePoint {
+    timestamp: String,  // bucket start (RFC3339, local time)
+    tokens_per_minute: f64,
+}
+
+#[command]
+pub fn get_burn_rate_analysis() -> Result<BurnRateInfo, CommandError> {
+    let claude_path = get_claude_config_dir()?;
+
+    let all_entries = get_all_usage_entries(&claude_path);
+
+    Ok(analyze_burn_rate(&all_entries))
+}
+
+#[derive(Debug, Serialize)]
+pub struct LiveSessionRate {
+    session_id: String,
+    project_path: String,
+    last_activity: String,
+    recent_tokens: u64,  // tokens in the last LIVE_SESSION_MINUTES minutes
+    tokens_per_minute: f64,
+    window_end: String,  // session start plus the session window (RFC3339, local time)
+    minutes_to_window_close: f64,
+    projected_tokens_to_window_close: u64,  // at the current rate, until the window closes
 }
 
+/// Sessions that logged usage in the last few minutes, with each one's current token
+/// rate and what it would add before its window closes. Sorted by rate, fastest first.
 #[command]
-pub fn get_burn_rate_analysis() -> Result<BurnRateInfo, String> {
-    let claude_path = dirs::home_dir()
-        .ok_or("Failed to get home directory")?
-        .join(".claude");
+pub fn get_live_session_rates() -> Result<Vec<LiveSessionRate>, CommandError> {
+    let claude_path = get_claude_config_dir()?;
 
     let all_entries = get_all_usage_entries(&claude_path);
+
+    Ok(build_live_session_rates(&all_entries, Local::now(), session_window_hours()))
+}
+
+fn build_live_session_rates(entries: &[UsageEntry], current_time: DateTime<Local>, window_hours: i64) -> Vec<LiveSessionRate> {
+    let recent_since = current_time - Duration::minutes(LIVE_SESSION_MINUTES);
+    let session_starts = track_active_sessions(entries);
+
+    // session id -> (recent tokens, latest entry)
+    let mut recent: HashMap<&str, (u64, &UsageEntry)> = HashMap::new();
+    for entry in entries {
+        let entry_time = match parse_entry_time(&entry.timestamp) {
+            Some(time) => time.with_timezone(&Local),
+            None => continue,
+        };
+        if entry_time <= recent_since || entry_time > current_time {
+            continue;
+        }
+        let tokens = entry.total_tokens();
+        let session = recent.entry(entry.session_id.as_str()).or_insert((0, entry));
+        session.0 = session.0.saturating_add(tokens);
+        if entry.timestamp > session.1.timestamp {
+            session.1 = entry;
+        }
+    }
+
+    let mut rates: Vec<LiveSessionRate> = recent
+        .into_iter()
+        .filter_map(|(session_id, (recent_tokens, latest))| {
+            let window_end = *session_starts.get(session_id)? + Duration::hours(window_hours);
+            let minutes_to_window_close = (window_end.signed_duration_since(current_time).num_seconds() as f64 / 60.0).max(0.0);
+            let tokens_per_minute = recent_tokens as f64 / LIVE_SESSION_MINUTES as f64;
+            Some(LiveSessionRate {
+                session_id: session_id.to_string(),
+                project_path: latest.project_path.clone(),
+                last_activity: latest.timestamp.clone(),
+                recent_tokens,
+                tokens_per_minute,
+                window_end: window_end.to_rfc3339(),
+                minutes_to_window_close,
+                projected_tokens_to_window_close: (tokens_per_minute * minutes_to_window_close).round() as u64,
+            })
+        })
+        .collect();
+    rates.sort_by(|a, b| {
+        b.tokens_per_minute
+            .total_cmp(&a.tokens_per_minute)
+            .then_with(|| a.session_id.cmp(&b.session_id))
+    });
+
+    rates
+}
+
+// Bucket token usage into fixed windows ending at `current_time`, zero-filling idle buckets
+fn build_burn_rate_series(entries: &[UsageEntry], current_time: DateTime<Local>) -> Vec<BurnRatePoint> {
+    let bucket_secs = BURN_RATE_BUCKET_MINUTES * 60;
+    let bucket_count = BURN_RATE_SERIES_HOURS * 60 / BURN_RATE_BUCKET_MINUTES;
+    let current_bucket = current_time.timestamp().div_euclid(bucket_secs);
+    let first_bucket = current_bucket - bucket_count + 1;
+
+    let mut bucket_tokens = vec![0u64; bucket_count as usize];
+    for entry in entries {
+        if let Some(entry_time) = parse_entry_time(&entry.timestamp) {
+            let bucket = entry_time.timestamp().div_euclid(bucket_secs);
+            if bucket >= first_bucket && bucket <= current_bucket {
+                let slot = &mut bucket_tokens[(bucket - first_bucket) as usize];
+                *slot = slot.saturating_add(entry.total_tokens());
+            }
+        }
+    }
+
+    bucket_tokens
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, tokens)| {
+            let start = DateTime::from_timestamp((first_bucket + i as i64) * bucket_secs, 0)?;
+            Some(BurnRatePoint {
+                timestamp: start.with_timezone(&Local).to_rfc3339(),
+                tokens_per_minute: tokens as f64 / BURN_RATE_BUCKET_MINUTES as f64,
+            })
+        })
+        .collect()
+}
+
+// Tokens used by the busiest session still inside its window, counting only
+// entries from the session start up to the window end
+fn busiest_window_tokens(entries: &[UsageEntry], current_time: DateTime<Local>, window_hours: i64) -> u64 {
+    let window = Duration::hours(window_hours);
+    let session_starts = track_active_sessions(entries);
+    let mut session_tokens: HashMap<&str, u64> = HashMap::new();
+
+    for entry in entries {
+        let start = match session_starts.get(&entry.session_id) {
+            Some(start) if current_time.signed_duration_since(*start) < window => *start,
+            _ => continue,
+        };
+        if let Some(entry_time) = parse_entry_time(&entry.timestamp) {
+            if entry_time.with_timezone(&Local) < start + window {
+                let tokens = session_tokens.entry(entry.session_id.as_str()).or_insert(0);
+                *tokens = tokens.saturating_add(entry.total_tokens());
+            }
+        }
+    }
+
+    session_tokens.into_values().max().unwrap_or(0)
+}
+
+fn analyze_burn_rate(all_entries: &[UsageEntry]) -> BurnRateInfo {
+    let current_time = Local::now();
+    let burn_rate_series = build_burn_rate_series(all_entries, current_time);
+    let window_hours = session_window_hours();
+
+    let window_token_cap = load_usage_prefs().window_token_cap.filter(|cap| *cap > 0);
+    let tokens_used_this_window = window_token_cap.map(|_| busiest_window_tokens(all_entries, current_time, window_hours));
+    let percent_of_cap = window_token_cap
+        .zip(tokens_used_this_window)
+        .map(|(cap, used)| used as f64 / cap as f64 * 100.0);
+
     if all_entries.is_empty() {
-        return Ok(BurnRateInfo {
+        return BurnRateInfo {
             current_burn_rate: 0.0,
             estimated_depletion_time: None,
             session_utilization: 0.0,
             recommendations: vec!["No usage data available".to_string()],
-        });
+            burn_rate_series,
+            window_token_cap,
+            tokens_used_this_window,
+            percent_of_cap,
+        };
     }
 
-    let current_time = Local::now();
     let one_hour_ago = current_time - Duration::hours(1);
     
     // Filter entries from the last hour for burn rate calculation
     let recent_entries: Vec<_> = all_entries
         .iter()
         .filter(|entry| {
-            if let Ok(entry_time) = DateTime::parse_from_rfc3339(&entry.timestamp) {
+            if let Some(entry_time) = parse_entry_time(&entry.timestamp) {
                 entry_time.with_timezone(&Local) > one_hour_ago
             } else {
                 false
@@ -1368,28 +4533,32 @@ pub fn get_burn_rate_analysis() -> Result<BurnRateInfo, String> {
         .collect();
     
     if recent_entries.is_empty() {
-        return Ok(BurnRateInfo {
+        return BurnRateInfo {
             current_burn_rate: 0.0,
             estimated_depletion_time: None,
             session_utilization: 0.0,
             recommendations: vec!["No recent activity detected".to_string()],
-        });
+            burn_rate_series,
+            window_token_cap,
+            tokens_used_this_window,
+            percent_of_cap,
+        };
     }
     
     // Calculate burn rate (tokens per minute)
     let total_recent_tokens: u64 = recent_entries
         .iter()
-        .map(|entry| entry.input_tokens + entry.output_tokens + entry.cache_creation_tokens + entry.cache_read_tokens)
-        .sum();
+        .map(|entry| entry.total_tokens())
+        .fold(0, u64::saturating_add);
     
     let burn_rate = total_recent_tokens as f64 / 60.0; // per minute
     
     // Find active sessions and estimate when they'll run out
-    let session_starts = track_active_sessions(&all_entries);
+    let session_starts = track_active_sessions(all_entries);
     let active_sessions = session_starts
         .iter()
         .filter(|(_, start_time)| {
-            current_time.signed_duration_since(**start_time).num_hours() < SESSION_WINDOW_HOURS
+            current_time.signed_duration_since(**start_time).num_hours() < window_hours
         })
         .count();
     
@@ -1400,7 +4569,7 @@ pub fn get_burn_rate_analysis() -> Result<BurnRateInfo, String> {
             .map(|start| current_time.signed_duration_since(*start).num_hours() as f64)
             .sum::<f64>() / session_starts.len() as f64;
         
-        (avg_session_age / SESSION_WINDOW_HOURS as f64 * 100.0).min(100.0)
+        (avg_session_age / window_hours as f64 * 100.0).min(100.0)
     } else {
         0.0
     };
@@ -1408,14 +4577,31 @@ pub fn get_burn_rate_analysis() -> Result<BurnRateInfo, String> {
     // Generate recommendations
     let mut recommendations = Vec::new();
     
+    let peak_burn_rate = burn_rate_series
+        .iter()
+        .map(|point| point.tokens_per_minute)
+        .fold(0.0, f64::max);
+    
     if burn_rate > 100.0 {
-        recommendations.push("High burn rate detected. Consider optimizing prompts or using smaller models.".to_string());
+        recommendations.push(format!(
+            "High burn rate detected (peak {:.0} tokens/min over {} minutes). Consider optimizing prompts or using smaller models.",
+            peak_burn_rate, BURN_RATE_BUCKET_MINUTES
+        ));
     }
     
     if session_utilization > 80.0 {
         recommendations.push("Sessions are nearing expiration. Plan token-intensive tasks around session resets.".to_string());
     }
     
+    if let (Some(cap), Some(used), Some(percent)) = (window_token_cap, tokens_used_this_window, percent_of_cap) {
+        if percent >= WINDOW_CAP_WARNING_PERCENT {
+            recommendations.push(format!(
+                "The busiest session has used {:.0}% of its {}-hour token cap ({} of {} tokens). Plan token-intensive tasks around the next reset.",
+                percent, window_hours, used, cap
+            ));
+        }
+    }
+    
     if active_sessions > 3 {
         recommendations.push("Multiple active sessions detected. Consider consolidating work into fewer sessions.".to_string());
     }
@@ -1424,10 +4610,857 @@ pub fn get_burn_rate_analysis() -> Result<BurnRateInfo, String> {
         recommendations.push("Usage patterns look optimal.".to_string());
     }
     
-    Ok(BurnRateInfo {
+    // With a cap, project when the busiest session exhausts it at the current rate
+    let estimated_depletion_time = match (window_token_cap, tokens_used_this_window) {
+        (Some(cap), Some(used)) if burn_rate > 0.0 => {
+            let minutes_left = cap.saturating_sub(used) as f64 / burn_rate;
+            Duration::try_seconds((minutes_left * 60.0) as i64)
+                .and_then(|remaining| current_time.checked_add_signed(remaining))
+                .map(|depletion| depletion.to_rfc3339())
+        }
+        _ => None,
+    };
+    
+    BurnRateInfo {
         current_burn_rate: burn_rate,
-        estimated_depletion_time: None, // TODO: Implement based on current session limits
+        estimated_depletion_time,
         session_utilization,
         recommendations,
+        burn_rate_series,
+        window_token_cap,
+        tokens_used_this_window,
+        percent_of_cap,
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DashboardSnapshot {
+    stats: UsageStats,
+    active_sessions: Vec<ActiveSessionInfo>,
+    burn_rate: BurnRateInfo,
+}
+
+/// Scan usage logs once and return everything the main dashboard needs
+#[command]
+pub fn get_dashboard_snapshot(days: Option<u32>) -> Result<DashboardSnapshot, CommandError> {
+    let claude_path = get_claude_config_dir()?;
+
+    let all_entries = get_all_usage_entries(&claude_path);
+
+    // Session and burn rate analysis always look at the full history, like their standalone commands
+    let active_sessions = build_active_sessions(&all_entries);
+    let burn_rate = analyze_burn_rate(&all_entries);
+
+    let filtered_entries = filter_entries_by_days(all_entries, days);
+    let stats = aggregate_usage_stats(&filtered_entries);
+
+    Ok(DashboardSnapshot {
+        stats,
+        active_sessions,
+        burn_rate,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const TEST_BASE_URL: &str = "https://api.anthropic.com";
+
+    // Cached scan with fixed settings, so results don't depend on the machine's env or prefs
+    fn cached_scan(claude_path: &Path, force_rebuild: bool) -> Vec<UsageEntry> {
+        get_cached_usage_entries_in(claude_path, force_rebuild, TEST_BASE_URL, true)
+    }
+
+    fn usage_entry(session_id: &str, project_path: &str, timestamp: &str) -> UsageEntry {
+        UsageEntry {
+            timestamp: timestamp.to_string(),
+            model: "claude-sonnet-4".to_string(),
+            input_tokens: 100,
+            output_tokens: 50,
+            cache_creation_tokens: 0,
+            cache_read_tokens: 0,
+            cost: 1.0,
+            session_id: session_id.to_string(),
+            project_path: project_path.to_string(),
+            api_base_url: TEST_BASE_URL.to_string(),
+            estimated_cost: None,
+            cost_reported: false,
+            dedup_key: None,
+        }
+    }
+
+    // A log line reporting `usage` for message `msg_id`; tests add top-level fields as needed
+    fn usage_line(timestamp: &str, msg_id: &str, model: &str, usage: serde_json::Value) -> serde_json::Value {
+        serde_json::json!({
+            "timestamp": timestamp,
+            "message": { "id": msg_id, "model": model, "usage": usage }
+        })
+    }
+
+    // A cache-read-only line, deduped by message and request id
+    fn cache_read_line(timestamp: &str, msg_id: &str, req_id: &str) -> serde_json::Value {
+        let mut line = usage_line(timestamp, msg_id, "claude-sonnet-4", serde_json::json!({ "cache_read_input_tokens": 1000 }));
+        line["requestId"] = req_id.into();
+        line
+    }
+
+    #[test]
+    fn test_claude_config_dir_env_override() {
+        let env_dir = || Some("/env/claude".to_string());
+        let profile_dir = || Some(PathBuf::from("/profiles/work"));
+        let home = || Some(PathBuf::from("/home/dev"));
+
+        assert_eq!(
+            resolve_claude_config_dir(env_dir(), profile_dir(), home()).unwrap(),
+            PathBuf::from("/env/claude")
+        );
+        // A blank CLAUDE_CONFIG_DIR counts as unset
+        assert_eq!(
+            resolve_claude_config_dir(Some("  ".to_string()), profile_dir(), home()).unwrap(),
+            PathBuf::from("/profiles/work")
+        );
+        assert_eq!(
+            resolve_claude_config_dir(None, None, home()).unwrap(),
+            PathBuf::from("/home/dev/.claude")
+        );
+        assert!(matches!(
+            resolve_claude_config_dir(None, None, None),
+            Err(CommandError::HomeDirNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_entry_time_mixed_format_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let session_dir = temp_dir.path().join("session-1");
+        fs::create_dir_all(&session_dir).unwrap();
+        let log_path = session_dir.join("log.jsonl");
+
+        let line = |timestamp: &str, id: &str| {
+            usage_line(timestamp, id, "claude-sonnet-4", serde_json::json!({ "input_tokens": 10, "output_tokens": 5 })).to_string()
+        };
+        let content = [
+            line("2025-01-15T10:00:00Z", "msg-1"),
+            line("2025-01-15T11:30:00", "msg-2"),
+            line("2025-01-15 12:00:00.250", "msg-3"),
+        ]
+        .join("\n");
+        fs::write(&log_path, content).unwrap();
+
+        let entries = parse_jsonl_file(&log_path, "-tmp-project", "https://api.anthropic.com", DedupMode::default(), &mut HashSet::new());
+        assert_eq!(entries.len(), 3);
+        assert!(entries.iter().all(|e| parse_entry_time(&e.timestamp).is_some()));
+
+        // Naive timestamps are interpreted as local time
+        let naive = parse_entry_time("2025-01-15T11:30:00").unwrap();
+        assert_eq!(
+            naive.with_timezone(&Local).naive_local(),
+            NaiveDate::from_ymd_opt(2025, 1, 15).unwrap().and_hms_opt(11, 30, 0).unwrap()
+        );
+
+        assert!(parse_entry_time("not a timestamp").is_none());
+    }
+
+    #[test]
+    fn test_concurrent_usage_scans_match() {
+        let temp_dir = TempDir::new().unwrap();
+
+        // Distinct costs per model, project and day keep every sorted list unambiguous
+        for (project, session, day, model, tokens) in [
+            ("-tmp-alpha", "session-a", "2025-01-10", "claude-sonnet-4", 1_000),
+            ("-tmp-alpha", "session-b", "2025-01-11", "claude-opus-4", 2_000),
+            ("-tmp-beta", "session-c", "2025-01-12", "claude-3-5-haiku", 4_000),
+        ] {
+            let session_dir = temp_dir.path().join("projects").join(project).join(session);
+            fs::create_dir_all(&session_dir).unwrap();
+            let content = (0..50)
+                .map(|i| {
+                    let mut line = usage_line(
+                        &format!("{}T10:{:02}:00Z", day, i),
+                        &format!("{}-msg-{}", session, i),
+                        model,
+                        serde_json::json!({ "input_tokens": tokens, "output_tokens": tokens / 2 }),
+                    );
+                    line["cwd"] = format!("/tmp/{}", project.trim_start_matches("-tmp-")).into();
+                    line.to_string()
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            fs::write(session_dir.join("log.jsonl"), content).unwrap();
+        }
+
+        let results: Vec<serde_json::Value> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|_| scope.spawn(|| serde_json::to_value(get_usage_stats_in(temp_dir.path(), None, None, None, None, None)).unwrap()))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        assert_eq!(results[0]["total_sessions"], 3);
+        assert_eq!(results[0]["total_input_tokens"], 50 * (1_000 + 2_000 + 4_000));
+        assert!(results.iter().all(|r| r == &results[0]));
+    }
+
+    #[test]
+    fn test_scan_stamps_api_base_url_from_settings() {
+        let temp_dir = TempDir::new().unwrap();
+        let session_dir = temp_dir.path().join("projects").join("-tmp-project").join("session-1");
+        fs::create_dir_all(&session_dir).unwrap();
+        fs::write(
+            temp_dir.path().join("settings.json"),
+            r#"{"env": {"ANTHROPIC_BASE_URL": "https://gateway.example.com"}}"#,
+        )
+        .unwrap();
+        let content = (0..20)
+            .map(|i| {
+                usage_line(
+                    &format!("2025-01-15T10:{:02}:00Z", i),
+                    &format!("msg-{}", i),
+                    "claude-sonnet-4",
+                    serde_json::json!({ "input_tokens": 10, "output_tokens": 5 }),
+                )
+                .to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let log_path = session_dir.join("log.jsonl");
+        fs::write(&log_path, &content).unwrap();
+
+        // The env var still wins over settings.json
+        let from_env = resolve_api_base_url_in(Some("https://env.example.com".to_string()), Some(temp_dir.path()));
+        assert_eq!((from_env.value.as_str(), from_env.source), ("https://env.example.com", ConfigSource::Env));
+        let from_settings = resolve_api_base_url_in(None, Some(temp_dir.path()));
+        assert_eq!(from_settings.source, ConfigSource::Settings);
+        let resolved = from_settings.value;
+        let scanned = get_cached_usage_entries_in(temp_dir.path(), false, &resolved, true);
+
+        assert_eq!(resolved, "https://gateway.example.com");
+        assert_eq!(scanned.len(), 20);
+        assert!(scanned.iter().all(|e| e.api_base_url == resolved));
+
+        let expected = parse_usage_lines(content.lines(), &log_path, "-tmp-project", &resolved, DedupMode::default(), &mut HashSet::new());
+        assert_eq!(
+            serde_json::to_value(&scanned).unwrap(),
+            serde_json::to_value(&expected).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_settings_base_url_refreshes_when_settings_change() {
+        let temp_dir = TempDir::new().unwrap();
+        let settings_path = temp_dir.path().join("settings.json");
+        fs::write(&settings_path, r#"{"env": {"ANTHROPIC_BASE_URL": "https://a.example.com"}}"#).unwrap();
+        assert_eq!(settings_base_url(&settings_path).as_deref(), Some("https://a.example.com"));
+        assert_eq!(settings_base_url(&settings_path).as_deref(), Some("https://a.example.com"));
+
+        fs::write(&settings_path, r#"{"env": {"ANTHROPIC_BASE_URL": "https://gateway.example.com"}}"#).unwrap();
+        assert_eq!(settings_base_url(&settings_path).as_deref(), Some("https://gateway.example.com"));
+
+        fs::write(&settings_path, r#"{"env": {}}"#).unwrap();
+        assert_eq!(settings_base_url(&settings_path), None);
+        fs::remove_file(&settings_path).unwrap();
+        assert_eq!(settings_base_url(&settings_path), None);
+    }
+
+    #[test]
+    fn test_project_path_variants_merge() {
+        let entries = vec![
+            usage_entry("session-1", "/home/dev/my-app", "2025-01-10T10:00:00Z"),
+            usage_entry("session-2", "/home/dev/my-app/", "2025-01-12T10:00:00Z"),
+            usage_entry("session-3", "-home-dev-my-app", "2025-01-11T10:00:00Z"),
+            usage_entry("session-4", "/home/dev/other", "2025-01-09T10:00:00Z"),
+        ];
+
+        let stats = aggregate_usage_stats_with(&entries, false, &BTreeMap::new());
+        assert_eq!(stats.by_project.len(), 2);
+
+        let merged = &stats.by_project[0];
+        assert_eq!(merged.project_path, "/home/dev/my-app");
+        assert_eq!(merged.project_name, "my-app");
+        assert_eq!(merged.session_count, 3);
+        assert_eq!(merged.total_cost, 3.0);
+        assert_eq!(merged.last_used, "2025-01-12T10:00:00Z");
+
+        assert_eq!(trim_project_path("/"), "/");
+        assert_eq!(trim_project_path("C:\\work\\app\\"), "C:\\work\\app");
+    }
+
+    #[test]
+    fn test_session_spanning_projects_uses_earliest_project() {
+        let first = usage_entry("session-1", "/home/dev/app-a", "2025-01-10T10:00:00Z");
+        let second = usage_entry("session-1", "/home/dev/app-b", "2025-01-10T11:00:00Z");
+
+        for entries in [vec![first.clone(), second.clone()], vec![second, first]] {
+            let sessions = build_active_sessions_at(&entries, Local::now(), DEFAULT_SESSION_WINDOW_HOURS);
+            assert_eq!(sessions.len(), 1);
+            assert_eq!(sessions[0].project_path, "/home/dev/app-a");
+            assert_eq!(sessions[0].project_paths, vec!["/home/dev/app-a", "/home/dev/app-b"]);
+            assert_eq!(sessions[0].total_tokens, 300);
+            assert_eq!(sessions[0].last_activity, "2025-01-10T11:00:00Z");
+        }
+    }
+
+    #[test]
+    fn test_active_sessions_are_deterministic() {
+        // Sessions sharing a start time tie on time remaining; one mixes offsets so
+        // string order and time order of its timestamps disagree
+        let mut entries = vec![
+            usage_entry("session-b", "/home/dev/app-b", "2025-01-10T10:00:00Z"),
+            usage_entry("session-a", "/home/dev/app-a", "2025-01-10T10:00:00Z"),
+            usage_entry("session-a", "/home/dev/app-c", "2025-01-10T12:30:00+02:00"),
+            usage_entry("session-a", "/home/dev/app-a", "2025-01-10T11:00:00Z"),
+            usage_entry("session-c", "/home/dev/app-c", "2025-01-09T08:00:00Z"),
+        ];
+        let now = DateTime::parse_from_rfc3339("2025-01-10T12:00:00Z").unwrap().with_timezone(&Local);
+
+        let expected = serde_json::to_value(build_active_sessions_at(&entries, now, 5)).unwrap();
+        for _ in 0..entries.len() {
+            entries.rotate_left(1);
+            let sessions = build_active_sessions_at(&entries, now, 5);
+            assert_eq!(serde_json::to_value(&sessions).unwrap(), expected);
+        }
+
+        let sessions = build_active_sessions_at(&entries, now, 5);
+        let ids: Vec<&str> = sessions.iter().map(|s| s.session_id.as_str()).collect();
+        assert_eq!(ids, vec!["session-a", "session-b", "session-c"]);
+        assert_eq!(sessions[0].project_path, "/home/dev/app-a");
+        assert_eq!(sessions[0].last_activity, "2025-01-10T11:00:00Z");
+        assert!(!sessions[2].is_active);
+    }
+
+    #[test]
+    fn test_usage_aggregate_folds_new_logs() {
+        let temp_dir = TempDir::new().unwrap();
+        let claude_path = temp_dir.path().to_path_buf();
+        let line = |timestamp: &str, msg_id: &str, req_id: &str| cache_read_line(timestamp, msg_id, req_id).to_string();
+        let write_log = |session: &str, lines: &[String]| {
+            let session_dir = claude_path.join("projects").join("-tmp-project").join(session);
+            fs::create_dir_all(&session_dir).unwrap();
+            fs::write(session_dir.join("log.jsonl"), lines.join("\n")).unwrap();
+        };
+
+        write_log("session-1", &[line("2025-01-15T10:00:00Z", "msg-1", "req-1")]);
+        assert_eq!(cached_scan(&claude_path, false).len(), 1);
+        assert!(usage_aggregate_path(&claude_path).exists());
+
+        // The replayed cache line is deduped against the cached log without re-reading it
+        write_log(
+            "session-2",
+            &[
+                line("2025-01-15T10:00:00Z", "msg-1", "req-1"),
+                line("2025-01-15T11:00:00Z", "msg-2", "req-2"),
+            ],
+        );
+        let folded = cached_scan(&claude_path, false);
+        assert_eq!(folded.len(), 2);
+        assert_eq!(load_usage_aggregate(&claude_path).files.len(), 2);
+
+        let rebuilt = cached_scan(&claude_path, true);
+        assert_eq!(
+            serde_json::to_value(&folded).unwrap(),
+            serde_json::to_value(&rebuilt).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_usage_aggregate_resumes_appended_lines() {
+        let temp_dir = TempDir::new().unwrap();
+        let claude_path = temp_dir.path().to_path_buf();
+        let line = |timestamp: &str, msg_id: &str, req_id: &str| {
+            let mut line = cache_read_line(timestamp, msg_id, req_id);
+            line["cwd"] = "/tmp/project".into();
+            line.to_string()
+        };
+        let session_dir = claude_path.join("projects").join("-tmp-project").join("session-1");
+        fs::create_dir_all(&session_dir).unwrap();
+        let log_path = session_dir.join("log.jsonl");
+        let append = |text: &str| {
+            let mut file = fs::OpenOptions::new().create(true).append(true).open(&log_path).unwrap();
+            file.write_all(text.as_bytes()).unwrap();
+        };
+        let cached_offset = || {
+            load_usage_aggregate(&claude_path)
+                .files
+                .values()
+                .next()
+                .and_then(|f| f.offset)
+                .unwrap()
+        };
+        let assert_matches_rebuild = |entries: &Vec<UsageEntry>| {
+            let rebuilt = cached_scan(&claude_path, true);
+            assert_eq!(
+                serde_json::to_value(entries).unwrap(),
+                serde_json::to_value(&rebuilt).unwrap()
+            );
+        };
+
+        append(&format!("{}\n", line("2025-01-15T10:00:00Z", "msg-1", "req-1")));
+        assert_eq!(cached_scan(&claude_path, false).len(), 1);
+        let first_offset = cached_offset();
+        assert_eq!(first_offset, fs::metadata(&log_path).unwrap().len());
+
+        // A new line, a replay of the first one, and a line still being written
+        let partial = line("2025-01-15T12:00:00Z", "msg-3", "req-3");
+        let (head, tail) = partial.split_at(partial.len() / 2);
+        append(&format!(
+            "{}\n{}\n{}",
+            line("2025-01-15T11:00:00Z", "msg-2", "req-2"),
+            line("2025-01-15T10:00:00Z", "msg-1", "req-1"),
+            head
+        ));
+        let resumed = cached_scan(&claude_path, false);
+        assert_eq!(resumed.len(), 2);
+        let resumed_offset = cached_offset();
+        assert!(resumed_offset > first_offset);
+        assert_eq!(resumed_offset + head.len() as u64, fs::metadata(&log_path).unwrap().len());
+        assert_matches_rebuild(&resumed);
+
+        // Finishing the partial line picks it up from the saved offset
+        append(&format!("{}\n", tail));
+        let completed = cached_scan(&claude_path, false);
+        assert_eq!(completed.len(), 3);
+        assert_eq!(cached_offset(), fs::metadata(&log_path).unwrap().len());
+        assert_matches_rebuild(&completed);
+    }
+
+    #[test]
+    fn test_usage_aggregate_appends_only_to_grown_shard() {
+        let temp_dir = TempDir::new().unwrap();
+        let claude_path = temp_dir.path().to_path_buf();
+        let line = |timestamp: &str, msg_id: &str| format!("{}\n", cache_read_line(timestamp, msg_id, &format!("req-{}", msg_id)));
+        let log_path = |session: &str| {
+            let session_dir = claude_path.join("projects").join("-tmp-project").join(session);
+            fs::create_dir_all(&session_dir).unwrap();
+            session_dir.join("log.jsonl")
+        };
+        let shard_path = |session: &str| {
+            let key = log_path(session).to_string_lossy().to_string();
+            usage_aggregate_shard_dir(&claude_path).join(usage_aggregate_shard_name(&key))
+        };
+        let shard = |session: &str| fs::read(shard_path(session)).unwrap();
+
+        fs::write(log_path("session-1"), line("2025-01-15T10:00:00Z", "msg-1")).unwrap();
+        fs::write(log_path("session-2"), line("2025-01-15T11:00:00Z", "msg-2")).unwrap();
+        assert_eq!(cached_scan(&claude_path, false).len(), 2);
+        let (first, second) = (shard("session-1"), shard("session-2"));
+
+        let mut file = fs::OpenOptions::new().append(true).open(log_path("session-2")).unwrap();
+        file.write_all(line("2025-01-15T12:00:00Z", "msg-3").as_bytes()).unwrap();
+        drop(file);
+        assert_eq!(cached_scan(&claude_path, false).len(), 3);
+
+        // Only the grown log's shard changes, and only by appending; the index holds no entries
+        assert_eq!(shard("session-1"), first);
+        let grown = shard("session-2");
+        assert!(grown.len() > second.len() && grown.starts_with(&second));
+        let index = fs::read_to_string(usage_aggregate_path(&claude_path)).unwrap();
+        assert!(!index.contains("timestamp"));
+
+        // Bytes past the indexed length are dropped on the next append, and a
+        // missing shard just reparses its log
+        let mut file = fs::OpenOptions::new().append(true).open(shard_path("session-2")).unwrap();
+        file.write_all(b"{\"torn\":").unwrap();
+        drop(file);
+        fs::remove_file(shard_path("session-1")).unwrap();
+        let mut file = fs::OpenOptions::new().append(true).open(log_path("session-2")).unwrap();
+        file.write_all(line("2025-01-15T13:00:00Z", "msg-4").as_bytes()).unwrap();
+        drop(file);
+        let entries = cached_scan(&claude_path, false);
+        assert_eq!(entries.len(), 4);
+        assert_eq!(shard("session-1"), first);
+        assert!(!String::from_utf8(shard("session-2")).unwrap().contains("torn"));
+        assert_eq!(
+            serde_json::to_value(&entries).unwrap(),
+            serde_json::to_value(cached_scan(&claude_path, true)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_usage_aggregate_drops_removed_logs() {
+        let temp_dir = TempDir::new().unwrap();
+        let claude_path = temp_dir.path().to_path_buf();
+        let log_path = |session: &str| {
+            let session_dir = claude_path.join("projects").join("-tmp-project").join(session);
+            fs::create_dir_all(&session_dir).unwrap();
+            session_dir.join("log.jsonl")
+        };
+        let shard_path = |session: &str| {
+            let key = log_path(session).to_string_lossy().to_string();
+            usage_aggregate_shard_dir(&claude_path).join(usage_aggregate_shard_name(&key))
+        };
+        let modified = |path: &Path| fs::metadata(path).unwrap().modified().unwrap();
+
+        fs::write(log_path("session-1"), format!("{}\n", cache_read_line("2025-01-15T10:00:00Z", "msg-1", "req-1"))).unwrap();
+        fs::write(log_path("session-2"), format!("{}\n", cache_read_line("2025-01-15T11:00:00Z", "msg-2", "req-2"))).unwrap();
+        assert_eq!(cached_scan(&claude_path, false).len(), 2);
+        set_file_age(&shard_path("session-1"), 1);
+        let kept_modified = modified(&shard_path("session-1"));
+
+        // The removed log and its shard drop out; the other log's shard isn't rewritten
+        fs::remove_file(log_path("session-2")).unwrap();
+        let entries = cached_scan(&claude_path, false);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].timestamp, "2025-01-15T10:00:00Z");
+        assert!(!shard_path("session-2").exists());
+        assert_eq!(modified(&shard_path("session-1")), kept_modified);
+        assert_eq!(load_usage_aggregate(&claude_path).files.len(), 1);
+    }
+
+    #[test]
+    fn test_cache_writes_priced_by_ttl() {
+        for model in ["claude-opus-4", "claude-sonnet-4", "claude-sonnet-3.7", "claude-sonnet-3.5", "claude-haiku-3.5"] {
+            let (input, _, write_5m, write_1h, _) = model_prices(model).unwrap();
+            assert!((write_5m - input * 1.25).abs() < 1e-9, "{} 5m write price", model);
+            assert!((write_1h - input * 2.0).abs() < 1e-9, "{} 1h write price", model);
+        }
+
+        let cost = |usage: serde_json::Value| calculate_cost("claude-sonnet-4", &serde_json::from_value(usage).unwrap());
+        // 1M tokens at 5m, 1M at 1h, and 1M not covered by the breakdown (billed as 5m)
+        let split = cost(serde_json::json!({
+            "cache_creation_input_tokens": 3_000_000,
+            "cache_creation": { "ephemeral_5m_input_tokens": 1_000_000, "ephemeral_1h_input_tokens": 1_000_000 }
+        }));
+        assert!((split - (2.0 * SONNET_4_CACHE_WRITE_PRICE + SONNET_4_CACHE_WRITE_1H_PRICE)).abs() < 1e-9);
+
+        // Without a breakdown the whole cache write is priced at the 5m rate
+        let total_only = cost(serde_json::json!({ "cache_creation_input_tokens": 1_000_000 }));
+        assert!((total_only - SONNET_4_CACHE_WRITE_PRICE).abs() < 1e-9);
+        let empty_breakdown = cost(serde_json::json!({ "cache_creation_input_tokens": 1_000_000, "cache_creation": {} }));
+        assert!((empty_breakdown - SONNET_4_CACHE_WRITE_PRICE).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_token_sums_saturate() {
+        let entry = |session_id: &str| UsageEntry {
+            input_tokens: u64::MAX - 1,
+            output_tokens: u64::MAX - 1,
+            cache_creation_tokens: 1,
+            cache_read_tokens: 1,
+            ..usage_entry(session_id, "/home/dev/app", "2025-01-15T10:00:00Z")
+        };
+        let entries = vec![entry("session-1"), entry("session-2")];
+
+        let stats = aggregate_usage_stats_with(&entries, false, &BTreeMap::new());
+        assert_eq!(stats.total_input_tokens, u64::MAX);
+        assert_eq!(stats.total_cache_read_tokens, 2);
+        assert_eq!(stats.total_tokens, u64::MAX);
+        assert_eq!(stats.by_model[0].total_tokens, u64::MAX);
+        assert_eq!(stats.by_project[0].total_tokens, u64::MAX);
+        assert_eq!(stats.by_date[0].total_tokens, u64::MAX);
+        let sessions = build_active_sessions_at(&entries, Local::now(), DEFAULT_SESSION_WINDOW_HOURS);
+        assert_eq!(sessions[0].total_tokens, u64::MAX);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_scan_follows_symlinked_session_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        let claude_path = temp_dir.path().join(".claude");
+        let project_dir = claude_path.join("projects").join("-tmp-project");
+        fs::create_dir_all(&project_dir).unwrap();
+
+        // Session storage lives on "another drive" and is linked into the project
+        let storage = temp_dir.path().join("storage").join("session-1");
+        fs::create_dir_all(&storage).unwrap();
+        let line = usage_line("2025-01-15T10:00:00Z", "msg-1", "claude-sonnet-4", serde_json::json!({ "input_tokens": 10, "output_tokens": 5 }));
+        fs::write(storage.join("log.jsonl"), line.to_string()).unwrap();
+        std::os::unix::fs::symlink(&storage, project_dir.join("session-1")).unwrap();
+
+        let followed = list_usage_files(&claude_path, true);
+        let not_followed = list_usage_files(&claude_path, false);
+
+        assert_eq!(followed, vec![(project_dir.join("session-1").join("log.jsonl"), "-tmp-project".to_string())]);
+        assert!(not_followed.is_empty());
+    }
+
+    #[test]
+    fn test_streaming_parse_matches_whole_file_parse() {
+        let temp_dir = TempDir::new().unwrap();
+        let session_dir = temp_dir.path().join("session-1");
+        fs::create_dir_all(&session_dir).unwrap();
+        let log_path = session_dir.join("log.jsonl");
+
+        // Repeated message ids exercise dedup, blank and junk lines are skipped
+        let cache_line = |i: usize| {
+            (
+                format!("2025-01-15T10:{:02}:{:02}Z", (i / 60) % 60, i % 60),
+                format!("msg-{}", i % 997),
+                format!("req-{}", i % 997),
+            )
+        };
+        let io_line = |i: usize| {
+            (
+                format!("2025-01-15T11:{:02}:{:02}Z", (i / 60) % 60, i % 60),
+                format!("msg-{}", i % 1_500),
+                (i % 50 + 1) as u64,
+            )
+        };
+        let content = (0..20_000)
+            .map(|i| match i % 5 {
+                0 => String::new(),
+                1 => "not json".to_string(),
+                2 => {
+                    let (timestamp, msg_id, req_id) = cache_line(i);
+                    cache_read_line(&timestamp, &msg_id, &req_id).to_string()
+                }
+                _ => {
+                    let (timestamp, msg_id, input_tokens) = io_line(i);
+                    let mut line = usage_line(&timestamp, &msg_id, "claude-opus-4", serde_json::json!({ "input_tokens": input_tokens, "output_tokens": 7 }));
+                    line["cwd"] = "/tmp/project".into();
+                    line.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        fs::write(&log_path, &content).unwrap();
+
+        let gz_path = session_dir.join("log.jsonl.gz");
+        let mut encoder = flate2::write::GzEncoder::new(fs::File::create(&gz_path).unwrap(), flate2::Compression::fast());
+        encoder.write_all(content.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+
+        // Expected entries built straight from the generator: the first line of each
+        // dedup key survives, in file order, and the project comes from the first cwd
+        let mut expected = Vec::new();
+        let mut seen = HashSet::new();
+        let mut project_path = "-tmp-project";
+        for i in 0..20_000 {
+            match i % 5 {
+                2 => {
+                    let (timestamp, msg_id, req_id) = cache_line(i);
+                    if seen.insert(format!("cache:{}:{}", msg_id, req_id)) {
+                        expected.push((timestamp, "claude-sonnet-4", 0, 0, 1000, project_path));
+                    }
+                }
+                3 | 4 => {
+                    project_path = "/tmp/project";
+                    let (timestamp, msg_id, input_tokens) = io_line(i);
+                    if seen.insert(format!("io:{}", msg_id)) {
+                        expected.push((timestamp, "claude-opus-4", input_tokens, 7, 0, project_path));
+                    }
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(expected.len(), 997 + 600);
+
+        let summarize = |entries: Vec<UsageEntry>| {
+            entries
+                .into_iter()
+                .map(|e| {
+                    assert_eq!(e.session_id, "session-1");
+                    (e.timestamp, e.model, e.input_tokens, e.output_tokens, e.cache_read_tokens, e.project_path)
+                })
+                .collect::<Vec<_>>()
+        };
+        let expected: Vec<_> = expected
+            .into_iter()
+            .map(|(timestamp, model, input, output, cache_read, project)| {
+                (timestamp, model.to_string(), input, output, cache_read, project.to_string())
+            })
+            .collect();
+
+        let streamed = parse_jsonl_file(&log_path, "-tmp-project", "https://api.anthropic.com", DedupMode::default(), &mut HashSet::new());
+        let streamed_gz = parse_jsonl_file(&gz_path, "-tmp-project", "https://api.anthropic.com", DedupMode::default(), &mut HashSet::new());
+        assert_eq!(summarize(streamed), expected);
+        assert_eq!(summarize(streamed_gz), expected);
+    }
+
+    fn write_aged_log(claude_path: &Path, session: &str, age_days: u64) -> PathBuf {
+        let session_dir = claude_path.join("projects").join("-tmp-project").join(session);
+        fs::create_dir_all(&session_dir).unwrap();
+        let log_path = session_dir.join("log.jsonl");
+        let line = usage_line(
+            "2024-01-15T10:00:00Z",
+            &format!("msg-{}", session),
+            "claude-sonnet-4",
+            serde_json::json!({ "input_tokens": 10, "output_tokens": 5 }),
+        );
+        fs::write(&log_path, format!("{}\n", line)).unwrap();
+        set_file_age(&log_path, age_days);
+        log_path
+    }
+
+    fn set_file_age(path: &Path, age_days: u64) {
+        let modified = std::time::SystemTime::now() - std::time::Duration::from_secs(age_days * 86_400);
+        fs::File::options().write(true).open(path).unwrap().set_modified(modified).unwrap();
+    }
+
+    #[test]
+    fn test_archive_old_logs_archives_and_skips() {
+        let temp_dir = TempDir::new().unwrap();
+        let claude_path = temp_dir.path();
+        let archive_dir = TempDir::new().unwrap();
+        let archive_root = archive_dir.path().canonicalize().unwrap();
+        let archived_path = |session: &str| archive_root.join("-tmp-project").join(session).join("log.jsonl.gz");
+
+        let old_log = write_aged_log(claude_path, "session-old", 30);
+        let recent_log = write_aged_log(claude_path, "session-recent", 0);
+        let blocked_log = write_aged_log(claude_path, "session-blocked", 30);
+        let old_content = fs::read(&old_log).unwrap();
+        fs::create_dir_all(archived_path("session-blocked").parent().unwrap()).unwrap();
+        fs::write(archived_path("session-blocked"), b"existing archive").unwrap();
+
+        let report = archive_old_logs_in(claude_path, 7, &archive_root.to_string_lossy(), Local::now(), 5).unwrap();
+        assert_eq!(report.files_archived, 1);
+        assert_eq!(report.entries_archived, 1);
+        assert_eq!(report.skipped_active, 1);
+        assert_eq!(report.errors.len(), 1);
+
+        // The archived log decompresses back to the original bytes
+        assert!(!old_log.exists());
+        let mut restored = Vec::new();
+        std::io::Read::read_to_end(
+            &mut GzDecoder::new(fs::File::open(archived_path("session-old")).unwrap()),
+            &mut restored,
+        )
+        .unwrap();
+        assert_eq!(restored, old_content);
+
+        // A log written within the session window stays put
+        assert!(recent_log.exists());
+        assert!(!archived_path("session-recent").exists());
+
+        // An existing archive is never overwritten, and its log is kept
+        assert!(blocked_log.exists());
+        assert_eq!(fs::read(archived_path("session-blocked")).unwrap(), b"existing archive");
+    }
+
+    #[test]
+    fn test_archive_old_logs_rejects_archive_dir_in_projects() {
+        let temp_dir = TempDir::new().unwrap();
+        let claude_path = temp_dir.path();
+        let old_log = write_aged_log(claude_path, "session-old", 30);
+
+        for archive_dir in [
+            claude_path.join("projects").join("archive"),
+            claude_path.join("projects"),
+            claude_path.to_path_buf(),
+        ] {
+            assert!(matches!(
+                archive_old_logs_in(claude_path, 7, &archive_dir.to_string_lossy(), Local::now(), 5),
+                Err(CommandError::Validation(_))
+            ));
+        }
+        assert!(old_log.exists());
+    }
+
+    #[test]
+    fn test_orphaned_sessions_skip_running_and_guard_deletes() {
+        let temp_dir = TempDir::new().unwrap();
+        let claude_path = temp_dir.path();
+        let projects_dir = claude_path.join("projects");
+        let write_transcript = |session: &str, age_days: u64| {
+            let session_dir = projects_dir.join("-tmp-project").join(session);
+            fs::create_dir_all(&session_dir).unwrap();
+            let log_path = session_dir.join("log.jsonl");
+            fs::write(&log_path, "{\"type\":\"user\",\"timestamp\":\"2024-01-15T10:00:00Z\"}\n").unwrap();
+            set_file_age(&log_path, age_days);
+            session_dir
+        };
+        let orphan_dir = write_transcript("session-orphan", 30);
+        let running_dir = write_transcript("session-running", 0);
+        let used_dir = write_aged_log(claude_path, "session-used", 30).parent().unwrap().to_path_buf();
+
+        let orphans = find_orphaned_sessions_in(&projects_dir, Local::now(), 5, true).unwrap();
+        assert_eq!(orphans.len(), 1);
+        assert_eq!(PathBuf::from(&orphans[0].path), orphan_dir);
+        assert_eq!(orphans[0].project_name, "-tmp-project");
+        assert_eq!(orphans[0].file_count, 1);
+
+        let delete = |path: &Path| {
+            delete_orphaned_session_in(&projects_dir, &path.to_string_lossy(), Local::now(), 5, true)
+        };
+
+        // Only directories strictly inside projects/ can be deleted
+        let outside = TempDir::new().unwrap();
+        assert!(matches!(delete(outside.path()), Err(CommandError::Validation(_))));
+        assert!(matches!(delete(&projects_dir), Err(CommandError::Validation(_))));
+        assert!(matches!(delete(&projects_dir.join("..").join("projects")), Err(CommandError::Validation(_))));
+        assert!(matches!(delete(&projects_dir.join("missing")), Err(CommandError::NotFound(_))));
+        assert!(outside.path().exists());
+
+        // Directories with usage data or a live transcript are refused
+        assert!(matches!(delete(&used_dir), Err(CommandError::Validation(_))));
+        assert!(matches!(delete(&running_dir), Err(CommandError::Validation(_))));
+        assert!(used_dir.exists() && running_dir.exists());
+
+        delete(&orphan_dir).unwrap();
+        assert!(!orphan_dir.exists());
+    }
+
+    #[test]
+    fn test_plan_from_settings_markers() {
+        let plan = |settings: serde_json::Value| {
+            let mut signals = Vec::new();
+            let result = plan_from_settings(&settings, &mut signals);
+            assert_eq!(signals.is_empty(), result == (None, false));
+            result
+        };
+
+        assert_eq!(plan(serde_json::json!({ "subscriptionType": "Max_20x" })), (Some(ClaudePlan::Max), false));
+        assert_eq!(plan(serde_json::json!({ "subscriptionType": "pro" })), (Some(ClaudePlan::Pro), false));
+        assert_eq!(plan(serde_json::json!({ "apiKeyHelper": "~/bin/key.sh" })), (Some(ClaudePlan::Api), false));
+        assert_eq!(plan(serde_json::json!({ "apiKeyHelper": null })), (None, false));
+        assert_eq!(plan(serde_json::json!({ "env": { "ANTHROPIC_AUTH_TOKEN": "sk-test" } })), (Some(ClaudePlan::Api), false));
+        assert_eq!(plan(serde_json::json!({ "forceLoginMethod": "console" })), (Some(ClaudePlan::Api), false));
+        assert_eq!(plan(serde_json::json!({ "forceLoginMethod": "claudeai" })), (None, true));
+        assert_eq!(plan(serde_json::json!({})), (None, false));
+
+        // A named subscription outranks API key markers
+        assert_eq!(
+            plan(serde_json::json!({ "subscriptionType": "max", "env": { "ANTHROPIC_API_KEY": "sk-test" } })),
+            (Some(ClaudePlan::Max), false)
+        );
+    }
+
+    #[test]
+    fn test_build_plan_info_from_usage() {
+        let entry = |model: &str, cost_reported: bool| UsageEntry {
+            model: model.to_string(),
+            cost_reported,
+            ..usage_entry("session-1", "/home/dev/app", "2025-01-15T10:00:00Z")
+        };
+        let subscription_login = serde_json::json!({ "forceLoginMethod": "claudeai" });
+
+        // Settings markers win regardless of usage
+        let info = build_plan_info(Some(&serde_json::json!({ "subscriptionType": "pro" })), &[entry("claude-opus-4", true)]);
+        assert_eq!((info.plan, info.confidence), (ClaudePlan::Pro, PlanConfidence::High));
+        assert_eq!(info.plan_monthly_usd, Some(PRO_PLAN_MONTHLY_USD));
+
+        // Reported costUSD counts even for models without known pricing
+        let reported = [entry("custom-model", true), entry("custom-model", true), entry("claude-sonnet-4", false)];
+        let info = build_plan_info(None, &reported);
+        assert_eq!((info.plan, info.confidence), (ClaudePlan::Api, PlanConfidence::Medium));
+        assert_eq!(info.plan_monthly_usd, None);
+        assert_eq!(info.session_window_hours, None);
+        let info = build_plan_info(Some(&subscription_login), &reported);
+        assert_eq!((info.plan, info.confidence), (ClaudePlan::Api, PlanConfidence::Low));
+
+        let unreported = [entry("claude-opus-4", false), entry("claude-sonnet-4", false)];
+        let info = build_plan_info(Some(&subscription_login), &unreported);
+        assert_eq!((info.plan, info.confidence), (ClaudePlan::Max, PlanConfidence::Medium));
+        assert_eq!(info.session_window_hours, Some(DEFAULT_SESSION_WINDOW_HOURS));
+        let info = build_plan_info(Some(&subscription_login), &unreported[1..]);
+        assert_eq!((info.plan, info.confidence), (ClaudePlan::Pro, PlanConfidence::Low));
+
+        let info = build_plan_info(None, &unreported);
+        assert_eq!((info.plan, info.confidence), (ClaudePlan::Unknown, PlanConfidence::Low));
+        let info = build_plan_info(None, &[]);
+        assert_eq!(info.plan, ClaudePlan::Unknown);
+        assert_eq!(info.signals, vec!["No usage logs found".to_string()]);
+    }
+
+    #[test]
+    fn test_daily_series_rejects_out_of_range_days() {
+        let end_date = NaiveDate::from_ymd_opt(2025, 1, 15).unwrap();
+        assert!(matches!(build_daily_series(&[], u32::MAX, end_date), Err(CommandError::Validation(_))));
+
+        let series = build_daily_series(&[], 3, end_date).unwrap();
+        let dates: Vec<&str> = series.iter().map(|d| d.date.as_str()).collect();
+        assert_eq!(dates, vec!["2025-01-13", "2025-01-14", "2025-01-15"]);
+        assert!(build_daily_series(&[], 0, end_date).unwrap().is_empty());
+    }
+}
@@ -15,6 +15,7 @@ use commands::agents::{
     import_agent_from_file, import_agent_from_github, init_database, kill_agent_session,
     list_agent_runs, list_agent_runs_with_metrics, list_agents, list_claude_installations,
     list_running_sessions, load_agent_session_history, set_claude_binary_path, stream_session_output, update_agent, AgentDb,
+    reset_claude_binary_cache, get_app_db_status, check_claude_binary_current,
 };
 use commands::claude::{
     cancel_claude_execution, check_auto_checkpoint, check_claude_version, cleanup_old_checkpoints,
@@ -29,7 +30,7 @@ use commands::claude::{
     get_hooks_config, update_hooks_config, validate_hook_command,
     set_custom_claude_path, get_claude_path, clear_custom_claude_path,
     restore_project, list_hidden_projects, enhance_prompt,
-    ClaudeProcessState,
+    ClaudeProcessState, encode_project_path,
 };
 use commands::mcp::{
     mcp_add, mcp_add_from_claude_desktop, mcp_add_json, mcp_get, mcp_get_server_status, mcp_list,
@@ -40,6 +41,20 @@ use commands::mcp::{
 use commands::usage::{
     get_session_stats, get_usage_by_date_range, get_usage_details, get_usage_stats,
     get_today_usage_stats, get_usage_by_api_base_url, get_active_sessions, get_burn_rate_analysis,
+    get_daily_series, reveal_project_dir, get_lifetime_summary, export_usage_json,
+    get_dashboard_snapshot, get_usage_patterns, usage_data_available, estimate_cost,
+    get_session_window_hours, set_session_window_hours, detect_cost_anomalies, get_day_hourly,
+    export_usage_ndjson, resolve_api_base_url, get_window_token_cap, set_window_token_cap,
+    validate_claude_settings, get_daily_series_smoothed,
+    find_orphaned_sessions, delete_orphaned_session, estimate_tokens, forecast_cost,
+    set_session_tags, get_usage_by_tag, get_dedup_diagnostics, get_subscription_breakeven,
+    get_recent_usage, archive_old_logs, get_cost_concentration, get_usage_stats_multi,
+    get_project_aliases, set_project_alias, get_cache_hit_ratio, locate_session_file,
+    rebuild_usage_aggregate, get_session_latency, check_provider_model_pricing,
+    get_live_session_rates, get_follow_symlinks, set_follow_symlinks,
+    get_monthly_budget, set_monthly_budget, get_budget_runway, get_monthly_statement,
+    compare_provider_costs, get_daily_cap, set_daily_cap, check_daily_cap, list_sessions,
+    detect_plan,
 };
 use commands::storage::{
     storage_list_tables, storage_read_table, storage_update_row, storage_delete_row,
@@ -48,10 +63,16 @@ use commands::storage::{
 use commands::clipboard::{
     save_clipboard_image,
 };
+use commands::profiles::{
+    add_profile, get_active_profile, list_profiles, remove_profile, set_active_profile,
+};
 use commands::provider::{
     get_provider_presets, get_current_provider_config, switch_provider_config,
     clear_provider_config, test_provider_connection, add_provider_config,
     update_provider_config, delete_provider_config, get_provider_config,
+    validate_providers_file, switch_provider_model, test_all_providers, preview_provider_switch,
+    get_provider_by_base_url, get_providers_with_status, diff_providers,
+    preview_terminate_claude_processes, verify_env_persistence, export_provider_env_script,
 };
 use process::ProcessRegistryState;
 use std::sync::Mutex;
@@ -98,6 +119,14 @@ fn main() {
             // Initialize Claude process state
             app.manage(ClaudeProcessState::default());
 
+            // Detect in-place Claude upgrades behind the cached binary path
+            let app_handle = app.handle().clone();
+            std::thread::spawn(move || {
+                if let Err(e) = claude_binary::check_claude_binary_current(&app_handle) {
+                    log::warn!("Failed to check Claude binary version: {}", e);
+                }
+            });
+
 
             Ok(())
         })
@@ -134,6 +163,7 @@ fn main() {
             get_claude_path,
             clear_custom_claude_path,
             enhance_prompt,
+            encode_project_path,
             
             // Checkpoint Management
             create_checkpoint,
@@ -172,6 +202,7 @@ fn main() {
             load_agent_session_history,
             get_claude_binary_path,
             set_claude_binary_path,
+            reset_claude_binary_cache,
             list_claude_installations,
             export_agent,
             export_agent_to_file,
@@ -180,6 +211,8 @@ fn main() {
             fetch_github_agents,
             fetch_github_agent_content,
             import_agent_from_github,
+            get_app_db_status,
+            check_claude_binary_current,
             
             // Usage & Analytics
             get_usage_stats,
@@ -190,6 +223,56 @@ fn main() {
             get_session_stats,
             get_active_sessions,
             get_burn_rate_analysis,
+            get_daily_series,
+            reveal_project_dir,
+            get_lifetime_summary,
+            export_usage_json,
+            get_dashboard_snapshot,
+            get_usage_patterns,
+            usage_data_available,
+            estimate_cost,
+            get_session_window_hours,
+            set_session_window_hours,
+            detect_cost_anomalies,
+            get_day_hourly,
+            export_usage_ndjson,
+            resolve_api_base_url,
+            get_window_token_cap,
+            set_window_token_cap,
+            validate_claude_settings,
+            get_daily_series_smoothed,
+            find_orphaned_sessions,
+            delete_orphaned_session,
+            estimate_tokens,
+            forecast_cost,
+            set_session_tags,
+            get_usage_by_tag,
+            get_dedup_diagnostics,
+            get_subscription_breakeven,
+            get_recent_usage,
+            archive_old_logs,
+            get_cost_concentration,
+            get_usage_stats_multi,
+            get_project_aliases,
+            set_project_alias,
+            get_cache_hit_ratio,
+            locate_session_file,
+            rebuild_usage_aggregate,
+            get_session_latency,
+            check_provider_model_pricing,
+            get_live_session_rates,
+            get_follow_symlinks,
+            set_follow_symlinks,
+            get_monthly_budget,
+            set_monthly_budget,
+            get_budget_runway,
+            get_monthly_statement,
+            compare_provider_costs,
+            get_daily_cap,
+            set_daily_cap,
+            check_daily_cap,
+            list_sessions,
+            detect_plan,
             
             // MCP (Model Context Protocol)
             mcp_add,
@@ -220,6 +303,11 @@ fn main() {
             commands::slash_commands::slash_command_get,
             commands::slash_commands::slash_command_save,
             commands::slash_commands::slash_command_delete,
+            commands::slash_commands::slash_command_duplicate,
+            commands::slash_commands::preview_command_expansion,
+            commands::slash_commands::restore_slash_command,
+            commands::slash_commands::export_slash_commands,
+            commands::slash_commands::import_slash_commands,
             // Clipboard
             save_clipboard_image,
             
@@ -233,6 +321,23 @@ fn main() {
             update_provider_config,
             delete_provider_config,
             get_provider_config,
+            validate_providers_file,
+            switch_provider_model,
+            test_all_providers,
+            preview_provider_switch,
+            get_provider_by_base_url,
+            get_providers_with_status,
+            diff_providers,
+            preview_terminate_claude_processes,
+            verify_env_persistence,
+            export_provider_env_script,
+            
+            // Config Profiles
+            list_profiles,
+            get_active_profile,
+            set_active_profile,
+            add_profile,
+            remove_profile,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
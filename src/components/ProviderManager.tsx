@@ -90,8 +90,12 @@ export default function ProviderManager({ onBack }: ProviderManagerProps) {
   const testConnection = async (config: ProviderConfig) => {
     try {
       setTesting(config.id);
-      const message = await api.testProviderConnection(config.base_url);
-      setToastMessage({ message, type: 'success' });
+      const result = await api.testProviderConnection(config);
+      if (result.status === 'ok') {
+        setToastMessage({ message: `连接测试成功：${result.base_url} (${result.latency_ms} ms)`, type: 'success' });
+      } else {
+        setToastMessage({ message: `连接测试失败：${result.error ?? result.status}`, type: 'error' });
+      }
     } catch (error) {
       console.error('Failed to test connection:', error);
       setToastMessage({ message: '连接测试失败', type: 'error' });
@@ -134,8 +138,8 @@ export default function ProviderManager({ onBack }: ProviderManagerProps) {
         await api.updateProviderConfig({ ...formData, id: editingProvider.id });
         setToastMessage({ message: '代理商更新成功', type: 'success' });
       } else {
-        await api.addProviderConfig(formData);
-        setToastMessage({ message: '代理商添加成功', type: 'success' });
+        const result = await api.addProviderConfig(formData);
+        setToastMessage({ message: result.duplicate_of ? result.message : '代理商添加成功', type: 'success' });
       }
       setShowForm(false);
       setEditingProvider(null);
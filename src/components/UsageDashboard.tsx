@@ -358,7 +358,7 @@ export const UsageDashboard: React.FC<UsageDashboardProps> = ({ onBack }) => {
                           <div className="flex items-center space-x-3">
                             <Badge 
                               variant="outline" 
-                              className={cn("text-xs", getModelColor(model.model))}
+                              className={cn("text-xs", model.uncosted ? "text-muted-foreground" : getModelColor(model.model))}
                             >
                               {getModelDisplayName(model.model)}
                             </Badge>
@@ -366,8 +366,8 @@ export const UsageDashboard: React.FC<UsageDashboardProps> = ({ onBack }) => {
                               {model.session_count} 个会话
                             </span>
                           </div>
-                          <span className="text-sm font-semibold">
-                            {formatCurrency(model.total_cost)}
+                          <span className={cn("text-sm font-semibold", model.uncosted && "text-muted-foreground")}>
+                            {model.uncosted ? "未计费" : formatCurrency(model.total_cost)}
                           </span>
                         </div>
                         <div className="grid grid-cols-4 gap-2 text-xs">
@@ -432,7 +432,7 @@ export const UsageDashboard: React.FC<UsageDashboardProps> = ({ onBack }) => {
                       <h3 className="text-sm font-semibold mb-4">按会话使用情况</h3>
                       <div className="space-y-3">
                           {sessionStats?.map((session) => (
-                              <div key={`${session.project_path}-${session.project_name}`} className="flex items-center justify-between py-2 border-b border-border last:border-0">
+                              <div key={`${session.project_path}-${session.session_id}`} className="flex items-center justify-between py-2 border-b border-border last:border-0">
                                   <div className="flex flex-col">
                                       <div className="flex items-center space-x-2">
                                         <Briefcase className="h-4 w-4 text-muted-foreground" />
@@ -441,7 +441,7 @@ export const UsageDashboard: React.FC<UsageDashboardProps> = ({ onBack }) => {
                                         </span>
                                       </div>
                                       <span className="text-sm font-medium mt-1">
-                                          {session.project_name}
+                                          {session.session_id}
                                       </span>
                                   </div>
                                   <div className="text-right">
@@ -465,6 +465,11 @@ export const UsageDashboard: React.FC<UsageDashboardProps> = ({ onBack }) => {
                       <div key={apiUrlStat.api_base_url} className="space-y-2">
                         <div className="flex items-center justify-between">
                           <div className="flex items-center space-x-3">
+                            {apiUrlStat.provider_name && (
+                              <span className="text-sm font-medium">
+                                {apiUrlStat.provider_name}
+                              </span>
+                            )}
                             <Badge variant="outline" className="text-xs">
                               {apiUrlStat.api_base_url}
                             </Badge>
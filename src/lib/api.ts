@@ -1,6 +1,20 @@
 import { invoke } from "@tauri-apps/api/core";
 import type { HooksConfiguration } from '@/types/hooks';
 
+/** Structured error rejected by usage, provider and slash command commands */
+export interface CommandError {
+  kind:
+    | 'home_dir_not_found'
+    | 'file_read'
+    | 'file_write'
+    | 'parse'
+    | 'not_found'
+    | 'validation'
+    | 'network'
+    | 'other';
+  message: string;
+}
+
 /** Process type for tracking in ProcessRegistry */
 export type ProcessType = 
   | { AgentRun: { agent_id: number; agent_name: string } }
@@ -103,6 +117,8 @@ export interface ClaudeInstallation {
   path: string;
   /** Version string if available */
   version?: string;
+  /** Build metadata (e.g. commit or build date) when the binary reports it */
+  build?: string;
   /** Source of discovery (e.g., "nvm", "system", "homebrew", "which", "bundled") */
   source: string;
   /** Type of installation */
@@ -205,12 +221,18 @@ export interface ModelUsage {
   cache_creation_tokens: number;
   cache_read_tokens: number;
   session_count: number;
+  /** No price known and no cost recorded: $0 here means unknown, not free */
+  uncosted?: boolean;
 }
 
 export interface DailyUsage {
   date: string;
   total_cost: number;
   total_tokens: number;
+  input_tokens: number;
+  output_tokens: number;
+  cache_creation_tokens: number;
+  cache_read_tokens: number;
   models_used: string[];
 }
 
@@ -221,8 +243,22 @@ export interface ProjectUsage {
   total_tokens: number;
   session_count: number;
   last_used: string;
+  tokens_per_dollar: number;
+  session_id?: string;
+  tags?: string[];
 }
 
+/**
+ * How repeated usage lines are deduplicated: "strict" by message id, "loose"
+ * (default) by session + message id for IO and message + request id for cache-only
+ * lines, "none" counts every line
+ */
+export type DedupMode = "strict" | "loose" | "none";
+
+/**
+ * Usage grouped by API base URL. The logs don't record the endpoint, so all
+ * entries are attributed to the currently configured base URL.
+ */
 export interface ApiBaseUrlUsage {
   api_base_url: string;
   total_cost: number;
@@ -232,6 +268,7 @@ export interface ApiBaseUrlUsage {
   cache_creation_tokens: number;
   cache_read_tokens: number;
   session_count: number;
+  provider_name?: string | null;
 }
 
 export interface UsageStats {
@@ -242,6 +279,7 @@ export interface UsageStats {
   total_cache_creation_tokens: number;
   total_cache_read_tokens: number;
   total_sessions: number;
+  unknown_model_token_count?: number;
   by_model: ModelUsage[];
   by_date: DailyUsage[];
   by_project: ProjectUsage[];
@@ -354,6 +392,43 @@ export interface ProviderConfig {
   auth_token?: string;
   api_key?: string;
   model?: string;
+  small_fast_model?: string;
+  http_proxy?: string;
+  no_proxy?: string;
+  pricing_override?: ModelPricing;
+}
+
+/**
+ * Result of adding a provider; duplicate_of is set when another provider already uses the same base_url
+ */
+export interface AddProviderResult {
+  message: string;
+  duplicate_of?: string | null;
+}
+
+/**
+ * Flat per-million-token rates a provider charges regardless of model
+ */
+export interface ModelPricing {
+  input: number;
+  output: number;
+  cache_write?: number;
+  cache_read?: number;
+}
+
+/**
+ * Result of a provider connectivity test
+ */
+export interface ProviderTestResult {
+  provider_id: string;
+  provider_name: string;
+  base_url: string;
+  reachable: boolean;
+  status: 'ok' | 'throttled' | 'auth_failed' | 'error' | 'unreachable';
+  status_code?: number;
+  latency_ms?: number;
+  error?: string;
+  model_accepted?: boolean;
 }
 
 /**
@@ -446,6 +521,8 @@ export interface SlashCommand {
   has_file_references: boolean;
   /** Whether the command uses $ARGUMENTS placeholder */
   accepts_arguments: boolean;
+  /** Non-fatal problems, e.g. unrecognized allowed-tools entries */
+  warnings?: string[];
 }
 
 
@@ -1122,11 +1199,28 @@ export const api = {
 
   /**
    * Gets overall usage statistics
+   * @param days - Optional number of recent days to include
+   * @param projectPath - Optional project to limit the statistics to
+   * @param trustReportedCost - Use costUSD from the logs as-is (default); false re-prices entries from tokens
+   * @param countOnlyBilledSessions - Only count sessions that had a nonzero-cost entry (default false)
+   * @param dedupMode - How repeated usage lines are deduplicated (default "loose")
    * @returns Promise resolving to usage statistics
    */
-  async getUsageStats(): Promise<UsageStats> {
+  async getUsageStats(
+    days?: number,
+    projectPath?: string,
+    trustReportedCost?: boolean,
+    countOnlyBilledSessions?: boolean,
+    dedupMode?: DedupMode
+  ): Promise<UsageStats> {
     try {
-      return await invoke<UsageStats>("get_usage_stats");
+      return await invoke<UsageStats>("get_usage_stats", {
+        days,
+        projectPath,
+        trustReportedCost,
+        countOnlyBilledSessions,
+        dedupMode,
+      });
     } catch (error) {
       console.error("Failed to get usage stats:", error);
       throw error;
@@ -1868,11 +1962,17 @@ export const api = {
   /**
    * Lists all available slash commands
    * @param projectPath - Optional project path to include project-specific commands
+   * @param sortBy - Optional sort order; "recent" and "frequency" use session history
+   * @param limit - Optional maximum number of commands to return
    * @returns Promise resolving to array of slash commands
    */
-  async slashCommandsList(projectPath?: string): Promise<SlashCommand[]> {
+  async slashCommandsList(
+    projectPath?: string,
+    sortBy?: "name" | "recent" | "frequency",
+    limit?: number
+  ): Promise<SlashCommand[]> {
     try {
-      return await invoke<SlashCommand[]>("slash_commands_list", { projectPath });
+      return await invoke<SlashCommand[]>("slash_commands_list", { projectPath, sortBy, limit });
     } catch (error) {
       console.error("Failed to list slash commands:", error);
       throw error;
@@ -1882,11 +1982,12 @@ export const api = {
   /**
    * Gets a single slash command by ID
    * @param commandId - Unique identifier of the command
+   * @param projectPath - Optional project path (required for project commands)
    * @returns Promise resolving to the slash command
    */
-  async slashCommandGet(commandId: string): Promise<SlashCommand> {
+  async slashCommandGet(commandId: string, projectPath?: string): Promise<SlashCommand> {
     try {
-      return await invoke<SlashCommand>("slash_command_get", { commandId });
+      return await invoke<SlashCommand>("slash_command_get", { commandId, projectPath });
     } catch (error) {
       console.error("Failed to get slash command:", error);
       throw error;
@@ -2060,12 +2161,13 @@ export const api = {
 
   /**
    * Tests connection to a provider endpoint
-   * @param baseUrl - The base URL to test
-   * @returns Promise resolving to test result message
+   * @param config - The provider configuration to test
+   * @param checkModel - Also send a 1-token request to confirm the configured model is usable
+   * @returns Promise resolving to the test result
    */
-  async testProviderConnection(baseUrl: string): Promise<string> {
+  async testProviderConnection(config: ProviderConfig, checkModel?: boolean): Promise<ProviderTestResult> {
     try {
-      return await invoke<string>("test_provider_connection", { baseUrl });
+      return await invoke<ProviderTestResult>("test_provider_connection", { config, checkModel });
     } catch (error) {
       console.error("Failed to test provider connection:", error);
       throw error;
@@ -2075,9 +2177,9 @@ export const api = {
   /**
    * Adds a new provider configuration
    * @param config - The provider configuration to add
-   * @returns Promise resolving to success message
+   * @returns Promise resolving to the success message and the id of any provider already using the same base_url
    */
-  async addProviderConfig(config: Omit<ProviderConfig, 'id'>): Promise<string> {
+  async addProviderConfig(config: Omit<ProviderConfig, 'id'>): Promise<AddProviderResult> {
     // Generate ID from name
     const id = config.name
       .toLowerCase()
@@ -2091,7 +2193,7 @@ export const api = {
     };
     
     try {
-      return await invoke<string>("add_provider_config", { config: fullConfig });
+      return await invoke<AddProviderResult>("add_provider_config", { config: fullConfig });
     } catch (error) {
       console.error("Failed to add provider config:", error);
       throw error;